@@ -0,0 +1,261 @@
+//! This is where the [`CachedStatus`] struct and its dependencies go.
+use crate::provider::ArchLinux;
+use crate::{Error, HttpClient, Status};
+use std::fs::{self, File};
+use std::io::{self, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime};
+
+/// The mirror status, cached in a file and retrieved again once the cached one is older
+/// than its time to live. The cached status is stored as it was normalized, so statuses of
+/// other formats can be cached with [`CachedStatus::store`] too.
+///
+/// It is not available on wasm32, which has no filesystem.
+///
+/// ```no_run
+/// # async fn run(client: impl arch_mirrors_rs::HttpClient) -> Result<(), arch_mirrors_rs::Error> {
+/// use arch_mirrors_rs::CachedStatus;
+/// use std::time::Duration;
+///
+/// let cache = CachedStatus::new(client, "/tmp/mirrorstatus.json")
+///     .ttl(Duration::from_secs(600));
+/// let (status, retrieved) = cache.refresh().await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct CachedStatus<C> {
+    client: C,
+    url: String,
+    path: PathBuf,
+    ttl: Duration,
+}
+
+impl<C> CachedStatus<C> {
+    /// The default time to live of the cached status, five minutes like reflector.
+    pub const DEFAULT_TTL: Duration = Duration::from_secs(300);
+
+    /// A cache in the file at `path` for the official Arch Linux mirror status, retrieved
+    /// with `client`.
+    #[must_use]
+    pub fn new(client: C, path: impl Into<PathBuf>) -> Self {
+        Self {
+            client,
+            url: ArchLinux::URL.to_owned(),
+            path: path.into(),
+            ttl: Self::DEFAULT_TTL,
+        }
+    }
+
+    /// Retrieve the mirror status from `url` instead, which must follow the format of the
+    /// official one.
+    #[must_use]
+    pub fn url(mut self, url: impl Into<String>) -> Self {
+        self.url = url.into();
+        self
+    }
+
+    /// How long the cached status is used before it is retrieved again.
+    #[must_use]
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// The file in which the status is cached.
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// When the status was cached, or nothing if there is none.
+    #[must_use]
+    pub fn modified(&self) -> Option<SystemTime> {
+        fs::metadata(&self.path)
+            .and_then(|meta| meta.modified())
+            .ok()
+    }
+
+    /// The cached status regardless of its age, along with the time it was cached, or
+    /// nothing if there is none.
+    ///
+    /// # Errors
+    /// Fails if the cache file cannot be read or does not contain a mirror status.
+    pub fn cached(&self) -> Result<Option<(Status, SystemTime)>, Error> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        let mtime = file.metadata()?.modified()?;
+        Ok(Some((
+            serde_json::from_reader(BufReader::new(file))?,
+            mtime,
+        )))
+    }
+
+    /// The cached status along with the time it was cached, or nothing if there is none or
+    /// it is older than the time to live.
+    ///
+    /// # Errors
+    /// Fails if the cache file cannot be read or does not contain a mirror status.
+    pub fn fresh(&self) -> Result<Option<(Status, SystemTime)>, Error> {
+        let is_fresh = self
+            .modified()
+            .and_then(|mtime| SystemTime::now().duration_since(mtime).ok())
+            .is_some_and(|elapsed| elapsed <= self.ttl);
+        if !is_fresh {
+            return Ok(None);
+        }
+        self.cached()
+    }
+
+    /// Replace the cached status with `status`. The status is written to a temporary file
+    /// that is renamed into place, so a crash or a concurrent run never leaves a partially
+    /// written cache behind.
+    ///
+    /// # Errors
+    /// Fails if the cache file cannot be written.
+    pub fn store(&self, status: &Status) -> Result<(), Error> {
+        write_atomic(&self.path, &serde_json::to_vec_pretty(status)?)?;
+        Ok(())
+    }
+}
+
+/// Write `contents` to a temporary file next to `path` and rename it into place. The name of
+/// the temporary file is unique to the process and the call, so concurrent writers do not
+/// write to the same temporary file.
+fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let mut temp_name = path.file_name().unwrap_or_default().to_owned();
+    temp_name.push(format!(
+        ".{}.{}.tmp",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    let temp_path = path.with_file_name(temp_name);
+    let result = File::create(&temp_path)
+        .and_then(|mut file| {
+            file.write_all(contents)?;
+            file.sync_all()
+        })
+        .and_then(|()| fs::rename(&temp_path, path));
+    if result.is_err() {
+        // The temporary file may not exist, and the original error matters more.
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+impl<C: HttpClient> CachedStatus<C> {
+    /// The cached status if it is within its time to live, or the status retrieved and
+    /// cached by [`CachedStatus::force`] otherwise, along with the time it was retrieved.
+    ///
+    /// # Errors
+    /// Fails if the cache cannot be accessed, the request fails or the response is not a
+    /// supported mirror status.
+    pub async fn refresh(&self) -> Result<(Status, SystemTime), Error> {
+        match self.fresh()? {
+            Some(cached) => Ok(cached),
+            None => self.force().await,
+        }
+    }
+
+    /// Retrieve the status regardless of the cached one and cache it, along with the time
+    /// it was retrieved.
+    ///
+    /// # Errors
+    /// Fails if the request fails, the response is not a supported mirror status or the
+    /// cache file cannot be written.
+    pub async fn force(&self) -> Result<(Status, SystemTime), Error> {
+        let status = Status::get_with(&self.client, &self.url).await?;
+        self.store(&status)?;
+        Ok((status, SystemTime::now()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::future::{Future, ready};
+    use std::pin::pin;
+    use std::task::{Context, Poll, Waker};
+    use url::Url;
+
+    /// Serves a mirror status whose number of checks counts the requests.
+    #[derive(Default)]
+    struct Mock(Cell<u32>);
+
+    impl HttpClient for Mock {
+        fn get(&self, _url: &Url) -> impl Future<Output = Result<Vec<u8>, Error>> {
+            self.0.set(self.0.get() + 1);
+            let json = format!(
+                r#"{{"cutoff": 3600, "last_check": "2026-01-01T00:00:00Z", "num_checks": {},
+                "check_frequency": 600, "urls": [], "version": 3}}"#,
+                self.0.get()
+            );
+            ready(Ok(json.into_bytes()))
+        }
+    }
+
+    /// Run a future of the mock, which never waits.
+    fn run<T>(future: impl Future<Output = T>) -> T {
+        match pin!(future).poll(&mut Context::from_waker(Waker::noop())) {
+            Poll::Ready(output) => output,
+            Poll::Pending => panic!("the mock never waits"),
+        }
+    }
+
+    #[test]
+    fn use_fresh_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = CachedStatus::new(Mock::default(), dir.path().join("status.json"));
+        assert!(cache.cached().unwrap().is_none());
+        assert_eq!(run(cache.refresh()).unwrap().0.num_checks, 1);
+        assert_eq!(run(cache.refresh()).unwrap().0.num_checks, 1);
+        assert_eq!(cache.client.0.get(), 1);
+    }
+
+    #[test]
+    fn refresh_stale_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = CachedStatus::new(Mock::default(), dir.path().join("status.json"))
+            .ttl(Duration::from_secs(600));
+        run(cache.refresh()).unwrap();
+        let an_hour_ago = SystemTime::now() - Duration::from_secs(3600);
+        File::options()
+            .write(true)
+            .open(cache.path())
+            .unwrap()
+            .set_modified(an_hour_ago)
+            .unwrap();
+        assert!(cache.fresh().unwrap().is_none());
+        assert_eq!(cache.cached().unwrap().unwrap().1, an_hour_ago);
+        assert_eq!(run(cache.refresh()).unwrap().0.num_checks, 2);
+        assert_eq!(cache.fresh().unwrap().unwrap().0.num_checks, 2);
+    }
+
+    #[test]
+    fn force_refresh() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = CachedStatus::new(Mock::default(), dir.path().join("status.json"));
+        run(cache.refresh()).unwrap();
+        assert_eq!(run(cache.force()).unwrap().0.num_checks, 2);
+        assert_eq!(cache.cached().unwrap().unwrap().0.num_checks, 2);
+    }
+
+    #[test]
+    fn store_replaces_the_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = CachedStatus::new(Mock::default(), dir.path().join("status.json"));
+        run(cache.force()).unwrap();
+        let (mut status, _) = run(cache.force()).unwrap();
+        status.num_checks = 42;
+        cache.store(&status).unwrap();
+        assert_eq!(cache.cached().unwrap().unwrap().0, status);
+        let files = fs::read_dir(dir.path()).unwrap().count();
+        assert_eq!(files, 1, "temporary files are left behind");
+    }
+}
@@ -0,0 +1,399 @@
+//! This is where the [`CountryCode`] struct and its dependencies go.
+use crate::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt::Formatter;
+use std::str::FromStr;
+
+use Continent::{Africa, Antarctica, Asia, Europe, NorthAmerica, Oceania, SouthAmerica};
+
+/// The countries of ISO 3166-1 and Kosovo, given by their code, common English name and
+/// continent, sorted by code.
+const COUNTRIES: &[(&str, &str, Continent)] = &[
+    ("AD", "Andorra", Europe),
+    ("AE", "United Arab Emirates", Asia),
+    ("AF", "Afghanistan", Asia),
+    ("AG", "Antigua and Barbuda", NorthAmerica),
+    ("AI", "Anguilla", NorthAmerica),
+    ("AL", "Albania", Europe),
+    ("AM", "Armenia", Asia),
+    ("AO", "Angola", Africa),
+    ("AQ", "Antarctica", Antarctica),
+    ("AR", "Argentina", SouthAmerica),
+    ("AS", "American Samoa", Oceania),
+    ("AT", "Austria", Europe),
+    ("AU", "Australia", Oceania),
+    ("AW", "Aruba", NorthAmerica),
+    ("AX", "Åland Islands", Europe),
+    ("AZ", "Azerbaijan", Asia),
+    ("BA", "Bosnia and Herzegovina", Europe),
+    ("BB", "Barbados", NorthAmerica),
+    ("BD", "Bangladesh", Asia),
+    ("BE", "Belgium", Europe),
+    ("BF", "Burkina Faso", Africa),
+    ("BG", "Bulgaria", Europe),
+    ("BH", "Bahrain", Asia),
+    ("BI", "Burundi", Africa),
+    ("BJ", "Benin", Africa),
+    ("BL", "Saint Barthélemy", NorthAmerica),
+    ("BM", "Bermuda", NorthAmerica),
+    ("BN", "Brunei", Asia),
+    ("BO", "Bolivia", SouthAmerica),
+    ("BQ", "Caribbean Netherlands", NorthAmerica),
+    ("BR", "Brazil", SouthAmerica),
+    ("BS", "Bahamas", NorthAmerica),
+    ("BT", "Bhutan", Asia),
+    ("BV", "Bouvet Island", Antarctica),
+    ("BW", "Botswana", Africa),
+    ("BY", "Belarus", Europe),
+    ("BZ", "Belize", NorthAmerica),
+    ("CA", "Canada", NorthAmerica),
+    ("CC", "Cocos (Keeling) Islands", Asia),
+    ("CD", "DR Congo", Africa),
+    ("CF", "Central African Republic", Africa),
+    ("CG", "Congo", Africa),
+    ("CH", "Switzerland", Europe),
+    ("CI", "Côte d'Ivoire", Africa),
+    ("CK", "Cook Islands", Oceania),
+    ("CL", "Chile", SouthAmerica),
+    ("CM", "Cameroon", Africa),
+    ("CN", "China", Asia),
+    ("CO", "Colombia", SouthAmerica),
+    ("CR", "Costa Rica", NorthAmerica),
+    ("CU", "Cuba", NorthAmerica),
+    ("CV", "Cabo Verde", Africa),
+    ("CW", "Curaçao", NorthAmerica),
+    ("CX", "Christmas Island", Asia),
+    ("CY", "Cyprus", Europe),
+    ("CZ", "Czechia", Europe),
+    ("DE", "Germany", Europe),
+    ("DJ", "Djibouti", Africa),
+    ("DK", "Denmark", Europe),
+    ("DM", "Dominica", NorthAmerica),
+    ("DO", "Dominican Republic", NorthAmerica),
+    ("DZ", "Algeria", Africa),
+    ("EC", "Ecuador", SouthAmerica),
+    ("EE", "Estonia", Europe),
+    ("EG", "Egypt", Africa),
+    ("EH", "Western Sahara", Africa),
+    ("ER", "Eritrea", Africa),
+    ("ES", "Spain", Europe),
+    ("ET", "Ethiopia", Africa),
+    ("FI", "Finland", Europe),
+    ("FJ", "Fiji", Oceania),
+    ("FK", "Falkland Islands", SouthAmerica),
+    ("FM", "Micronesia", Oceania),
+    ("FO", "Faroe Islands", Europe),
+    ("FR", "France", Europe),
+    ("GA", "Gabon", Africa),
+    ("GB", "United Kingdom", Europe),
+    ("GD", "Grenada", NorthAmerica),
+    ("GE", "Georgia", Asia),
+    ("GF", "French Guiana", SouthAmerica),
+    ("GG", "Guernsey", Europe),
+    ("GH", "Ghana", Africa),
+    ("GI", "Gibraltar", Europe),
+    ("GL", "Greenland", NorthAmerica),
+    ("GM", "Gambia", Africa),
+    ("GN", "Guinea", Africa),
+    ("GP", "Guadeloupe", NorthAmerica),
+    ("GQ", "Equatorial Guinea", Africa),
+    ("GR", "Greece", Europe),
+    (
+        "GS",
+        "South Georgia and the South Sandwich Islands",
+        Antarctica,
+    ),
+    ("GT", "Guatemala", NorthAmerica),
+    ("GU", "Guam", Oceania),
+    ("GW", "Guinea-Bissau", Africa),
+    ("GY", "Guyana", SouthAmerica),
+    ("HK", "Hong Kong", Asia),
+    ("HM", "Heard Island and McDonald Islands", Antarctica),
+    ("HN", "Honduras", NorthAmerica),
+    ("HR", "Croatia", Europe),
+    ("HT", "Haiti", NorthAmerica),
+    ("HU", "Hungary", Europe),
+    ("ID", "Indonesia", Asia),
+    ("IE", "Ireland", Europe),
+    ("IL", "Israel", Asia),
+    ("IM", "Isle of Man", Europe),
+    ("IN", "India", Asia),
+    ("IO", "British Indian Ocean Territory", Asia),
+    ("IQ", "Iraq", Asia),
+    ("IR", "Iran", Asia),
+    ("IS", "Iceland", Europe),
+    ("IT", "Italy", Europe),
+    ("JE", "Jersey", Europe),
+    ("JM", "Jamaica", NorthAmerica),
+    ("JO", "Jordan", Asia),
+    ("JP", "Japan", Asia),
+    ("KE", "Kenya", Africa),
+    ("KG", "Kyrgyzstan", Asia),
+    ("KH", "Cambodia", Asia),
+    ("KI", "Kiribati", Oceania),
+    ("KM", "Comoros", Africa),
+    ("KN", "Saint Kitts and Nevis", NorthAmerica),
+    ("KP", "North Korea", Asia),
+    ("KR", "South Korea", Asia),
+    ("KW", "Kuwait", Asia),
+    ("KY", "Cayman Islands", NorthAmerica),
+    ("KZ", "Kazakhstan", Asia),
+    ("LA", "Laos", Asia),
+    ("LB", "Lebanon", Asia),
+    ("LC", "Saint Lucia", NorthAmerica),
+    ("LI", "Liechtenstein", Europe),
+    ("LK", "Sri Lanka", Asia),
+    ("LR", "Liberia", Africa),
+    ("LS", "Lesotho", Africa),
+    ("LT", "Lithuania", Europe),
+    ("LU", "Luxembourg", Europe),
+    ("LV", "Latvia", Europe),
+    ("LY", "Libya", Africa),
+    ("MA", "Morocco", Africa),
+    ("MC", "Monaco", Europe),
+    ("MD", "Moldova", Europe),
+    ("ME", "Montenegro", Europe),
+    ("MF", "Saint Martin", NorthAmerica),
+    ("MG", "Madagascar", Africa),
+    ("MH", "Marshall Islands", Oceania),
+    ("MK", "North Macedonia", Europe),
+    ("ML", "Mali", Africa),
+    ("MM", "Myanmar", Asia),
+    ("MN", "Mongolia", Asia),
+    ("MO", "Macao", Asia),
+    ("MP", "Northern Mariana Islands", Oceania),
+    ("MQ", "Martinique", NorthAmerica),
+    ("MR", "Mauritania", Africa),
+    ("MS", "Montserrat", NorthAmerica),
+    ("MT", "Malta", Europe),
+    ("MU", "Mauritius", Africa),
+    ("MV", "Maldives", Asia),
+    ("MW", "Malawi", Africa),
+    ("MX", "Mexico", NorthAmerica),
+    ("MY", "Malaysia", Asia),
+    ("MZ", "Mozambique", Africa),
+    ("NA", "Namibia", Africa),
+    ("NC", "New Caledonia", Oceania),
+    ("NE", "Niger", Africa),
+    ("NF", "Norfolk Island", Oceania),
+    ("NG", "Nigeria", Africa),
+    ("NI", "Nicaragua", NorthAmerica),
+    ("NL", "Netherlands", Europe),
+    ("NO", "Norway", Europe),
+    ("NP", "Nepal", Asia),
+    ("NR", "Nauru", Oceania),
+    ("NU", "Niue", Oceania),
+    ("NZ", "New Zealand", Oceania),
+    ("OM", "Oman", Asia),
+    ("PA", "Panama", NorthAmerica),
+    ("PE", "Peru", SouthAmerica),
+    ("PF", "French Polynesia", Oceania),
+    ("PG", "Papua New Guinea", Oceania),
+    ("PH", "Philippines", Asia),
+    ("PK", "Pakistan", Asia),
+    ("PL", "Poland", Europe),
+    ("PM", "Saint Pierre and Miquelon", NorthAmerica),
+    ("PN", "Pitcairn Islands", Oceania),
+    ("PR", "Puerto Rico", NorthAmerica),
+    ("PS", "Palestine", Asia),
+    ("PT", "Portugal", Europe),
+    ("PW", "Palau", Oceania),
+    ("PY", "Paraguay", SouthAmerica),
+    ("QA", "Qatar", Asia),
+    ("RE", "Réunion", Africa),
+    ("RO", "Romania", Europe),
+    ("RS", "Serbia", Europe),
+    ("RU", "Russia", Europe),
+    ("RW", "Rwanda", Africa),
+    ("SA", "Saudi Arabia", Asia),
+    ("SB", "Solomon Islands", Oceania),
+    ("SC", "Seychelles", Africa),
+    ("SD", "Sudan", Africa),
+    ("SE", "Sweden", Europe),
+    ("SG", "Singapore", Asia),
+    ("SH", "Saint Helena", Africa),
+    ("SI", "Slovenia", Europe),
+    ("SJ", "Svalbard and Jan Mayen", Europe),
+    ("SK", "Slovakia", Europe),
+    ("SL", "Sierra Leone", Africa),
+    ("SM", "San Marino", Europe),
+    ("SN", "Senegal", Africa),
+    ("SO", "Somalia", Africa),
+    ("SR", "Suriname", SouthAmerica),
+    ("SS", "South Sudan", Africa),
+    ("ST", "São Tomé and Príncipe", Africa),
+    ("SV", "El Salvador", NorthAmerica),
+    ("SX", "Sint Maarten", NorthAmerica),
+    ("SY", "Syria", Asia),
+    ("SZ", "Eswatini", Africa),
+    ("TC", "Turks and Caicos Islands", NorthAmerica),
+    ("TD", "Chad", Africa),
+    ("TF", "French Southern Territories", Antarctica),
+    ("TG", "Togo", Africa),
+    ("TH", "Thailand", Asia),
+    ("TJ", "Tajikistan", Asia),
+    ("TK", "Tokelau", Oceania),
+    ("TL", "Timor-Leste", Oceania),
+    ("TM", "Turkmenistan", Asia),
+    ("TN", "Tunisia", Africa),
+    ("TO", "Tonga", Oceania),
+    ("TR", "Turkey", Asia),
+    ("TT", "Trinidad and Tobago", NorthAmerica),
+    ("TV", "Tuvalu", Oceania),
+    ("TW", "Taiwan", Asia),
+    ("TZ", "Tanzania", Africa),
+    ("UA", "Ukraine", Europe),
+    ("UG", "Uganda", Africa),
+    ("UM", "United States Minor Outlying Islands", Oceania),
+    ("US", "United States", NorthAmerica),
+    ("UY", "Uruguay", SouthAmerica),
+    ("UZ", "Uzbekistan", Asia),
+    ("VA", "Vatican City", Europe),
+    ("VC", "Saint Vincent and the Grenadines", NorthAmerica),
+    ("VE", "Venezuela", SouthAmerica),
+    ("VG", "British Virgin Islands", NorthAmerica),
+    ("VI", "United States Virgin Islands", NorthAmerica),
+    ("VN", "Vietnam", Asia),
+    ("VU", "Vanuatu", Oceania),
+    ("WF", "Wallis and Futuna", Oceania),
+    ("WS", "Samoa", Oceania),
+    ("XK", "Kosovo", Europe),
+    ("YE", "Yemen", Asia),
+    ("YT", "Mayotte", Africa),
+    ("ZA", "South Africa", Africa),
+    ("ZM", "Zambia", Africa),
+    ("ZW", "Zimbabwe", Africa),
+];
+
+/// The continents. Countries that span two continents are assigned to one of them as in
+/// common geographical databases, e.g. Russia is in Europe and Turkey is in Asia.
+#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub enum Continent {
+    /// Africa.
+    Africa,
+
+    /// Antarctica.
+    Antarctica,
+
+    /// Asia.
+    Asia,
+
+    /// Europe.
+    Europe,
+
+    /// North America, including Central America and the Caribbean.
+    NorthAmerica,
+
+    /// Oceania.
+    Oceania,
+
+    /// South America.
+    SouthAmerica,
+}
+
+impl Continent {
+    /// The two-letter code of the continent, e.g. "EU".
+    #[must_use]
+    pub const fn code(self) -> &'static str {
+        match self {
+            Self::Africa => "AF",
+            Self::Antarctica => "AN",
+            Self::Asia => "AS",
+            Self::Europe => "EU",
+            Self::NorthAmerica => "NA",
+            Self::Oceania => "OC",
+            Self::SouthAmerica => "SA",
+        }
+    }
+}
+
+/// An ISO 3166-1 alpha-2 country code, which is only ever one of the known countries.
+///
+/// It parses from the code or the name of the country, ignoring case, and is displayed as
+/// the code.
+///
+/// ```
+/// use arch_mirrors_rs::country::{Continent, CountryCode};
+///
+/// let country: CountryCode = "sweden".parse().unwrap();
+/// assert_eq!(country.as_str(), "SE");
+/// assert_eq!(country.name(), "Sweden");
+/// assert_eq!(country.continent(), Continent::Europe);
+/// assert_eq!("se".parse::<CountryCode>().unwrap(), country);
+/// ```
+#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub struct CountryCode(usize);
+
+impl CountryCode {
+    /// The country with the given code, ignoring case.
+    #[must_use]
+    pub fn from_code(code: &str) -> Option<Self> {
+        let code = code.trim();
+        COUNTRIES
+            .iter()
+            .position(|(known, ..)| known.eq_ignore_ascii_case(code))
+            .map(Self)
+    }
+
+    /// The country with the given code or name, ignoring case.
+    #[must_use]
+    pub fn lookup(country: &str) -> Option<Self> {
+        let country = country.trim();
+        COUNTRIES
+            .iter()
+            .position(|(code, name, _)| {
+                code.eq_ignore_ascii_case(country) || name.eq_ignore_ascii_case(country)
+            })
+            .map(Self)
+    }
+
+    /// All known countries, sorted by code.
+    pub fn all() -> impl Iterator<Item = Self> {
+        (0..COUNTRIES.len()).map(Self)
+    }
+
+    /// The code of the country, e.g. "SE".
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        COUNTRIES[self.0].0
+    }
+
+    /// The common English name of the country, e.g. "Sweden".
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        COUNTRIES[self.0].1
+    }
+
+    /// The continent of the country.
+    #[must_use]
+    pub fn continent(self) -> Continent {
+        COUNTRIES[self.0].2
+    }
+}
+
+impl FromStr for CountryCode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::lookup(s).ok_or_else(|| Error::InvalidCountry(s.into()))
+    }
+}
+
+impl std::fmt::Display for CountryCode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Serialize for CountryCode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for CountryCode {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let code = String::deserialize(deserializer)?;
+        code.parse().map_err(serde::de::Error::custom)
+    }
+}
@@ -0,0 +1,65 @@
+//! This is where the [`Error`] enum and its dependencies go.
+
+/// The possible errors that could happen when working with the mirror status.
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    /// The mirror status could not be retrieved.
+    #[cfg(feature = "reqwest")]
+    #[error("failed to retrieve the mirror status")]
+    Network(#[from] reqwest::Error),
+
+    /// The request of an [`HttpClient`](crate::HttpClient) other than reqwest failed.
+    #[error("failed to retrieve the mirror status")]
+    Transport(#[source] Box<dyn std::error::Error + Send + Sync>),
+
+    /// The cached mirror status could not be read or written.
+    #[error("failed to access the cached mirror status")]
+    Cache(#[from] std::io::Error),
+
+    /// The retrieved data is not a mirror status.
+    #[error("invalid mirror status")]
+    Decode(#[from] serde_json::Error),
+
+    /// A URL could not be parsed.
+    #[error("invalid URL '{url}'")]
+    InvalidUrl {
+        /// The URL that could not be parsed.
+        url: String,
+        /// Why it could not be parsed.
+        source: url::ParseError,
+    },
+
+    /// The mirror status is in a version of its format that is not supported.
+    #[error(
+        "unsupported mirror status version {0}, expected version {expected}; the format of the \
+         mirror status may have changed",
+        expected = crate::Status::VERSION
+    )]
+    UnsupportedVersion(u32),
+
+    /// An invalid string was passed to [`Protocol::from_str()`](std::str::FromStr::from_str).
+    #[error("can't parse '{0}' to a valid protocol")]
+    InvalidProtocol(String),
+
+    /// An unknown country was parsed as a [`CountryCode`](crate::CountryCode).
+    #[error("unknown country '{0}'")]
+    InvalidCountry(String),
+
+    /// An invalid term was parsed as part of [`ScoreWeights`](crate::mirror::ScoreWeights).
+    #[error(
+        "invalid score weight '{0}', expected a non-negative weight of delay, duration, stddev \
+         or completion, e.g. 'stddev=2'"
+    )]
+    InvalidScoreWeight(String),
+}
+
+impl Error {
+    /// An [`Error::InvalidUrl`] for `url`.
+    pub(crate) fn invalid_url(url: &str, source: url::ParseError) -> Self {
+        Self::InvalidUrl {
+            url: url.to_owned(),
+            source,
+        }
+    }
+}
@@ -0,0 +1,33 @@
+//! This is where the [`Extra`] struct and its dependencies go.
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::cmp::Ordering;
+use std::ops::{Deref, DerefMut};
+
+/// The fields of a JSON object that are not known to this crate, such as those added to the
+/// mirror status after this version was released. They are kept so that they survive
+/// deserializing and serializing the object again, e.g. in a cache.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Extra(pub Map<String, Value>);
+
+impl Deref for Extra {
+    type Target = Map<String, Value>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for Extra {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+/// JSON values have no order, so unknown fields are only comparable when they are equal.
+impl PartialOrd for Extra {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        (self == other).then_some(Ordering::Equal)
+    }
+}
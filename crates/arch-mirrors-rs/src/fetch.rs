@@ -0,0 +1,191 @@
+//! This is where the [`StatusFetcher`] builder and its dependencies go.
+use crate::provider::ArchLinux;
+use crate::{Error, Status};
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Duration;
+
+/// A builder for retrieving the mirror status with custom timeouts, a proxy and a user
+/// agent. On wasm32, the requests are made by the browser, which does not support
+/// timeouts or proxies, so only the user agent can be set.
+///
+/// ```no_run
+/// # async fn run() -> Result<(), arch_mirrors_rs::Error> {
+/// use arch_mirrors_rs::StatusFetcher;
+/// use std::time::Duration;
+///
+/// let status = StatusFetcher::new()
+///     .connect_timeout(Duration::from_secs(5))
+///     .user_agent("my-app/1.0")
+///     .fetch()
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatusFetcher {
+    url: String,
+    #[cfg(not(target_arch = "wasm32"))]
+    connect_timeout: Option<Duration>,
+    #[cfg(not(target_arch = "wasm32"))]
+    read_timeout: Option<Duration>,
+    #[cfg(not(target_arch = "wasm32"))]
+    timeout: Option<Duration>,
+    #[cfg(not(target_arch = "wasm32"))]
+    proxy: Option<String>,
+    user_agent: Option<String>,
+}
+
+impl StatusFetcher {
+    /// A fetcher for the official Arch Linux mirror status without any timeouts, proxy or
+    /// user agent.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            url: ArchLinux::URL.to_owned(),
+            #[cfg(not(target_arch = "wasm32"))]
+            connect_timeout: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            read_timeout: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            timeout: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            proxy: None,
+            user_agent: None,
+        }
+    }
+
+    /// Retrieve the mirror status from `url` instead, which must follow the format of the
+    /// official one.
+    #[must_use]
+    pub fn url(mut self, url: impl Into<String>) -> Self {
+        self.url = url.into();
+        self
+    }
+
+    /// The timeout for connecting to the server.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[must_use]
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// The timeout for each read from the server. The blocking client does not support it,
+    /// so it is ignored by `fetch_blocking`.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[must_use]
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = Some(timeout);
+        self
+    }
+
+    /// The timeout for the whole request, from connecting until the response is read.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[must_use]
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Send the requests through the HTTP(S) proxy at `url`.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[must_use]
+    pub fn proxy(mut self, url: impl Into<String>) -> Self {
+        self.proxy = Some(url.into());
+        self
+    }
+
+    /// The value of the User-Agent header of the requests.
+    #[must_use]
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = Some(user_agent.into());
+        self
+    }
+
+    /// A client builder with the timeouts, proxy and user agent of the fetcher, so further
+    /// options can be set before building the client.
+    ///
+    /// # Errors
+    /// Fails if the proxy URL is invalid.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn client_builder(&self) -> Result<reqwest::ClientBuilder, Error> {
+        let mut builder = reqwest::Client::builder();
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        if let Some(timeout) = self.read_timeout {
+            builder = builder.read_timeout(timeout);
+        }
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(proxy_for(proxy)?);
+        }
+        if let Some(user_agent) = &self.user_agent {
+            builder = builder.user_agent(user_agent);
+        }
+        Ok(builder)
+    }
+
+    /// A client builder with the user agent of the fetcher, so further options can be set
+    /// before building the client.
+    ///
+    /// # Errors
+    /// Never fails on wasm32, but returns a [`Result`] like on other targets.
+    #[cfg(target_arch = "wasm32")]
+    #[allow(clippy::unnecessary_wraps)]
+    pub fn client_builder(&self) -> Result<reqwest::ClientBuilder, Error> {
+        let mut builder = reqwest::Client::builder();
+        if let Some(user_agent) = &self.user_agent {
+            builder = builder.user_agent(user_agent);
+        }
+        Ok(builder)
+    }
+
+    /// Retrieve the mirror status.
+    ///
+    /// # Errors
+    /// Fails if the client cannot be built, the request fails or the response is not a
+    /// supported mirror status.
+    pub async fn fetch(&self) -> Result<Status, Error> {
+        let client = self.client_builder()?.build()?;
+        Status::get_with_client(&client, &self.url).await
+    }
+
+    /// Retrieve the mirror status, blocking the current thread.
+    ///
+    /// # Errors
+    /// Fails if the client cannot be built, the request fails or the response is not a
+    /// supported mirror status.
+    #[cfg(feature = "blocking")]
+    pub fn fetch_blocking(&self) -> Result<Status, Error> {
+        let mut builder = reqwest::blocking::Client::builder();
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(proxy_for(proxy)?);
+        }
+        if let Some(user_agent) = &self.user_agent {
+            builder = builder.user_agent(user_agent);
+        }
+        Status::get_blocking_with_client(&builder.build()?, &self.url)
+    }
+}
+
+impl Default for StatusFetcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A proxy for all requests through the HTTP(S) proxy at `url`.
+#[cfg(not(target_arch = "wasm32"))]
+fn proxy_for(url: &str) -> Result<reqwest::Proxy, Error> {
+    let parsed = url::Url::parse(url).map_err(|err| Error::invalid_url(url, err))?;
+    Ok(reqwest::Proxy::all(parsed)?)
+}
@@ -0,0 +1,48 @@
+//! This is where the [`HttpClient`] trait and its implementations go.
+use crate::Error;
+use std::future::Future;
+use url::Url;
+
+/// An HTTP client that retrieves the mirror status, so it can be fetched with
+/// [`Status::get_with`](crate::Status::get_with) from any HTTP library, or from a mock
+/// transport in tests.
+///
+/// It is implemented for [`reqwest::Client`] with the `reqwest` feature. Blocking clients,
+/// such as ureq, can return a [`std::future::ready`] future.
+///
+/// ```
+/// use arch_mirrors_rs::{Error, HttpClient};
+/// use std::future::{Future, ready};
+/// use url::Url;
+///
+/// /// Serves the same mirror status for every URL.
+/// struct Mock(&'static str);
+///
+/// impl HttpClient for Mock {
+///     fn get(&self, _url: &Url) -> impl Future<Output = Result<Vec<u8>, Error>> {
+///         ready(Ok(self.0.as_bytes().to_vec()))
+///     }
+/// }
+///
+/// async fn offline() -> Result<arch_mirrors_rs::Status, Error> {
+///     let mock = Mock(r#"{"cutoff": 3600, "last_check": "2026-01-01T00:00:00Z", "num_checks": 1,
+///         "check_frequency": 600, "urls": [], "version": 3}"#);
+///     arch_mirrors_rs::Status::get_with(&mock, "https://example.com/status.json").await
+/// }
+/// ```
+pub trait HttpClient {
+    /// Retrieve the body of the response to a GET request to `url`.
+    ///
+    /// # Errors
+    /// Fails if the request fails or the response has an error status, which should be
+    /// reported as [`Error::Transport`] unless a more specific variant applies.
+    fn get(&self, url: &Url) -> impl Future<Output = Result<Vec<u8>, Error>>;
+}
+
+#[cfg(feature = "reqwest")]
+impl HttpClient for reqwest::Client {
+    async fn get(&self, url: &Url) -> Result<Vec<u8>, Error> {
+        let response = self.get(url.clone()).send().await?.error_for_status()?;
+        Ok(response.bytes().await?.to_vec())
+    }
+}
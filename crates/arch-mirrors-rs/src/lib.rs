@@ -3,10 +3,29 @@
 #![warn(missing_docs)]
 #![warn(rustdoc::missing_crate_level_docs)]
 #![warn(rustdoc::invalid_codeblock_attributes)]
+#[cfg(not(target_arch = "wasm32"))]
+pub mod cache;
+pub mod country;
+pub mod error;
+pub mod extra;
+#[cfg(feature = "reqwest")]
+pub mod fetch;
+pub mod http;
 pub mod mirror;
+pub mod mirrorlist;
 pub mod protocol;
+pub mod provider;
+pub mod selector;
 pub mod status;
 
 pub use crate::mirror::Mirror;
+#[cfg(not(target_arch = "wasm32"))]
+pub use cache::CachedStatus;
+pub use country::CountryCode;
+pub use error::Error;
+#[cfg(feature = "reqwest")]
+pub use fetch::StatusFetcher;
+pub use http::HttpClient;
 pub use protocol::Protocol;
+pub use provider::MirrorProvider;
 pub use status::Status;
@@ -1,5 +1,7 @@
 //! This is where the [`Url`] struct and all of its dependencies go.
+use crate::Error;
 use serde::{Deserialize, Serialize};
+use std::str::FromStr;
 
 /// An Arch Linux mirror and its statistics.
 #[derive(Debug, Clone, PartialOrd, PartialEq, Serialize, Deserialize)]
@@ -25,7 +27,8 @@ pub struct Mirror {
 
     /// The average (mean) time it took to connect and retrieve the `lastsync` file from the given
     /// URL. Note that this connection time is from the location of the Arch server; your geography
-    /// may product different results.
+    /// may product different results. The mirror status names it `duration_avg`.
+    #[serde(rename = "duration_avg", alias = "duration_average")]
     pub duration_average: Option<f64>,
 
     /// The standard deviation of the connect and retrieval time. A high standard deviation can
@@ -42,7 +45,10 @@ pub struct Mirror {
     /// The country where the mirror resides in.
     pub country: String,
 
-    /// The ISO-3166-1 country code where the mirror resides in.
+    /// The ISO-3166-1 country code where the mirror resides in. It is empty if unknown, and
+    /// [`typed_country_code`](Self::typed_country_code) gives it as a [`CountryCode`].
+    ///
+    /// [`CountryCode`]: crate::CountryCode
     pub country_code: String,
 
     /// Whether or not this mirror has Arch Linux ISOs(?)
@@ -56,4 +62,260 @@ pub struct Mirror {
 
     /// The details of the mirror.
     pub details: String,
+
+    /// The fields of the mirror that are not known to this crate.
+    #[serde(flatten)]
+    pub extra: crate::extra::Extra,
+}
+
+impl Mirror {
+    /// The country where the mirror resides in, if it is known.
+    #[must_use]
+    pub fn typed_country_code(&self) -> Option<crate::CountryCode> {
+        crate::CountryCode::from_code(&self.country_code)
+    }
+
+    /// The time since the last sync of the mirror at `now`, if it ever synced.
+    #[must_use]
+    pub fn age(&self, now: jiff::Timestamp) -> Option<jiff::SignedDuration> {
+        Some(now.duration_since(self.last_sync?))
+    }
+
+    /// Compute the score of the mirror the way the Arch Linux mirror status does, from its
+    /// delay, average duration, standard deviation and completion percentage. Like
+    /// [`score`](Self::score), lower is better, and there is none for mirrors that never
+    /// completed a check.
+    #[must_use]
+    pub fn compute_score(&self) -> Option<f64> {
+        self.weighted_score(&ScoreWeights::default())
+    }
+
+    /// Compute the score of the mirror like [`compute_score`](Self::compute_score), with
+    /// each term multiplied by its weight and the completion percentage raised to the power
+    /// of its weight. Lower is better.
+    #[must_use]
+    pub fn weighted_score(&self, weights: &ScoreWeights) -> Option<f64> {
+        let completion_pct = self.completion_pct.filter(|pct| *pct > 0.0)?;
+        let hours_delay = f64::from(self.delay?) / 3600.0;
+        let duration_stddev = self.duration_stddev.unwrap_or(0.0);
+        let sum = weights.delay * hours_delay
+            + weights.duration * self.duration_average?
+            + weights.stddev * duration_stddev;
+        Some(sum / completion_pct.powf(weights.completion))
+    }
+
+    /// The layout of the repositories on Arch Linux mirrors, relative to the URL of a mirror
+    /// and using pacman's `$repo` and `$arch` variables.
+    pub const REPO_LAYOUT: &'static str = "$repo/os/$arch";
+
+    /// The URL of the directory of a repository on the mirror, e.g. `$repo/os/$arch/`. The
+    /// path is joined to the URL of the mirror whether or not it ends with a slash.
+    ///
+    /// # Errors
+    /// Fails if `repo` or `arch` do not make up a valid URL.
+    pub fn repo_url(&self, repo: &str, arch: &str) -> Result<url::Url, Error> {
+        self.repo_url_with_layout(Self::REPO_LAYOUT, repo, arch)
+    }
+
+    /// The URL of the database of a repository on the mirror, e.g.
+    /// `$repo/os/$arch/$repo.db`.
+    ///
+    /// # Errors
+    /// Fails if `repo` or `arch` do not make up a valid URL.
+    pub fn db_url(&self, repo: &str, arch: &str) -> Result<url::Url, Error> {
+        self.db_url_with_layout(Self::REPO_LAYOUT, repo, arch)
+    }
+
+    /// The URL of the directory of a repository on a mirror whose repositories are laid out
+    /// as `layout`, e.g. `$arch/$repo` for Arch Linux ARM.
+    ///
+    /// # Errors
+    /// Fails if `repo` or `arch` do not make up a valid URL.
+    pub fn repo_url_with_layout(
+        &self,
+        layout: &str,
+        repo: &str,
+        arch: &str,
+    ) -> Result<url::Url, Error> {
+        let path = layout.replace("$repo", repo).replace("$arch", arch);
+        let path = format!("{}/", path.trim_matches('/'));
+        // Paths are joined relative to the URL, which must end with a slash to keep its last
+        // segment.
+        let mut base = self.url.clone();
+        if !base.path().ends_with('/') {
+            base.set_path(&format!("{}/", base.path()));
+        }
+        base.join(&path)
+            .map_err(|err| Error::invalid_url(&format!("{base}{path}"), err))
+    }
+
+    /// The URL of the database of a repository on a mirror whose repositories are laid out
+    /// as `layout`.
+    ///
+    /// # Errors
+    /// Fails if `repo` or `arch` do not make up a valid URL.
+    pub fn db_url_with_layout(
+        &self,
+        layout: &str,
+        repo: &str,
+        arch: &str,
+    ) -> Result<url::Url, Error> {
+        let repo_url = self.repo_url_with_layout(layout, repo, arch)?;
+        let file = format!("{repo}.db");
+        repo_url
+            .join(&file)
+            .map_err(|err| Error::invalid_url(&format!("{repo_url}{file}"), err))
+    }
+}
+
+/// The weights of the terms of the score of a mirror, for [`Mirror::weighted_score`]. A
+/// weight of 0 ignores its term. The default weights of 1 give the score of the Arch Linux
+/// mirror status.
+///
+/// Weights are parsed from a comma-separated list of terms and their weights, where terms
+/// that are left out keep their default weight.
+///
+/// ```
+/// use arch_mirrors_rs::mirror::ScoreWeights;
+///
+/// let weights = "stddev=4,delay=0.5".parse::<ScoreWeights>().unwrap();
+/// assert_eq!(
+///     weights,
+///     ScoreWeights {
+///         delay: 0.5,
+///         stddev: 4.0,
+///         ..ScoreWeights::default()
+///     }
+/// );
+/// assert!("latency=1".parse::<ScoreWeights>().is_err());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScoreWeights {
+    /// The weight of the delay in hours.
+    pub delay: f64,
+
+    /// The weight of the average duration of the checks.
+    pub duration: f64,
+
+    /// The weight of the standard deviation of the duration of the checks.
+    pub stddev: f64,
+
+    /// The power of the completion percentage that the sum of the other terms is divided by.
+    pub completion: f64,
+}
+
+impl Default for ScoreWeights {
+    fn default() -> Self {
+        Self {
+            delay: 1.0,
+            duration: 1.0,
+            stddev: 1.0,
+            completion: 1.0,
+        }
+    }
+}
+
+impl FromStr for ScoreWeights {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let defaults = Self::default();
+        let [delay, duration, stddev, completion] = parse_weights(
+            s,
+            ["delay", "duration", "stddev", "completion"],
+            [
+                defaults.delay,
+                defaults.duration,
+                defaults.stddev,
+                defaults.completion,
+            ],
+        )
+        .map_err(|term| Error::InvalidScoreWeight(term.to_owned()))?;
+        Ok(Self {
+            delay,
+            duration,
+            stddev,
+            completion,
+        })
+    }
+}
+
+/// Parse a comma-separated list of weights like "stddev=4,delay=0.5" into the weights of
+/// the given names, in the same order. Names that are left out keep their default weight.
+/// Fails with the offending term if it is not one of the names followed by "=" and a
+/// finite weight of at least 0.
+pub(crate) fn parse_weights<'a, const N: usize>(
+    s: &'a str,
+    names: [&str; N],
+    defaults: [f64; N],
+) -> Result<[f64; N], &'a str> {
+    let mut weights = defaults;
+    for term in s.split(',') {
+        let term = term.trim();
+        let (name, weight) = term.split_once('=').ok_or(term)?;
+        let weight = weight
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .filter(|weight| weight.is_finite() && *weight >= 0.0)
+            .ok_or(term)?;
+        let index = names
+            .iter()
+            .position(|known| *known == name.trim())
+            .ok_or(term)?;
+        weights[index] = weight;
+    }
+    Ok(weights)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jiff::{SignedDuration, Timestamp};
+
+    /// A mirror as listed by the Arch Linux mirror status.
+    const ARCHWEB_MIRROR: &str = r#"{"url": "https://mirror.example.se/archlinux/",
+        "protocol": "https", "last_sync": "2024-05-01T09:30:00Z", "completion_pct": 0.98,
+        "delay": 1830, "duration_avg": 0.36, "duration_stddev": 0.21,
+        "score": 1.1003401360544217, "active": true, "country": "Sweden",
+        "country_code": "SE", "isos": true, "ipv4": true, "ipv6": true, "details":
+        "https://archlinux.org/mirrors/mirror.example.se/1/"}"#;
+
+    #[test]
+    fn read_archweb_mirror() {
+        let mirror = serde_json::from_str::<Mirror>(ARCHWEB_MIRROR).unwrap();
+        assert_eq!(mirror.duration_average, Some(0.36));
+        assert!(mirror.extra.0.is_empty());
+        let value = serde_json::to_value(&mirror).unwrap();
+        assert_eq!(value["duration_avg"], 0.36);
+    }
+
+    #[test]
+    fn read_old_cache() {
+        let json = ARCHWEB_MIRROR.replace("duration_avg", "duration_average");
+        let mirror = serde_json::from_str::<Mirror>(&json).unwrap();
+        assert_eq!(mirror.duration_average, Some(0.36));
+    }
+
+    #[test]
+    fn compute_archweb_score() {
+        let mirror = serde_json::from_str::<Mirror>(ARCHWEB_MIRROR).unwrap();
+        let score = mirror.compute_score().unwrap();
+        assert!((score - mirror.score.unwrap()).abs() < 1e-9, "{score}");
+
+        let incomplete = Mirror {
+            completion_pct: Some(0.0),
+            ..mirror
+        };
+        assert_eq!(incomplete.compute_score(), None);
+    }
+
+    #[test]
+    fn age_since_last_sync() {
+        let mut mirror = serde_json::from_str::<Mirror>(ARCHWEB_MIRROR).unwrap();
+        let now = "2024-05-01T10:00:00Z".parse::<Timestamp>().unwrap();
+        assert_eq!(mirror.age(now), Some(SignedDuration::from_mins(30)));
+        mirror.last_sync = None;
+        assert_eq!(mirror.age(now), None);
+    }
 }
@@ -0,0 +1,471 @@
+//! This is where the [`Mirrorlist`] struct, the [`MirrorlistWriter`] and their dependencies
+//! go.
+use crate::Mirror;
+use jiff::Timestamp;
+use std::convert::Infallible;
+use std::fmt::Formatter;
+use std::io::{self, Write};
+use std::str::FromStr;
+use url::Url;
+
+/// The banner at the top of the mirrorlists generated by reflector.
+const BANNER: &str = "Arch Linux mirrorlist generated by Reflector";
+
+/// A pacman mirrorlist, e.g. `/etc/pacman.d/mirrorlist`.
+///
+/// Every line is kept, so the mirrorlist can be edited and written back with [`Display`].
+/// Server lines are written back as `Server = url`, or `#Server = url` if they are commented
+/// out, and comments and blank lines without the whitespace around them. Other lines are
+/// written back as they are. Mirrorlists written by [`MirrorlistWriter`] are thus written
+/// back unchanged.
+///
+/// ```
+/// use arch_mirrors_rs::mirrorlist::Mirrorlist;
+///
+/// let mirrorlist = Mirrorlist::parse(
+///     "## Sweden\nServer = https://mirror.example.se/$repo/os/$arch\n\
+///      #Server = http://mirror.example.de/$repo/os/$arch\n",
+/// );
+/// assert_eq!(
+///     mirrorlist.servers().collect::<Vec<_>>(),
+///     ["https://mirror.example.se/$repo/os/$arch"]
+/// );
+/// assert_eq!(mirrorlist.disabled_servers().count(), 1);
+/// ```
+///
+/// [`Display`]: std::fmt::Display
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Mirrorlist {
+    /// The header written by reflector, if the mirrorlist has one.
+    pub header: Option<Header>,
+
+    /// Every line of the mirrorlist, in order.
+    pub lines: Vec<Line>,
+}
+
+/// A line of a pacman mirrorlist.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Line {
+    /// A `Server = ...` line, which is disabled if it is commented out. The spacing and the
+    /// number of "#" of the original line are not kept.
+    Server {
+        /// The URL of the server, with pacman's `$repo` and `$arch` variables.
+        url: String,
+
+        /// Whether the line is not commented out.
+        enabled: bool,
+    },
+
+    /// A comment other than a server, without its leading "#" and the whitespace around it.
+    Comment(String),
+
+    /// An empty line.
+    Blank,
+
+    /// Any other line, e.g. a section of pacman.conf, as it is.
+    Other(String),
+}
+
+/// The header that reflector writes at the top of a mirrorlist. Times that cannot be
+/// parsed, e.g. those written by other versions of reflector, are left out.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Header {
+    /// The command that generated the mirrorlist.
+    pub with: Option<String>,
+
+    /// When the mirrorlist was generated.
+    pub when: Option<Timestamp>,
+
+    /// Where the mirror status was retrieved from.
+    pub from: Option<String>,
+
+    /// When the mirror status was retrieved.
+    pub retrieved: Option<Timestamp>,
+
+    /// When the mirror status was last checked.
+    pub last_check: Option<Timestamp>,
+}
+
+impl Mirrorlist {
+    /// Parse a mirrorlist. Every line is valid, so parsing never fails.
+    #[must_use]
+    pub fn parse(content: &str) -> Self {
+        let lines = content.lines().map(Line::parse).collect::<Vec<_>>();
+        let header = Header::parse(&lines);
+        Self { header, lines }
+    }
+
+    /// The URLs of the servers that are enabled, in order.
+    pub fn servers(&self) -> impl Iterator<Item = &str> {
+        self.lines.iter().filter_map(|line| match line {
+            Line::Server { url, enabled: true } => Some(url.as_str()),
+            _ => None,
+        })
+    }
+
+    /// The URLs of the servers that are commented out, in order.
+    pub fn disabled_servers(&self) -> impl Iterator<Item = &str> {
+        self.lines.iter().filter_map(|line| match line {
+            Line::Server {
+                url,
+                enabled: false,
+            } => Some(url.as_str()),
+            _ => None,
+        })
+    }
+}
+
+impl FromStr for Mirrorlist {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self::parse(s))
+    }
+}
+
+impl std::fmt::Display for Mirrorlist {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        for line in &self.lines {
+            writeln!(f, "{line}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Line {
+    /// Parse a single line of a mirrorlist.
+    fn parse(line: &str) -> Self {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            return Self::Blank;
+        }
+        let (uncommented, enabled) = match trimmed.strip_prefix('#') {
+            Some(comment) => (comment.trim_start_matches('#').trim_start(), false),
+            None => (trimmed, true),
+        };
+        if let Some((key, value)) = uncommented.split_once('=') {
+            if key.trim() == "Server" {
+                return Self::Server {
+                    url: value.trim().to_owned(),
+                    enabled,
+                };
+            }
+        }
+        match trimmed.strip_prefix('#') {
+            Some(comment) => Self::Comment(comment.to_owned()),
+            None => Self::Other(line.to_owned()),
+        }
+    }
+}
+
+impl std::fmt::Display for Line {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self {
+            Self::Server { url, enabled: true } => write!(f, "Server = {url}"),
+            Self::Server {
+                url,
+                enabled: false,
+            } => write!(f, "#Server = {url}"),
+            Self::Comment(comment) => write!(f, "#{comment}"),
+            Self::Blank => Ok(()),
+            Self::Other(line) => f.write_str(line),
+        }
+    }
+}
+
+impl Header {
+    /// Find the header in the comments at the top of a mirrorlist.
+    fn parse(lines: &[Line]) -> Option<Self> {
+        let mut header = Self::default();
+        let mut found = false;
+        let comments = lines
+            .iter()
+            .take_while(|line| matches!(line, Line::Comment(_) | Line::Blank))
+            .filter_map(|line| match line {
+                Line::Comment(comment) => Some(comment.trim()),
+                _ => None,
+            });
+        for comment in comments {
+            if comment.contains(BANNER) {
+                found = true;
+                continue;
+            }
+            let Some((key, value)) = comment.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            match key {
+                "With" => header.with = Some(value.to_owned()),
+                "When" => header.when = value.parse().ok(),
+                "From" => header.from = Some(value.to_owned()),
+                "Retrieved" => header.retrieved = value.parse().ok(),
+                "Last Check" => header.last_check = value.parse().ok(),
+                _ => continue,
+            }
+            found = true;
+        }
+        found.then_some(header)
+    }
+}
+
+/// A comment written above the Server line of a mirror.
+type Comment = Box<dyn Fn(&Mirror) -> Option<String> + Send + Sync>;
+
+/// Writes mirrors as a pacman mirrorlist, which [`Mirrorlist::parse`] can read back.
+///
+/// ```
+/// use arch_mirrors_rs::mirrorlist::{Header, MirrorlistWriter};
+///
+/// let writer = MirrorlistWriter::new()
+///     .header(Header {
+///         from: Some("https://archlinux.org/mirrors/status/json/".to_owned()),
+///         ..Header::default()
+///     })
+///     .comment(|mirror| Some(mirror.country.clone()));
+/// let mut out = Vec::new();
+/// writer.write(&mut out, &[]).unwrap();
+/// assert!(String::from_utf8(out).unwrap().contains("# From:"));
+/// ```
+pub struct MirrorlistWriter {
+    header: Option<Header>,
+    repo_path: String,
+    comment: Option<Comment>,
+}
+
+impl MirrorlistWriter {
+    /// A writer without a header or comments, for the Arch Linux repository layout.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            header: None,
+            repo_path: Mirror::REPO_LAYOUT.to_owned(),
+            comment: None,
+        }
+    }
+
+    /// Write the reflector banner and the given header before the mirrors.
+    #[must_use]
+    pub fn header(mut self, header: Header) -> Self {
+        self.header = Some(header);
+        self
+    }
+
+    /// The path of a repository relative to the base URL of a mirror, appended to every
+    /// Server line. Defaults to [`Mirror::REPO_LAYOUT`].
+    #[must_use]
+    pub fn repo_path(mut self, repo_path: impl Into<String>) -> Self {
+        self.repo_path = repo_path.into();
+        self
+    }
+
+    /// Write the comment returned by `comment`, if any, above the Server line of each mirror.
+    #[must_use]
+    pub fn comment(
+        mut self,
+        comment: impl Fn(&Mirror) -> Option<String> + Send + Sync + 'static,
+    ) -> Self {
+        self.comment = Some(Box::new(comment));
+        self
+    }
+
+    /// Write the header followed by every mirror.
+    ///
+    /// # Errors
+    /// Fails if `out` cannot be written to.
+    pub fn write<'a>(
+        &self,
+        mut out: impl Write,
+        mirrors: impl IntoIterator<Item = &'a Mirror>,
+    ) -> io::Result<()> {
+        self.write_header(&mut out)?;
+        for mirror in mirrors {
+            self.write_mirror(&mut out, mirror)?;
+        }
+        Ok(())
+    }
+
+    /// Write the banner and the header, if there is one, followed by an empty line.
+    ///
+    /// # Errors
+    /// Fails if `out` cannot be written to.
+    pub fn write_header(&self, mut out: impl Write) -> io::Result<()> {
+        let Some(header) = &self.header else {
+            return Ok(());
+        };
+        writeln!(
+            out,
+            "################################################################################\n\
+             ################# {BANNER} #################\n\
+             ################################################################################\n"
+        )?;
+        let fields = [
+            ("With", header.with.clone()),
+            ("When", header.when.map(|when| when.to_string())),
+            ("From", header.from.clone()),
+            (
+                "Retrieved",
+                header.retrieved.map(|retrieved| retrieved.to_string()),
+            ),
+            (
+                "Last Check",
+                header.last_check.map(|last_check| last_check.to_string()),
+            ),
+        ];
+        for (key, value) in fields {
+            if let Some(value) = value {
+                writeln!(out, "# {:<12}{value}", format!("{key}:"))?;
+            }
+        }
+        writeln!(out)
+    }
+
+    /// Write the comment and the Server line of `mirror`.
+    ///
+    /// # Errors
+    /// Fails if `out` cannot be written to.
+    pub fn write_mirror(&self, mut out: impl Write, mirror: &Mirror) -> io::Result<()> {
+        if let Some(comment) = self.comment.as_ref().and_then(|comment| comment(mirror)) {
+            for line in comment.lines() {
+                writeln!(out, "# {line}")?;
+            }
+        }
+        self.write_server(out, &mirror.url)
+    }
+
+    /// Write the Server line of a mirror with the base URL `url`, without a comment.
+    ///
+    /// # Errors
+    /// Fails if `out` cannot be written to.
+    pub fn write_server(&self, mut out: impl Write, url: &Url) -> io::Result<()> {
+        writeln!(out, "Server = {url}{}", self.repo_path)
+    }
+}
+
+impl Default for MirrorlistWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Debug for MirrorlistWriter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MirrorlistWriter")
+            .field("header", &self.header)
+            .field("repo_path", &self.repo_path)
+            .field("comment", &self.comment.is_some())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_header() {
+        let when = "2024-05-01T10:00:00Z".parse::<Timestamp>().unwrap();
+        let header = Header {
+            with: Some("reflector --save /etc/pacman.d/mirrorlist".to_owned()),
+            when: Some(when),
+            from: Some("https://archlinux.org/mirrors/status/json/".to_owned()),
+            retrieved: Some(when),
+            last_check: Some(when),
+        };
+        let mut out = Vec::new();
+        MirrorlistWriter::new()
+            .header(header.clone())
+            .write_header(&mut out)
+            .unwrap();
+        let mirrorlist = Mirrorlist::parse(&String::from_utf8(out).unwrap());
+        assert_eq!(mirrorlist.header, Some(header));
+    }
+
+    #[test]
+    fn parse_upstream_header() {
+        // The Python reflector writes times in UTC without an offset, which are left out.
+        let mirrorlist = Mirrorlist::parse(
+            "################################################################################\n\
+             ################# Arch Linux mirrorlist generated by Reflector #################\n\
+             ################################################################################\n\
+             \n\
+             # With:       reflector --save /etc/pacman.d/mirrorlist\n\
+             # When:       2024-05-01 10:00:00 UTC\n\
+             # From:       https://archlinux.org/mirrors/status/json/\n\
+             # Retrieved:  2024-05-01 09:59:58 UTC\n\
+             # Last Check: 2024-05-01 09:50:12 UTC\n\
+             \n\
+             Server = https://mirror.example.se/$repo/os/$arch\n",
+        );
+        assert_eq!(
+            mirrorlist.header,
+            Some(Header {
+                with: Some("reflector --save /etc/pacman.d/mirrorlist".to_owned()),
+                from: Some("https://archlinux.org/mirrors/status/json/".to_owned()),
+                ..Header::default()
+            })
+        );
+        assert_eq!(Mirrorlist::parse("## Sweden\nServer = a\n").header, None);
+    }
+
+    #[test]
+    fn parse_commented_servers() {
+        let mirrorlist =
+            Mirrorlist::parse("#Server = a\n## Server=b\n  # Server = c  \n## Sweden\n");
+        assert_eq!(
+            mirrorlist.disabled_servers().collect::<Vec<_>>(),
+            ["a", "b", "c"]
+        );
+        assert_eq!(mirrorlist.servers().count(), 0);
+        assert_eq!(mirrorlist.lines[3], Line::Comment("# Sweden".to_owned()));
+        assert_eq!(
+            mirrorlist.to_string(),
+            "#Server = a\n#Server = b\n#Server = c\n## Sweden\n"
+        );
+    }
+
+    #[test]
+    fn parse_pacman_conf() {
+        let content = "[options]\nHoldPkg = pacman glibc\n\n[core]\n\
+                       Server = https://mirror.example.se/$repo/os/$arch\n\
+                       Include = /etc/pacman.d/mirrorlist\n";
+        let mirrorlist = Mirrorlist::parse(content);
+        assert_eq!(
+            mirrorlist.lines,
+            [
+                Line::Other("[options]".to_owned()),
+                Line::Other("HoldPkg = pacman glibc".to_owned()),
+                Line::Blank,
+                Line::Other("[core]".to_owned()),
+                Line::Server {
+                    url: "https://mirror.example.se/$repo/os/$arch".to_owned(),
+                    enabled: true,
+                },
+                Line::Other("Include = /etc/pacman.d/mirrorlist".to_owned()),
+            ]
+        );
+        assert_eq!(mirrorlist.to_string(), content);
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut out = Vec::new();
+        let writer = MirrorlistWriter::new().header(Header {
+            with: Some("reflector --latest 5".to_owned()),
+            when: Some("2024-05-01T10:00:00Z".parse().unwrap()),
+            ..Header::default()
+        });
+        writer.write_header(&mut out).unwrap();
+        writeln!(out, "# Sweden").unwrap();
+        writer
+            .write_server(&mut out, &Url::parse("https://mirror.example.se/").unwrap())
+            .unwrap();
+        writeln!(out, "#Server = http://mirror.example.de/$repo/os/$arch").unwrap();
+        let content = String::from_utf8(out).unwrap();
+        let mirrorlist = Mirrorlist::parse(&content);
+        assert_eq!(
+            mirrorlist.servers().collect::<Vec<_>>(),
+            ["https://mirror.example.se/$repo/os/$arch"]
+        );
+        assert_eq!(mirrorlist.to_string(), content);
+    }
+}
@@ -1,41 +1,22 @@
 //! This is where the [`Protocol`](Protocol) structs and its dependencies go.
+use crate::Error;
 use serde::{Deserialize, Serialize};
 use std::fmt::Formatter;
 use std::str::FromStr;
 
-/// The possible errors that could happen when working with [`Protocol`](Protocol).
-#[derive(Debug)]
-pub enum Error {
-    /// An invalid string was passed to [`Protocol::from_str()`].
-    InvalidProtocol(String),
-}
-
-impl std::fmt::Display for Error {
-    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
-        match self {
-            Self::InvalidProtocol(protocol) => {
-                write!(f, "can't parse '{protocol}' to a valid protocol")
-            }
-        }
-    }
-}
-
-impl std::error::Error for Error {
-    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
-        None
-    }
-
-    fn description(&self) -> &'static str {
-        ""
-    }
-
-    fn cause(&self) -> Option<&dyn std::error::Error> {
-        None
-    }
-}
-
 /// This contains every supported protocol by Arch Linux mirror status.
+///
+/// A protocol is displayed as its URL scheme, which parses back into the same protocol
+/// regardless of case.
+///
+/// ```
+/// use arch_mirrors_rs::Protocol;
+///
+/// assert_eq!(Protocol::Https.to_string(), "https");
+/// assert_eq!("HTTPS".parse::<Protocol>().unwrap(), Protocol::Https);
+/// ```
 #[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[non_exhaustive]
 pub enum Protocol {
     /// The HTTP protocol.
     #[serde(rename = "http")]
@@ -48,28 +29,41 @@ pub enum Protocol {
     /// The rsync protocol.
     #[serde(rename = "rsync")]
     Rsync,
+
+    /// The FTP protocol.
+    #[serde(rename = "ftp")]
+    Ftp,
 }
 
 impl FromStr for Protocol {
     type Err = Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
+        match s.to_ascii_lowercase().as_str() {
             "http" => Ok(Self::Http),
             "https" => Ok(Self::Https),
             "rsync" => Ok(Self::Rsync),
-            other => Err(Error::InvalidProtocol(other.into())),
+            "ftp" => Ok(Self::Ftp),
+            _ => Err(Error::InvalidProtocol(s.into())),
         }
     }
 }
 
-impl std::fmt::Display for Protocol {
-    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+impl Protocol {
+    /// The URL scheme of the protocol.
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
         match self {
-            Self::Http => write!(f, "http")?,
-            Self::Https => write!(f, "https")?,
-            Self::Rsync => write!(f, "rsync")?,
+            Self::Http => "http",
+            Self::Https => "https",
+            Self::Rsync => "rsync",
+            Self::Ftp => "ftp",
         }
-        Ok(())
+    }
+}
+
+impl std::fmt::Display for Protocol {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        f.write_str(self.as_str())
     }
 }
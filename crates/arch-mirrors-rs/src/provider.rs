@@ -0,0 +1,72 @@
+//! This is where the [`MirrorProvider`] trait and its implementations go.
+use crate::Status;
+
+/// The error returned when the data of a [`MirrorProvider`] cannot be normalized.
+pub type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// A source of mirrors, such as the mirror status of a distribution.
+///
+/// A provider tells where its data is retrieved from and how that data is normalized into a
+/// [`Status`]. Retrieving the data, including caching and retries, is left to the caller, so
+/// any HTTP client can be used.
+pub trait MirrorProvider {
+    /// The URL from which the data of the provider is retrieved.
+    fn url(&self) -> &str;
+
+    /// Normalize the retrieved data into a [`Status`].
+    ///
+    /// # Errors
+    /// Fails if the data is not in the format of the provider.
+    fn parse(&self, data: &[u8]) -> Result<Status, Error>;
+}
+
+/// The Arch Linux mirror status API.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchLinux {
+    url: String,
+    lenient: bool,
+}
+
+impl ArchLinux {
+    /// The URL of the official Arch Linux mirror status.
+    pub const URL: &'static str = "https://archlinux.org/mirrors/status/json/";
+
+    /// A provider for the mirror status at `url`, which must follow the format of the
+    /// official one.
+    #[must_use]
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            lenient: false,
+        }
+    }
+
+    /// Whether to accept mirror statuses in unsupported versions of the format, as
+    /// [`Status::from_json_lenient`] does.
+    #[must_use]
+    pub fn lenient(mut self, lenient: bool) -> Self {
+        self.lenient = lenient;
+        self
+    }
+}
+
+impl Default for ArchLinux {
+    fn default() -> Self {
+        Self::new(Self::URL)
+    }
+}
+
+impl MirrorProvider for ArchLinux {
+    fn url(&self) -> &str {
+        &self.url
+    }
+
+    fn parse(&self, data: &[u8]) -> Result<Status, Error> {
+        let status = if self.lenient {
+            Status::from_json_lenient(data)?
+        } else {
+            Status::from_json(data)?
+        };
+        Ok(status)
+    }
+}
@@ -0,0 +1,392 @@
+//! This is where the [`MirrorSelector`] builder and its dependencies go.
+use crate::{Mirror, Protocol, Status};
+use jiff::{SignedDuration, Timestamp};
+use std::cmp::{Ordering, Reverse};
+
+/// The orders in which mirrors can be sorted without measuring them.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum SortKey {
+    /// By the time of the last sync, most recently synced first. Mirrors that never synced
+    /// go last.
+    Age,
+
+    /// By country, in the order of the selected countries. Countries that were not selected
+    /// go to the position of the "*" glob, or last if there is none. Countries at the same
+    /// position are sorted alphabetically.
+    Country,
+
+    /// By the score of the mirror status, lowest and thus best first. Mirrors without a
+    /// score go last.
+    Score,
+
+    /// By the delay of the mirror status, highest first.
+    Delay,
+}
+
+/// A builder for filtering and sorting mirrors with the same semantics as reflector.
+///
+/// ```
+/// use arch_mirrors_rs::Protocol;
+/// use arch_mirrors_rs::selector::{MirrorSelector, SortKey};
+///
+/// let selector = MirrorSelector::new()
+///     .age(12.0)
+///     .countries(["Sweden", "DE"])
+///     .protocols([Protocol::Https])
+///     .sort(SortKey::Score)
+///     .take(10);
+/// # let _ = selector;
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct MirrorSelector {
+    age: Option<f32>,
+    delay: Option<f32>,
+    completion_percent: u8,
+    countries: Vec<String>,
+    protocols: Vec<Protocol>,
+    isos: bool,
+    ipv4: bool,
+    ipv6: bool,
+    sort: Option<SortKey>,
+    take: Option<usize>,
+}
+
+impl MirrorSelector {
+    /// A selector that only keeps mirrors that synced and completed every check, like
+    /// reflector without any options.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            age: None,
+            delay: None,
+            completion_percent: 100,
+            countries: Vec::new(),
+            protocols: Vec::new(),
+            isos: false,
+            ipv4: false,
+            ipv6: false,
+            sort: None,
+            take: None,
+        }
+    }
+
+    /// Only keep mirrors that synced within the last `hours`, truncated to whole hours.
+    #[must_use]
+    pub fn age(mut self, hours: f32) -> Self {
+        self.age = Some(hours);
+        self
+    }
+
+    /// Only keep mirrors with a delay of at most `hours`.
+    #[must_use]
+    pub fn delay(mut self, hours: f32) -> Self {
+        self.delay = Some(hours);
+        self
+    }
+
+    /// Only keep mirrors that completed at least `percent` of the checks, 100 by default.
+    #[must_use]
+    pub fn completion_percent(mut self, percent: u8) -> Self {
+        self.completion_percent = percent;
+        self
+    }
+
+    /// Only keep mirrors in one of the given countries, by name or code and ignoring case.
+    /// The "*" glob matches all countries, which is useful with [`SortKey::Country`].
+    #[must_use]
+    pub fn countries<S: Into<String>>(mut self, countries: impl IntoIterator<Item = S>) -> Self {
+        self.countries = countries.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Only keep mirrors that use one of the given protocols.
+    #[must_use]
+    pub fn protocols(mut self, protocols: impl IntoIterator<Item = Protocol>) -> Self {
+        self.protocols = protocols.into_iter().collect();
+        self
+    }
+
+    /// Whether to only keep mirrors that host ISOs.
+    #[must_use]
+    pub fn isos(mut self, isos: bool) -> Self {
+        self.isos = isos;
+        self
+    }
+
+    /// Whether to only keep mirrors that support IPv4.
+    #[must_use]
+    pub fn ipv4(mut self, ipv4: bool) -> Self {
+        self.ipv4 = ipv4;
+        self
+    }
+
+    /// Whether to only keep mirrors that support IPv6.
+    #[must_use]
+    pub fn ipv6(mut self, ipv6: bool) -> Self {
+        self.ipv6 = ipv6;
+        self
+    }
+
+    /// Sort the mirrors that are kept.
+    #[must_use]
+    pub fn sort(mut self, key: SortKey) -> Self {
+        self.sort = Some(key);
+        self
+    }
+
+    /// Keep at most `n` mirrors, after sorting.
+    #[must_use]
+    pub fn take(mut self, n: usize) -> Self {
+        self.take = Some(n);
+        self
+    }
+
+    /// Whether `mirror` matches every filter at `now`.
+    #[must_use]
+    pub fn matches(&self, mirror: &Mirror, now: Timestamp) -> bool {
+        mirror.last_sync.is_some()
+            && self
+                .age
+                .is_none_or(|hours| synced_within(mirror, hours, now))
+            && completed(mirror, self.completion_percent)
+            && (self.countries.is_empty() || in_countries(mirror, &self.countries))
+            && (self.protocols.is_empty() || self.protocols.contains(&mirror.protocol))
+            && self.delay.is_none_or(|hours| delayed_within(mirror, hours))
+            && (mirror.isos || !self.isos)
+            && (mirror.ipv4 || !self.ipv4)
+            && (mirror.ipv6 || !self.ipv6)
+    }
+
+    /// The mirrors of `status` that match every filter now, sorted and truncated.
+    #[must_use]
+    pub fn select(&self, status: &Status) -> Vec<Mirror> {
+        self.select_at(status, Timestamp::now())
+    }
+
+    /// The mirrors of `status` that match every filter at `now`, sorted and truncated.
+    #[must_use]
+    pub fn select_at(&self, status: &Status, now: Timestamp) -> Vec<Mirror> {
+        let mut mirrors = status
+            .urls
+            .iter()
+            .filter(|mirror| self.matches(mirror, now))
+            .cloned()
+            .collect::<Vec<_>>();
+        if let Some(key) = self.sort {
+            sort_mirrors(&mut mirrors, key, &self.countries);
+        }
+        if let Some(n) = self.take {
+            mirrors.truncate(n);
+        }
+        mirrors
+    }
+}
+
+impl Default for MirrorSelector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Sort `mirrors` by `key`. The order of `countries` is used by [`SortKey::Country`].
+pub fn sort_mirrors(mirrors: &mut [Mirror], key: SortKey, countries: &[String]) {
+    match key {
+        SortKey::Age => mirrors.sort_by_key(|mirror| Reverse(mirror.last_sync)),
+        SortKey::Country => sort_by_country(mirrors, countries),
+        SortKey::Score => mirrors.sort_by(|a, b| match (a.score, b.score) {
+            (Some(score_a), Some(score_b)) => {
+                score_a.partial_cmp(&score_b).unwrap_or(Ordering::Equal)
+            }
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        }),
+        SortKey::Delay => mirrors.sort_by_key(|mirror| Reverse(mirror.delay)),
+    }
+}
+
+/// Whether `mirror` synced within the last `hours` before `now`, truncated to whole hours.
+/// Mirrors that never synced do not match, and less than an hour matches all other mirrors.
+#[must_use]
+#[allow(clippy::cast_possible_truncation)]
+pub fn synced_within(mirror: &Mirror, hours: f32, now: Timestamp) -> bool {
+    let Some(last_sync) = mirror.last_sync else {
+        return false;
+    };
+    let max_age = SignedDuration::from_hours(hours as i64);
+    !max_age.is_positive()
+        || !last_sync
+            .checked_add(max_age)
+            .is_ok_and(|synced| synced < now)
+}
+
+/// Whether `mirror` completed at least `percent` of the checks. Mirrors without a
+/// completion percentage match.
+#[must_use]
+pub fn completed(mirror: &Mirror, percent: u8) -> bool {
+    let min_completion_pct = f64::from(percent) / 100.0;
+    mirror
+        .completion_pct
+        .is_none_or(|completion_pct| completion_pct >= min_completion_pct)
+}
+
+/// Whether the country of `mirror` is one of `countries`, by name or code and ignoring
+/// case, or they contain the "*" glob.
+#[must_use]
+pub fn in_countries(mirror: &Mirror, countries: &[String]) -> bool {
+    countries.iter().any(|country| {
+        let country = country.trim();
+        // All country names are in English and all country codes are in ASCII.
+        country == "*"
+            || country.eq_ignore_ascii_case(&mirror.country)
+            || country.eq_ignore_ascii_case(&mirror.country_code)
+    })
+}
+
+/// Whether `mirror` reports a delay of at most `hours`. Mirrors without a delay do not match.
+#[must_use]
+#[allow(clippy::cast_possible_truncation)]
+#[allow(clippy::cast_sign_loss)]
+pub fn delayed_within(mirror: &Mirror, hours: f32) -> bool {
+    let max_delay = (hours * 3600.0) as u32;
+    mirror.delay.is_some_and(|delay| delay <= max_delay)
+}
+
+fn sort_by_country(mirrors: &mut [Mirror], order: &[String]) {
+    let position = |name: &str| order.iter().position(|country| country.trim() == name);
+    let wildcard = position("*").unwrap_or(order.len());
+    let rank = |mirror: &Mirror| {
+        order
+            .iter()
+            .position(|country| {
+                let country = country.trim();
+                country.eq_ignore_ascii_case(&mirror.country)
+                    || country.eq_ignore_ascii_case(&mirror.country_code)
+            })
+            .unwrap_or(wildcard)
+    };
+    mirrors.sort_by(|a, b| {
+        rank(a)
+            .cmp(&rank(b))
+            .then_with(|| a.country.cmp(&b.country))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extra::Extra;
+
+    fn mirror(url: &str, country_code: &str, hours_ago: Option<i64>, score: Option<f64>) -> Mirror {
+        let now = "2024-05-01T10:00:00Z".parse::<Timestamp>().unwrap();
+        Mirror {
+            url: url.parse().unwrap(),
+            protocol: if url.starts_with("http:") {
+                Protocol::Http
+            } else {
+                Protocol::Https
+            },
+            last_sync: hours_ago.map(|hours| now - SignedDuration::from_hours(hours)),
+            completion_pct: Some(1.0),
+            delay: Some(600),
+            duration_average: None,
+            duration_stddev: None,
+            score,
+            active: true,
+            country: String::new(),
+            country_code: country_code.to_owned(),
+            isos: true,
+            ipv4: true,
+            ipv6: false,
+            details: String::new(),
+            extra: Extra::default(),
+        }
+    }
+
+    fn status(urls: Vec<Mirror>) -> Status {
+        Status {
+            cutoff: 3600,
+            last_check: "2024-05-01T10:00:00Z".parse().unwrap(),
+            num_checks: 1,
+            check_frequency: 600,
+            urls,
+            version: Status::VERSION,
+            extra: Extra::default(),
+        }
+    }
+
+    fn hosts(mirrors: &[Mirror]) -> Vec<&str> {
+        mirrors
+            .iter()
+            .map(|mirror| mirror.url.host_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn sort_by_age() {
+        let mut mirrors = vec![
+            mirror("https://b/", "DE", Some(5), None),
+            mirror("https://a/", "DE", None, None),
+            mirror("https://c/", "DE", Some(1), None),
+        ];
+        sort_mirrors(&mut mirrors, SortKey::Age, &[]);
+        assert_eq!(hosts(&mirrors), ["c", "b", "a"]);
+    }
+
+    #[test]
+    fn sort_by_score() {
+        let mut mirrors = vec![
+            mirror("https://a/", "DE", Some(1), None),
+            mirror("https://b/", "DE", Some(1), Some(3.0)),
+            mirror("https://c/", "DE", Some(1), Some(0.5)),
+        ];
+        sort_mirrors(&mut mirrors, SortKey::Score, &[]);
+        assert_eq!(hosts(&mirrors), ["c", "b", "a"]);
+    }
+
+    #[test]
+    fn sort_by_country() {
+        let mut mirrors = vec![
+            mirror("https://a/", "DE", Some(1), None),
+            mirror("https://b/", "FR", Some(1), None),
+            mirror("https://c/", "SE", Some(1), None),
+        ];
+        let countries = ["se".to_owned(), "*".to_owned(), "de".to_owned()];
+        sort_mirrors(&mut mirrors, SortKey::Country, &countries);
+        assert_eq!(hosts(&mirrors), ["c", "b", "a"]);
+    }
+
+    #[test]
+    fn select_latest() {
+        let now = "2024-05-01T10:00:00Z".parse::<Timestamp>().unwrap();
+        let status = status(vec![
+            mirror("https://a/", "DE", Some(20), None),
+            mirror("https://b/", "SE", Some(2), None),
+            mirror("https://c/", "DE", Some(6), None),
+            mirror("https://d/", "DE", None, None),
+            mirror("https://e/", "DE", Some(1), None),
+            mirror("http://f/", "DE", Some(1), None),
+        ]);
+        let selector = MirrorSelector::new()
+            .age(12.0)
+            .countries(["de"])
+            .protocols([Protocol::Https])
+            .sort(SortKey::Age)
+            .take(2);
+        assert_eq!(hosts(&selector.select_at(&status, now)), ["e", "c"]);
+    }
+
+    #[test]
+    fn select_matches_flags() {
+        let now = "2024-05-01T10:00:00Z".parse::<Timestamp>().unwrap();
+        let mut incomplete = mirror("https://b/", "DE", Some(1), None);
+        incomplete.completion_pct = Some(0.5);
+        let status = status(vec![mirror("https://a/", "DE", Some(1), None), incomplete]);
+        assert_eq!(hosts(&MirrorSelector::new().select_at(&status, now)), ["a"]);
+        let selector = MirrorSelector::new().completion_percent(50);
+        assert_eq!(hosts(&selector.select_at(&status, now)), ["a", "b"]);
+        let selector = MirrorSelector::new().ipv6(true);
+        assert!(selector.select_at(&status, now).is_empty());
+    }
+}
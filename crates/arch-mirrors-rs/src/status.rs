@@ -1,5 +1,10 @@
 //! This is where the [`Status`] struct and all of its direct dependencies go.
+use crate::extra::Extra;
+use crate::{Error, HttpClient, Mirror};
+use serde::de::{self, DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor};
 use serde::{Deserialize, Serialize};
+use std::fmt::Formatter;
+use std::io::Read;
 
 /// The status of all the Arch Linux mirrors.
 #[derive(Debug, Clone, PartialOrd, PartialEq, Serialize, Deserialize)]
@@ -21,4 +26,354 @@ pub struct Status {
 
     /// The version of the status.
     pub version: u32,
+
+    /// The fields of the status that are not known to this crate.
+    #[serde(flatten)]
+    pub extra: crate::extra::Extra,
+}
+
+impl Status {
+    /// The latest version of the format of the mirror status that is supported.
+    pub const VERSION: u32 = 3;
+
+    /// The versions of the format of the mirror status that are supported.
+    pub const SUPPORTED_VERSIONS: &'static [u32] = &[Self::VERSION];
+
+    /// Whether the status is in one of the [supported versions](Self::SUPPORTED_VERSIONS) of
+    /// its format.
+    #[must_use]
+    pub fn is_supported(&self) -> bool {
+        Self::SUPPORTED_VERSIONS.contains(&self.version)
+    }
+
+    /// Parse a mirror status in JSON.
+    ///
+    /// # Errors
+    /// Fails if `data` is not a mirror status or is in an unsupported version of its format.
+    pub fn from_json(data: &[u8]) -> Result<Self, Error> {
+        serde_json::from_slice::<Self>(data)?.check_version()
+    }
+
+    /// Parse a mirror status in JSON regardless of its version, as long as it has all the
+    /// fields of the supported versions. Check [`version`](Self::version) or
+    /// [`is_supported`](Self::is_supported) to tell whether its format may have changed.
+    ///
+    /// # Errors
+    /// Fails if `data` is not a mirror status.
+    pub fn from_json_lenient(data: &[u8]) -> Result<Self, Error> {
+        Ok(serde_json::from_slice(data)?)
+    }
+
+    /// Parse a mirror status in JSON as it is read from `reader`, without reading all of it
+    /// into memory first. Wrap unbuffered readers, such as files, in a
+    /// [`BufReader`](std::io::BufReader).
+    ///
+    /// # Errors
+    /// Fails if `reader` cannot be read, its data is not a mirror status or is in an
+    /// unsupported version of its format.
+    pub fn from_reader(reader: impl Read) -> Result<Self, Error> {
+        serde_json::from_reader::<_, Self>(reader)?.check_version()
+    }
+
+    /// Parse a mirror status in JSON as it is read from `reader`, passing every mirror to
+    /// `visit` as soon as it is parsed instead of collecting them, so only one mirror is in
+    /// memory at a time. The returned status has no mirrors.
+    ///
+    /// The version of the format is only known once the whole status was parsed, so
+    /// `visit` may be called for the mirrors of an unsupported version before that fails.
+    ///
+    /// ```
+    /// use arch_mirrors_rs::Status;
+    ///
+    /// let json = br#"{"cutoff": 3600, "last_check": "2026-01-01T00:00:00Z", "num_checks": 1,
+    ///     "check_frequency": 600, "urls": [], "version": 3}"#;
+    /// let mut count = 0;
+    /// let status = Status::for_each_mirror(&json[..], |_| count += 1).unwrap();
+    /// assert_eq!((count, status.num_checks), (0, 1));
+    /// ```
+    ///
+    /// # Errors
+    /// Fails if `reader` cannot be read, its data is not a mirror status or is in an
+    /// unsupported version of its format.
+    pub fn for_each_mirror(reader: impl Read, visit: impl FnMut(Mirror)) -> Result<Self, Error> {
+        let mut deserializer = serde_json::Deserializer::from_reader(reader);
+        let status = deserializer.deserialize_map(StatusVisitor(visit))?;
+        deserializer.end()?;
+        status.check_version()
+    }
+
+    /// Fail with [`Error::UnsupportedVersion`] unless the status is in a supported version.
+    fn check_version(self) -> Result<Self, Error> {
+        if !self.is_supported() {
+            return Err(Error::UnsupportedVersion(self.version));
+        }
+        Ok(self)
+    }
+
+    /// Retrieve the mirror status at `url` with any [`HttpClient`].
+    ///
+    /// # Errors
+    /// Fails if `url` is invalid, the request fails or the response is not a supported
+    /// mirror status.
+    pub async fn get_with(client: &impl HttpClient, url: &str) -> Result<Self, Error> {
+        let url = url::Url::parse(url).map_err(|err| Error::invalid_url(url, err))?;
+        Self::from_json(&client.get(&url).await?)
+    }
+}
+
+/// Deserializes a [`Status`] without its mirrors, which are passed to the callback instead.
+struct StatusVisitor<F>(F);
+
+impl<'de, F: FnMut(Mirror)> Visitor<'de> for StatusVisitor<F> {
+    type Value = Status;
+
+    fn expecting(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("a mirror status")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(mut self, mut map: A) -> Result<Status, A::Error> {
+        let mut cutoff = None;
+        let mut last_check = None;
+        let mut num_checks = None;
+        let mut check_frequency = None;
+        let mut version = None;
+        let mut extra = Extra::default();
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "cutoff" => cutoff = Some(map.next_value()?),
+                "last_check" => last_check = Some(map.next_value()?),
+                "num_checks" => num_checks = Some(map.next_value()?),
+                "check_frequency" => check_frequency = Some(map.next_value()?),
+                "urls" => map.next_value_seed(MirrorsVisitor(&mut self.0))?,
+                "version" => version = Some(map.next_value()?),
+                _ => {
+                    extra.insert(key, map.next_value()?);
+                }
+            }
+        }
+        Ok(Status {
+            cutoff: cutoff.ok_or_else(|| de::Error::missing_field("cutoff"))?,
+            last_check: last_check.ok_or_else(|| de::Error::missing_field("last_check"))?,
+            num_checks: num_checks.ok_or_else(|| de::Error::missing_field("num_checks"))?,
+            check_frequency: check_frequency
+                .ok_or_else(|| de::Error::missing_field("check_frequency"))?,
+            urls: Vec::new(),
+            version: version.ok_or_else(|| de::Error::missing_field("version"))?,
+            extra,
+        })
+    }
+}
+
+/// Deserializes the mirrors of a [`Status`] one at a time, passing each to the callback.
+struct MirrorsVisitor<'a, F>(&'a mut F);
+
+impl<'de, F: FnMut(Mirror)> DeserializeSeed<'de> for MirrorsVisitor<'_, F> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, F: FnMut(Mirror)> Visitor<'de> for MirrorsVisitor<'_, F> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("a list of mirrors")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        while let Some(mirror) = seq.next_element()? {
+            (self.0)(mirror);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "reqwest")]
+impl Status {
+    /// Retrieve the official Arch Linux mirror status.
+    ///
+    /// # Errors
+    /// Fails if the request fails or the response is not a supported mirror status.
+    pub async fn get() -> Result<Self, Error> {
+        Self::get_from_url(crate::provider::ArchLinux::URL).await
+    }
+
+    /// Retrieve the mirror status at `url` with a default client.
+    ///
+    /// # Errors
+    /// Fails if `url` is invalid, the request fails or the response is not a supported
+    /// mirror status.
+    pub async fn get_from_url(url: &str) -> Result<Self, Error> {
+        Self::get_with_client(&reqwest::Client::new(), url).await
+    }
+
+    /// Retrieve the mirror status at `url` with `client`, so its timeouts, proxies and user
+    /// agent apply.
+    ///
+    /// # Errors
+    /// Fails if `url` is invalid, the request fails or the response is not a supported
+    /// mirror status.
+    pub async fn get_with_client(client: &reqwest::Client, url: &str) -> Result<Self, Error> {
+        Self::get_with(client, url).await
+    }
+}
+
+#[cfg(feature = "blocking")]
+impl Status {
+    /// Retrieve the official Arch Linux mirror status, blocking the current thread.
+    ///
+    /// # Errors
+    /// Fails if the request fails or the response is not a supported mirror status.
+    pub fn get_blocking() -> Result<Self, Error> {
+        Self::get_blocking_from_url(crate::provider::ArchLinux::URL)
+    }
+
+    /// Retrieve the mirror status at `url` with a default client, blocking the current
+    /// thread.
+    ///
+    /// # Errors
+    /// Fails if `url` is invalid, the request fails or the response is not a supported
+    /// mirror status.
+    pub fn get_blocking_from_url(url: &str) -> Result<Self, Error> {
+        Self::get_blocking_with_client(&reqwest::blocking::Client::new(), url)
+    }
+
+    /// Retrieve the mirror status at `url` with `client`, blocking the current thread.
+    ///
+    /// # Errors
+    /// Fails if `url` is invalid, the request fails or the response is not a supported
+    /// mirror status.
+    pub fn get_blocking_with_client(
+        client: &reqwest::blocking::Client,
+        url: &str,
+    ) -> Result<Self, Error> {
+        let url = url::Url::parse(url).map_err(|err| Error::invalid_url(url, err))?;
+        let response = client.get(url).send()?.error_for_status()?;
+        Self::from_json(&response.bytes()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::ArchLinux;
+    use crate::{MirrorProvider, Protocol};
+
+    /// A mirror status in `version` with two mirrors.
+    fn status_json(version: u32) -> String {
+        format!(
+            r#"{{"cutoff": 86400, "last_check": "2024-05-01T10:00:00Z", "num_checks": 24,
+            "check_frequency": 3600, "urls": [{}, {}], "version": {version}}}"#,
+            mirror_json("https://mirror.example.se/archlinux/", "SE"),
+            mirror_json("rsync://mirror.example.de/archlinux/", "DE"),
+        )
+    }
+
+    fn mirror_json(url: &str, country_code: &str) -> String {
+        let protocol = url.split_once(':').unwrap().0;
+        format!(
+            r#"{{"url": "{url}", "protocol": "{protocol}", "last_sync": "2024-05-01T09:00:00Z",
+            "completion_pct": 1.0, "delay": 3600, "duration_avg": 0.5, "duration_stddev": 0.1,
+            "score": 1.6, "active": true, "country": "", "country_code": "{country_code}",
+            "isos": true, "ipv4": true, "ipv6": false, "details": ""}}"#
+        )
+    }
+
+    #[test]
+    fn reject_unsupported_version() {
+        let json = status_json(Status::VERSION + 1);
+        assert!(matches!(
+            Status::from_json(json.as_bytes()),
+            Err(Error::UnsupportedVersion(version)) if version == Status::VERSION + 1
+        ));
+        assert!(matches!(
+            Status::from_reader(json.as_bytes()),
+            Err(Error::UnsupportedVersion(_))
+        ));
+        let provider = ArchLinux::default();
+        assert!(provider.parse(json.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn accept_unsupported_version_leniently() {
+        let json = status_json(Status::VERSION + 1);
+        let status = Status::from_json_lenient(json.as_bytes()).unwrap();
+        assert_eq!(status.version, Status::VERSION + 1);
+        assert!(!status.is_supported());
+        assert_eq!(status.urls.len(), 2);
+        let provider = ArchLinux::default().lenient(true);
+        assert_eq!(provider.parse(json.as_bytes()).unwrap(), status);
+    }
+
+    #[test]
+    fn accept_supported_version() {
+        let json = status_json(Status::VERSION);
+        let status = Status::from_json(json.as_bytes()).unwrap();
+        assert!(status.is_supported());
+        assert_eq!(ArchLinux::default().parse(json.as_bytes()).unwrap(), status);
+    }
+
+    #[test]
+    fn keep_unknown_fields() {
+        let json = status_json(Status::VERSION)
+            .replacen(r#""version""#, r#""mirrors_total": 2, "version""#, 1)
+            .replacen(
+                r#""details": """#,
+                r#""details": "", "tier": {"level": 1}"#,
+                1,
+            );
+        let status = Status::from_json(json.as_bytes()).unwrap();
+        assert_eq!(status.extra["mirrors_total"], 2);
+        assert_eq!(status.urls[0].extra["tier"]["level"], 1);
+        assert!(!status.urls[1].extra.contains_key("tier"));
+
+        let value = serde_json::to_value(&status).unwrap();
+        assert_eq!(value["mirrors_total"], 2);
+        assert_eq!(value["urls"][0]["tier"]["level"], 1);
+        let round_trip = Status::from_json(&serde_json::to_vec(&status).unwrap()).unwrap();
+        assert_eq!(round_trip, status);
+    }
+
+    #[test]
+    fn visit_each_mirror() {
+        let json = status_json(Status::VERSION);
+        let mut mirrors = Vec::new();
+        let status =
+            Status::for_each_mirror(json.as_bytes(), |mirror| mirrors.push(mirror)).unwrap();
+        assert!(status.urls.is_empty());
+        let expected = Status::from_json(json.as_bytes()).unwrap();
+        assert_eq!(mirrors, expected.urls);
+        assert_eq!(
+            Status {
+                urls: mirrors,
+                ..status
+            },
+            expected
+        );
+    }
+
+    #[test]
+    fn visit_mirrors_until_decode_error() {
+        let json =
+            status_json(Status::VERSION).replace(r#""protocol": "rsync""#, r#""protocol": 1"#);
+        assert!(matches!(
+            Status::from_json(json.as_bytes()),
+            Err(Error::Decode(_))
+        ));
+        let mut visited = Vec::new();
+        let result = Status::for_each_mirror(json.as_bytes(), |mirror| {
+            visited.push(mirror.url.to_string());
+        });
+        assert!(matches!(result, Err(Error::Decode(_))));
+        assert_eq!(visited, ["https://mirror.example.se/archlinux/"]);
+    }
+
+    #[test]
+    fn read_ftp_mirrors() {
+        let json = status_json(Status::VERSION).replace(r#""rsync"#, r#""ftp"#);
+        let status = Status::from_json(json.as_bytes()).unwrap();
+        assert_eq!(status.urls[1].protocol, Protocol::Ftp);
+    }
 }
@@ -0,0 +1,60 @@
+//! Repository layouts of the supported distributions and architectures.
+use clap::ValueEnum;
+
+/// The architecture of Arch Linux itself. Other architectures are served by ports such as
+/// Arch Linux ARM, which use a different layout on their mirrors.
+const ARCH_LINUX: &str = "x86_64";
+
+/// The default URL of the mirrorlist of Artix Linux, which has no mirror status.
+pub const ARTIX_URL: &str =
+    "https://gitea.artixlinux.org/packages/artix-mirrorlist/raw/branch/master/mirrorlist";
+
+/// The default URL of the mirrorlist of Arch Linux 32, which has no mirror status.
+pub const ARCHLINUX32_URL: &str = "https://www.archlinux32.org/mirrorlist/all/";
+
+/// The distributions whose mirrors can be selected.
+#[derive(Debug, ValueEnum, Clone, Copy, PartialEq, Eq)]
+pub enum Distro {
+    /// Arch Linux and its ports
+    Arch,
+    /// Manjaro, whose mirrors serve each branch under its own path
+    Manjaro,
+    /// Artix Linux, whose mirrors are only published as a mirrorlist
+    Artix,
+    /// Arch Linux ARM, whose known mirrors are built in
+    #[value(name = "archarm")]
+    ArchArm,
+    /// Arch Linux 32, whose mirrors are only published as a mirrorlist
+    #[value(name = "archlinux32")]
+    ArchLinux32,
+}
+
+impl Distro {
+    /// Whether the mirrors come with the statistics of a mirror status, rather than a
+    /// plain mirrorlist.
+    pub fn has_status(self) -> bool {
+        match self {
+            Self::Arch | Self::Manjaro => true,
+            Self::Artix | Self::ArchArm | Self::ArchLinux32 => false,
+        }
+    }
+
+    /// The repository whose database is downloaded to rate mirrors.
+    pub fn rated_repo(self) -> &'static str {
+        match self {
+            Self::Arch | Self::Manjaro | Self::ArchArm | Self::ArchLinux32 => "extra",
+            Self::Artix => "world",
+        }
+    }
+}
+
+/// The path of a repository relative to the base URL of a mirror, using pacman's `$repo`
+/// and `$arch` variables.
+pub fn repo_path(distro: Distro, arch: &str) -> &'static str {
+    match distro {
+        Distro::Arch if arch == ARCH_LINUX => "$repo/os/$arch",
+        Distro::Arch | Distro::ArchArm | Distro::ArchLinux32 => "$arch/$repo",
+        Distro::Manjaro => "$repo/$arch",
+        Distro::Artix => "$repo/os/$arch",
+    }
+}
@@ -0,0 +1,78 @@
+//! The mirrors of Arch Linux ARM for "--distro archarm". Arch Linux ARM has no mirror
+//! status, so the known mirrors are built in and ranked by rating alone.
+use arch_mirrors_rs::extra::Extra;
+use arch_mirrors_rs::{CountryCode, Mirror, Protocol, Status};
+use jiff::Timestamp;
+use reqwest::Url;
+
+/// The known mirrors of Arch Linux ARM, given by the code of their country and their base
+/// URL. The first mirror picks a nearby mirror by the location of the client.
+const MIRRORS: &[(&str, &str)] = &[
+    ("", "http://mirror.archlinuxarm.org/"),
+    ("AU", "http://au.mirror.archlinuxarm.org/"),
+    ("BR", "http://br.mirror.archlinuxarm.org/"),
+    ("DE", "http://de.mirror.archlinuxarm.org/"),
+    ("DE", "http://eu.mirror.archlinuxarm.org/"),
+    ("DK", "http://dk.mirror.archlinuxarm.org/"),
+    ("GR", "http://gr.mirror.archlinuxarm.org/"),
+    ("HU", "http://hu.mirror.archlinuxarm.org/"),
+    ("JP", "http://jp.mirror.archlinuxarm.org/"),
+    ("SG", "http://sg.mirror.archlinuxarm.org/"),
+    ("TW", "http://tw.mirror.archlinuxarm.org/"),
+    ("TW", "http://tw2.mirror.archlinuxarm.org/"),
+    ("US", "http://ca.us.mirror.archlinuxarm.org/"),
+    ("US", "http://fl.us.mirror.archlinuxarm.org/"),
+    ("US", "http://il.us.mirror.archlinuxarm.org/"),
+    ("US", "http://nj.us.mirror.archlinuxarm.org/"),
+    ("ZA", "http://za.mirror.archlinuxarm.org/"),
+];
+
+/// The known mirrors as a mirror status without any statistics.
+pub fn status(last_check: Timestamp) -> Status {
+    let urls = MIRRORS
+        .iter()
+        .filter_map(|&(code, url)| {
+            let country = CountryCode::from_code(code).map_or("", CountryCode::name);
+            Some(Mirror {
+                url: Url::parse(url).ok()?,
+                protocol: Protocol::Http,
+                last_sync: None,
+                completion_pct: None,
+                delay: None,
+                duration_average: None,
+                duration_stddev: None,
+                score: None,
+                active: true,
+                country: country.to_owned(),
+                country_code: code.to_owned(),
+                isos: false,
+                ipv4: true,
+                ipv6: false,
+                details: String::new(),
+                extra: Extra::default(),
+            })
+        })
+        .collect();
+    Status {
+        cutoff: 0,
+        last_check,
+        num_checks: 0,
+        check_frequency: 0,
+        urls,
+        version: 0,
+        extra: Extra::default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_mirrors() {
+        let status = status(Timestamp::UNIX_EPOCH);
+        assert_eq!(status.urls.len(), MIRRORS.len());
+        assert_eq!(status.urls[1].country, "Australia");
+        assert!(status.urls[0].country.is_empty());
+    }
+}
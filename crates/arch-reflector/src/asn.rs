@@ -0,0 +1,113 @@
+//! Looking up the autonomous systems of mirrors for "--asn".
+use anyhow::{Context, Result};
+use arch_mirrors_rs::Status;
+use futures_util::future::join_all;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// The bulk whois service of Team Cymru, which maps IP addresses to the announcing AS.
+const WHOIS_SERVER: (&str, u16) = ("whois.cymru.com", 43);
+
+/// Parse an autonomous system number with an optional "AS" prefix, e.g. "AS3320".
+pub fn parse_asn(s: &str) -> Result<u32, String> {
+    let s = s.trim();
+    let number = s
+        .strip_prefix("AS")
+        .or_else(|| s.strip_prefix("as"))
+        .unwrap_or(s);
+    number
+        .parse()
+        .map_err(|_| format!("invalid autonomous system number '{s}'"))
+}
+
+/// Look up the autonomous system of the host of every mirror of `status`. Hosts that cannot
+/// be resolved or are not announced by any AS are left out.
+pub async fn lookup(status: &Status, timeout: Duration) -> Result<HashMap<String, u32>> {
+    let mut hosts = status
+        .urls
+        .iter()
+        .filter_map(|mirror| mirror.url.host_str())
+        .collect::<Vec<_>>();
+    hosts.sort_unstable();
+    hosts.dedup();
+    let resolved = join_all(hosts.into_iter().map(|host| async move {
+        match tokio::net::lookup_host((host, 0)).await {
+            Ok(mut addresses) => Some((host.to_owned(), addresses.next()?.ip())),
+            Err(err) => {
+                tracing::warn!(phase = "asn", "failed to resolve {host}: {err}");
+                None
+            }
+        }
+    }))
+    .await;
+    let addresses = resolved.into_iter().flatten().collect::<HashMap<_, _>>();
+    if addresses.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let mut query = String::from("begin\nnoheader\n");
+    for address in addresses.values() {
+        query.push_str(&address.to_string());
+        query.push('\n');
+    }
+    query.push_str("end\n");
+    let response = tokio::time::timeout(timeout, whois(&query))
+        .await
+        .context("timed out")
+        .and_then(|response| response)
+        .with_context(|| format!("failed to query {}", WHOIS_SERVER.0))?;
+
+    let asns = parse_response(&response);
+    Ok(addresses
+        .into_iter()
+        .filter_map(|(host, address)| Some((host, *asns.get(&address)?)))
+        .collect())
+}
+
+async fn whois(query: &str) -> Result<String> {
+    let mut stream = TcpStream::connect(WHOIS_SERVER).await?;
+    stream.write_all(query.as_bytes()).await?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await?;
+    Ok(response)
+}
+
+/// Parse the lines of the form "AS | IP | AS name" of a bulk whois response. Addresses that
+/// are not announced have "NA" as their AS and are skipped.
+fn parse_response(response: &str) -> HashMap<IpAddr, u32> {
+    response
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split('|').map(str::trim);
+            let asn = fields.next()?.parse().ok()?;
+            let address = fields.next()?.parse().ok()?;
+            Some((address, asn))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_bulk_response() {
+        let response = "Bulk mode; whois.cymru.com [2026-10-16 12:00:00 +0000]\n\
+                        3320    | 193.99.144.80    | DTAG Internet service provider operations, DE\n\
+                        NA      | 10.0.0.1         | NA\n\
+                        680     | 2001:638:d:c102::140 | DFN Verein zur Foerderung eines Deutschen Forschungsnetzes e.V., DE\n";
+        let asns = parse_response(response);
+        assert_eq!(asns.len(), 2);
+        assert_eq!(asns[&"193.99.144.80".parse::<IpAddr>().unwrap()], 3320);
+        assert_eq!(
+            asns[&"2001:638:d:c102::140".parse::<IpAddr>().unwrap()],
+            680
+        );
+        assert_eq!(parse_asn("AS3320"), Ok(3320));
+        assert_eq!(parse_asn("680"), Ok(680));
+        assert!(parse_asn("DTAG").is_err());
+    }
+}
@@ -0,0 +1,315 @@
+//! Benchmarking mirrors for "reflector bench" by downloading actual packages. Unlike
+//! rating, which extrapolates from the small database, this shows the throughput that
+//! mirrors sustain over larger transfers, including mirrors that throttle after a few
+//! megabytes.
+use crate::{BenchOptions, RunOptions};
+use anyhow::{Context, Result};
+use arch_mirrors_rs::Mirror;
+use flate2::read::GzDecoder;
+use futures_util::StreamExt;
+use reqwest::Url;
+use std::fmt::{self, Display, Formatter};
+use std::io::{Read, Write};
+use std::time::{Duration, Instant};
+
+/// A transfer is reported as throttled when its second half is slower than this fraction
+/// of its first half.
+const THROTTLE_RATIO: f64 = 0.5;
+
+/// A package listed in the database of the extra repository.
+#[derive(Debug, PartialEq)]
+struct Package {
+    file_name: String,
+    /// The size of the package file in bytes.
+    size: u64,
+}
+
+/// Part of the transfers from a mirror.
+#[derive(Debug, Default, Clone, Copy)]
+struct Part {
+    bytes: u64,
+    elapsed: Duration,
+}
+
+impl Part {
+    /// The transfer rate in bytes per second.
+    #[allow(clippy::cast_precision_loss)]
+    fn rate(self) -> f64 {
+        self.bytes as f64 / self.elapsed.max(Duration::from_micros(1)).as_secs_f64()
+    }
+}
+
+/// The packages transferred from a mirror, split at the middle of each package.
+#[derive(Debug, Default)]
+struct Transfer {
+    first_half: Part,
+    second_half: Part,
+}
+
+impl Transfer {
+    fn add(&mut self, first_half: Part, second_half: Part) {
+        self.first_half.bytes += first_half.bytes;
+        self.first_half.elapsed += first_half.elapsed;
+        self.second_half.bytes += second_half.bytes;
+        self.second_half.elapsed += second_half.elapsed;
+    }
+
+    fn total(&self) -> Part {
+        Part {
+            bytes: self.first_half.bytes + self.second_half.bytes,
+            elapsed: self.first_half.elapsed + self.second_half.elapsed,
+        }
+    }
+}
+
+impl Display for Transfer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let total = self.total();
+        write!(
+            f,
+            "{:.2} KiB/s  ({} bytes in {:.2?}, first half: {:.2} KiB/s, second half: {:.2} KiB/s)",
+            total.rate() / 1024.0,
+            total.bytes,
+            total.elapsed,
+            self.first_half.rate() / 1024.0,
+            self.second_half.rate() / 1024.0,
+        )?;
+        if self.second_half.rate() < self.first_half.rate() * THROTTLE_RATIO {
+            write!(f, "  throttled")?;
+        }
+        Ok(())
+    }
+}
+
+/// Download the packages closest to the requested size from each mirror in turn and print
+/// the throughput of each mirror.
+pub async fn bench(
+    bench_options: &BenchOptions,
+    run_options: &RunOptions,
+    http_client: &reqwest::Client,
+    mirrors: &[Mirror],
+    mut out: impl Write,
+) -> Result<()> {
+    let timeout = Duration::from_secs(bench_options.timeout);
+    let packages = fetch_packages(run_options, http_client, mirrors, timeout).await?;
+    let packages = pick_packages(packages, bench_options.size, bench_options.packages);
+    if packages.is_empty() {
+        anyhow::bail!("the database of the extra repository lists no packages");
+    }
+    let names = packages
+        .iter()
+        .map(|package| package.file_name.as_str())
+        .collect::<Vec<_>>();
+    writeln!(
+        out,
+        "Downloading {} ({} bytes) from {} mirrors",
+        names.join(", "),
+        packages.iter().map(|package| package.size).sum::<u64>(),
+        mirrors.len()
+    )?;
+
+    // One mirror at a time, so the downloads do not compete for bandwidth.
+    for mirror in mirrors {
+        let mut transfer = Transfer::default();
+        let result: Result<()> = async {
+            let repo_url = mirror.repo_url_with_layout(
+                run_options.repo_path(),
+                run_options.rated_repo(),
+                &run_options.arch,
+            )?;
+            for package in &packages {
+                download(http_client, &repo_url, package, timeout, &mut transfer).await?;
+            }
+            Ok(())
+        }
+        .await;
+        match result {
+            Ok(()) => writeln!(out, "{}  {transfer}", mirror.url)?,
+            Err(err) => writeln!(out, "{}  error: {err:#}", mirror.url)?,
+        }
+        out.flush()?;
+    }
+    Ok(())
+}
+
+/// Retrieve the database of the extra repository from the first mirror that serves it and
+/// list its packages.
+async fn fetch_packages(
+    run_options: &RunOptions,
+    http_client: &reqwest::Client,
+    mirrors: &[Mirror],
+    timeout: Duration,
+) -> Result<Vec<Package>> {
+    let mut last_err = anyhow::anyhow!("no HTTP(S) mirrors are left to benchmark");
+    for mirror in mirrors {
+        let db_url = mirror.db_url_with_layout(
+            run_options.repo_path(),
+            run_options.rated_repo(),
+            &run_options.arch,
+        )?;
+        let response = async {
+            http_client
+                .get(db_url.clone())
+                .timeout(timeout)
+                .send()
+                .await?
+                .error_for_status()?
+                .bytes()
+                .await
+        };
+        match response.await {
+            Ok(db) => {
+                return parse_packages(&db).with_context(|| format!("invalid database {db_url}"));
+            }
+            Err(err) => {
+                tracing::warn!(mirror_url = %mirror.url, "error while retrieving the database: {err}");
+                last_err = err.into();
+            }
+        }
+    }
+    Err(last_err.context("failed to retrieve the database of the extra repository"))
+}
+
+/// Download a package and add it to `transfer`.
+async fn download(
+    http_client: &reqwest::Client,
+    repo_url: &Url,
+    package: &Package,
+    timeout: Duration,
+    transfer: &mut Transfer,
+) -> Result<()> {
+    let url = repo_url.join(&package.file_name)?;
+    let response = http_client
+        .get(url)
+        .timeout(timeout)
+        .send()
+        .await?
+        .error_for_status()?;
+    let half = response.content_length().unwrap_or(package.size) / 2;
+    // Only time the transfer of the body, like rating does.
+    let start = Instant::now();
+    let mut stream = response.bytes_stream();
+    let mut bytes = 0;
+    let mut halfway = None;
+    while let Some(chunk) = stream.next().await {
+        bytes += chunk?.len() as u64;
+        if halfway.is_none() && bytes >= half {
+            halfway = Some(Part {
+                bytes,
+                elapsed: start.elapsed(),
+            });
+        }
+    }
+    let elapsed = start.elapsed();
+    let first_half = halfway.unwrap_or(Part { bytes, elapsed });
+    let second_half = Part {
+        bytes: bytes - first_half.bytes,
+        elapsed: elapsed.saturating_sub(first_half.elapsed),
+    };
+    transfer.add(first_half, second_half);
+    Ok(())
+}
+
+/// List the packages in a repository database, a gzip compressed tar archive with a
+/// "desc" file for each package.
+fn parse_packages(db: &[u8]) -> Result<Vec<Package>> {
+    let mut archive = tar::Archive::new(GzDecoder::new(db));
+    let mut packages = Vec::new();
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !entry.path()?.ends_with("desc") {
+            continue;
+        }
+        let mut desc = String::new();
+        entry.read_to_string(&mut desc)?;
+        packages.extend(parse_desc(&desc));
+    }
+    Ok(packages)
+}
+
+/// Read the file name and size of a package from its "desc" file, in which each field
+/// is a "%NAME%" line followed by its values.
+fn parse_desc(desc: &str) -> Option<Package> {
+    let mut file_name = None;
+    let mut size = None;
+    let mut lines = desc.lines();
+    while let Some(line) = lines.next() {
+        match line {
+            "%FILENAME%" => file_name = lines.next(),
+            "%CSIZE%" => size = lines.next().and_then(|size| size.parse().ok()),
+            _ => {}
+        }
+    }
+    Some(Package {
+        file_name: file_name?.to_owned(),
+        size: size?,
+    })
+}
+
+/// Pick the `count` packages closest to `size`.
+fn pick_packages(mut packages: Vec<Package>, size: u64, count: u32) -> Vec<Package> {
+    packages.sort_by_key(|package| package.size.abs_diff(size));
+    packages.truncate(usize::try_from(count).unwrap_or(usize::MAX));
+    packages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+
+    #[test]
+    fn pick_packages_from_database() {
+        let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::fast()));
+        for (name, size) in [("small", 1000), ("medium", 20_000), ("large", 900_000)] {
+            let desc = format!(
+                "%FILENAME%\n{name}-1.0-1-x86_64.pkg.tar.zst\n\n%NAME%\n{name}\n\n%CSIZE%\n{size}\n"
+            );
+            let mut header = tar::Header::new_gnu();
+            header.set_size(desc.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, format!("{name}-1.0-1/desc"), desc.as_bytes())
+                .unwrap();
+        }
+        let db = builder.into_inner().unwrap().finish().unwrap();
+
+        let packages = parse_packages(&db).unwrap();
+        assert_eq!(packages.len(), 3);
+        assert_eq!(
+            pick_packages(packages, 30_000, 2),
+            vec![
+                Package {
+                    file_name: "medium-1.0-1-x86_64.pkg.tar.zst".to_owned(),
+                    size: 20_000
+                },
+                Package {
+                    file_name: "small-1.0-1-x86_64.pkg.tar.zst".to_owned(),
+                    size: 1000
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn report_throttling() {
+        let second = Duration::from_secs(1);
+        let mut transfer = Transfer::default();
+        transfer.add(
+            Part {
+                bytes: 4096,
+                elapsed: second,
+            },
+            Part {
+                bytes: 1024,
+                elapsed: second,
+            },
+        );
+        assert_eq!(
+            transfer.to_string(),
+            "2.50 KiB/s  (5120 bytes in 2.00s, first half: 4.00 KiB/s, second half: 1.00 KiB/s)  throttled"
+        );
+    }
+}
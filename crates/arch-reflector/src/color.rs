@@ -0,0 +1,86 @@
+//! Colorizing the tables printed to a terminal for "--color".
+use clap::ValueEnum;
+use std::io::{self, IsTerminal};
+
+/// When to colorize output.
+#[derive(Debug, ValueEnum, Clone, Copy, PartialEq, Eq)]
+#[allow(
+    clippy::doc_markdown,
+    reason = "This is used to generate the user facing help."
+)]
+pub enum ColorChoice {
+    /// when STDOUT is a terminal and NO_COLOR is not set
+    Auto,
+    /// always
+    Always,
+    /// never
+    Never,
+}
+
+impl ColorChoice {
+    /// Whether to colorize output written to STDOUT.
+    pub fn enabled(self) -> bool {
+        match self {
+            Self::Auto => {
+                // See https://no-color.org: any non-empty value disables colors by default.
+                std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+                    && io::stdout().is_terminal()
+            }
+            Self::Always => true,
+            Self::Never => false,
+        }
+    }
+}
+
+/// The styles used in tables.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Style {
+    Header,
+    Good,
+    Fair,
+    Bad,
+}
+
+impl Style {
+    fn escape_code(self) -> &'static str {
+        match self {
+            Self::Header => "\x1b[1m",
+            Self::Good => "\x1b[32m",
+            Self::Fair => "\x1b[33m",
+            Self::Bad => "\x1b[31m",
+        }
+    }
+
+    /// The style of a score of the mirror status, where lower is better.
+    pub fn for_score(score: f64) -> Self {
+        if score < 2.0 {
+            Self::Good
+        } else if score < 10.0 {
+            Self::Fair
+        } else {
+            Self::Bad
+        }
+    }
+
+    /// The style of the time since the last synchronization of a mirror, so stale mirrors
+    /// stand out.
+    pub fn for_age(seconds: i64) -> Self {
+        const HOUR: i64 = 60 * 60;
+        if seconds < 6 * HOUR {
+            Self::Good
+        } else if seconds < 24 * HOUR {
+            Self::Fair
+        } else {
+            Self::Bad
+        }
+    }
+}
+
+/// Wrap `text` in the escape codes of `style` if `enabled`. Padding should be applied
+/// before, since the escape codes do not take up any columns.
+pub fn paint(enabled: bool, text: &str, style: Option<Style>) -> String {
+    match style {
+        Some(style) if enabled => format!("{}{text}\x1b[0m", style.escape_code()),
+        _ => text.to_owned(),
+    }
+}
@@ -0,0 +1,261 @@
+//! Reading default options from configuration files.
+use clap::parser::ValueSource;
+use clap::{ArgAction, ArgMatches, Command, value_parser};
+use directories::BaseDirs;
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+/// The system-wide configuration file. This is not the reflector.conf read by the systemd
+/// service, which saves the mirrorlist and must not apply to interactive runs.
+const SYSTEM_CONFIG: &str = "/etc/xdg/reflector/defaults.conf";
+
+/// The configuration files in order of increasing precedence.
+fn config_files() -> Vec<PathBuf> {
+    let mut files = vec![PathBuf::from(SYSTEM_CONFIG)];
+    if let Some(base_dirs) = BaseDirs::new() {
+        files.push(
+            base_dirs
+                .config_dir()
+                .join("reflector")
+                .join("reflector.conf"),
+        );
+    }
+    files
+}
+
+/// Let the flags of `command` take an optional "=true" or "=false", so that a flag set in
+/// a configuration file can be turned off on the command line, e.g. "--ipv4=false".
+pub fn command(command: Command) -> Command {
+    command.args_override_self(true).mut_args(|arg| {
+        if matches!(arg.get_action(), ArgAction::SetTrue) {
+            arg.action(ArgAction::Set)
+                .num_args(0..=1)
+                .require_equals(true)
+                .value_parser(value_parser!(bool))
+                .default_value("false")
+                .default_missing_value("true")
+                .value_name("bool")
+                .hide_possible_values(true)
+        } else {
+            arg
+        }
+    })
+}
+
+/// Insert the options from the configuration files before the command line arguments.
+///
+/// The configuration files use the same format as argument files: each line holds one
+/// option along with its values, and empty lines and lines starting with '#' are ignored.
+/// The files are taken from "--config" if given, and none are read with "--no-config".
+pub fn merge_args(command: &Command, args: Vec<OsString>) -> io::Result<Vec<OsString>> {
+    // Invalid arguments are reported when the merged arguments are parsed.
+    let Ok(matches) = command.clone().try_get_matches_from(&args) else {
+        return Ok(args);
+    };
+    let files = if matches.get_flag("no_config") {
+        Vec::new()
+    } else if let Some(path) = matches.get_one::<PathBuf>("config") {
+        vec![(path.clone(), true)]
+    } else {
+        config_files()
+            .into_iter()
+            .map(|path| (path, false))
+            .collect()
+    };
+
+    let mut configs = Vec::new();
+    for (path, required) in files {
+        match fs::read_to_string(&path) {
+            Ok(content) => configs.push(content),
+            // Sandboxed services, e.g. with systemd's ProtectHome, cannot access the
+            // user's configuration at all.
+            Err(err)
+                if !required
+                    && matches!(
+                        err.kind(),
+                        io::ErrorKind::NotFound | io::ErrorKind::PermissionDenied
+                    ) => {}
+            Err(err) => {
+                return Err(io::Error::new(
+                    err.kind(),
+                    format!("failed to read {}: {err}", path.display()),
+                ));
+            }
+        }
+    }
+    Ok(merge(command, &matches, args, &configs))
+}
+
+/// Insert the lines of the configurations, given in order of increasing precedence, before
+/// the arguments matched as `matches`.
+///
+/// An option given on the command line replaces the lines for the same option in the
+/// configurations, and drops the lines for options that conflict with it. Likewise, a
+/// configuration replaces the lines of the configurations before it.
+fn merge(
+    command: &Command,
+    matches: &ArgMatches,
+    args: Vec<OsString>,
+    configs: &[String],
+) -> Vec<OsString> {
+    let mut given = matches
+        .ids()
+        .filter(|id| matches.value_source(id.as_str()) == Some(ValueSource::CommandLine))
+        .map(|id| id.as_str().to_owned())
+        .collect::<HashSet<_>>();
+
+    let mut lines = Vec::new();
+    for content in configs.iter().rev() {
+        let mut file_ids = HashSet::new();
+        let mut file_lines = Vec::new();
+        for line in content.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let id = line
+                .split_whitespace()
+                .next()
+                .and_then(|option| find_arg_id(command, option));
+            if let Some(id) = id {
+                if is_overridden(command, &id, &given) {
+                    continue;
+                }
+                file_ids.insert(id);
+            }
+            file_lines.push(line.to_owned());
+        }
+        given.extend(file_ids);
+        lines.splice(0..0, file_lines);
+    }
+
+    let mut args = args.into_iter();
+    let mut merged = args.next().into_iter().collect::<Vec<_>>();
+    merged.extend(
+        lines
+            .iter()
+            .flat_map(|line| line.split_whitespace())
+            .map(OsString::from),
+    );
+    merged.extend(args);
+    merged
+}
+
+/// Whether the argument `id` is given or conflicts with one of the `given` arguments.
+fn is_overridden(command: &Command, id: &str, given: &HashSet<String>) -> bool {
+    let conflicts = |id: &str| {
+        command
+            .get_arguments()
+            .find(|arg| arg.get_id() == id)
+            .map(|arg| command.get_arg_conflicts_with(arg))
+            .unwrap_or_default()
+    };
+    given.contains(id)
+        || conflicts(id)
+            .iter()
+            .any(|arg| given.contains(arg.get_id().as_str()))
+        || given
+            .iter()
+            .any(|other| conflicts(other).iter().any(|arg| arg.get_id() == id))
+}
+
+/// Find the ID of the argument with the given long ("--name" or "--name=value") or short
+/// ("-n") option name.
+fn find_arg_id(command: &Command, option: &str) -> Option<String> {
+    let arg = if let Some(long) = option.strip_prefix("--") {
+        let long = long.split_once('=').map_or(long, |(long, _)| long);
+        command
+            .get_arguments()
+            .find(|arg| arg.get_long() == Some(long))
+    } else {
+        let short = option.strip_prefix('-')?.chars().next()?;
+        command
+            .get_arguments()
+            .find(|arg| arg.get_short() == Some(short))
+    }?;
+    Some(arg.get_id().as_str().to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Arg;
+
+    fn test_command() -> Command {
+        command(
+            Command::new("reflector")
+                .arg(
+                    Arg::new("no_config")
+                        .long("no-config")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(Arg::new("config").long("config"))
+                .arg(Arg::new("save").long("save"))
+                .arg(Arg::new("age").long("age").short('a'))
+                .arg(Arg::new("ipv4").long("ipv4").action(ArgAction::SetTrue))
+                .arg(
+                    Arg::new("stream")
+                        .long("stream")
+                        .action(ArgAction::SetTrue)
+                        .conflicts_with("save"),
+                ),
+        )
+    }
+
+    fn merged(args: &[&str], configs: &[&str]) -> ArgMatches {
+        let command = test_command();
+        let args = args.iter().map(OsString::from).collect::<Vec<_>>();
+        let matches = command.clone().try_get_matches_from(&args).unwrap();
+        let configs = configs.iter().map(ToString::to_string).collect::<Vec<_>>();
+        command
+            .try_get_matches_from(merge(&test_command(), &matches, args, &configs))
+            .unwrap()
+    }
+
+    #[test]
+    fn cli_command() {
+        command(<crate::Cli as clap::CommandFactory>::command()).debug_assert();
+    }
+
+    #[test]
+    fn config_values_are_defaults() {
+        let matches = merged(&["reflector"], &["# Comment\n\n--age 12\n--ipv4"]);
+        assert_eq!(matches.get_one::<String>("age").unwrap(), "12");
+        assert!(matches.get_flag("ipv4"));
+    }
+
+    #[test]
+    fn command_line_overrides_config() {
+        let matches = merged(&["reflector", "-a", "3"], &["--age 12"]);
+        assert_eq!(matches.get_one::<String>("age").unwrap(), "3");
+    }
+
+    #[test]
+    fn command_line_turns_off_config_flags() {
+        let matches = merged(&["reflector", "--ipv4=false"], &["--ipv4"]);
+        assert!(!matches.get_flag("ipv4"));
+        let matches = merged(&["reflector", "--ipv4"], &[]);
+        assert!(matches.get_flag("ipv4"));
+    }
+
+    #[test]
+    fn user_config_overrides_system_config() {
+        let matches = merged(&["reflector"], &["--age 12\n--save a", "--age 6"]);
+        assert_eq!(matches.get_one::<String>("age").unwrap(), "6");
+        assert_eq!(matches.get_one::<String>("save").unwrap(), "a");
+    }
+
+    #[test]
+    fn command_line_drops_conflicting_config() {
+        let matches = merged(
+            &["reflector", "--stream"],
+            &["--save /etc/pacman.d/mirrorlist"],
+        );
+        assert!(matches.get_flag("stream"));
+        assert_eq!(matches.get_one::<String>("save"), None);
+        let matches = merged(&["reflector", "--save", "a"], &["--stream"]);
+        assert!(!matches.get_flag("stream"));
+    }
+}
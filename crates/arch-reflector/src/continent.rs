@@ -0,0 +1,64 @@
+//! The continents accepted by "--continent".
+use arch_mirrors_rs::country;
+use clap::ValueEnum;
+
+/// The continents with their two-letter codes. Countries that span two continents are
+/// assigned to one of them as in common geographical databases, e.g. Russia is in Europe
+/// and Turkey is in Asia.
+#[derive(Debug, ValueEnum, Clone, Copy, PartialEq, Eq)]
+pub enum Continent {
+    /// Africa
+    #[value(name = "AF", alias = "africa")]
+    Africa,
+    /// Antarctica
+    #[value(name = "AN", alias = "antarctica")]
+    Antarctica,
+    /// Asia
+    #[value(name = "AS", alias = "asia")]
+    Asia,
+    /// Europe
+    #[value(name = "EU", alias = "europe")]
+    Europe,
+    /// North America, including Central America and the Caribbean
+    #[value(name = "NA", alias = "north-america")]
+    NorthAmerica,
+    /// Oceania
+    #[value(name = "OC", alias = "oceania")]
+    Oceania,
+    /// South America
+    #[value(name = "SA", alias = "south-america")]
+    SouthAmerica,
+}
+
+impl From<Continent> for country::Continent {
+    fn from(continent: Continent) -> Self {
+        match continent {
+            Continent::Africa => Self::Africa,
+            Continent::Antarctica => Self::Antarctica,
+            Continent::Asia => Self::Asia,
+            Continent::Europe => Self::Europe,
+            Continent::NorthAmerica => Self::NorthAmerica,
+            Continent::Oceania => Self::Oceania,
+            Continent::SouthAmerica => Self::SouthAmerica,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arch_mirrors_rs::CountryCode;
+
+    #[test]
+    fn every_country_is_on_one_continent() {
+        let continents = Continent::value_variants()
+            .iter()
+            .map(|&continent| country::Continent::from(continent))
+            .collect::<Vec<_>>();
+        assert!(CountryCode::all().all(|code| continents.contains(&code.continent())));
+        assert_eq!(
+            country::Continent::from(Continent::SouthAmerica),
+            country::Continent::SouthAmerica
+        );
+    }
+}
@@ -0,0 +1,195 @@
+//! Normalizing and validating the countries given to "--country", so typos fail early
+//! with a suggestion instead of silently producing an empty mirrorlist.
+use arch_mirrors_rs::{CountryCode, Status};
+
+/// Replace the countries given to "--country" by their code, so they match the mirrors
+/// whichever name the mirror status uses for them. The "*" glob and unknown countries are
+/// left as they are.
+pub fn normalize(countries: &[String]) -> Vec<String> {
+    countries
+        .iter()
+        .map(|country| match CountryCode::lookup(country) {
+            Some(code) => code.to_string(),
+            None => country.clone(),
+        })
+        .collect()
+}
+
+/// Check that each country given to "--country" is known by name or code, ignoring case.
+/// Countries are known if they are a [`CountryCode`] or have mirrors in the mirror status.
+pub fn validate(countries: &[String], status: &Status) -> anyhow::Result<()> {
+    for country in countries {
+        let country = country.trim();
+        if country == "*" || CountryCode::lookup(country).is_some() {
+            continue;
+        }
+        let known = status.urls.iter().any(|mirror| {
+            country.eq_ignore_ascii_case(&mirror.country)
+                || country.eq_ignore_ascii_case(&mirror.country_code)
+        });
+        if known {
+            continue;
+        }
+        match suggest(country, status) {
+            Some((name, code)) => {
+                anyhow::bail!("unknown country '{country}', did you mean '{name} ({code})'?")
+            }
+            None => anyhow::bail!(
+                "unknown country '{country}', see --list-countries for the countries with mirrors"
+            ),
+        }
+    }
+    Ok(())
+}
+
+/// Find the country whose name or code is closest to `country`, if any is close enough to
+/// be a typo.
+fn suggest<'a>(country: &str, status: &'a Status) -> Option<(&'a str, &'a str)> {
+    let country = country.to_lowercase();
+    let max_distance = (country.chars().count() / 3).max(1);
+    let mirror_countries = status
+        .urls
+        .iter()
+        .filter(|mirror| !mirror.country_code.is_empty())
+        .map(|mirror| (mirror.country.as_str(), mirror.country_code.as_str()));
+    CountryCode::all()
+        .map(|code| (code.name(), code.as_str()))
+        .chain(mirror_countries)
+        .map(|(name, code)| {
+            let distance = edit_distance(&country, &name.to_lowercase())
+                .min(edit_distance(&country, &code.to_lowercase()));
+            (distance, name, code)
+        })
+        .filter(|(distance, ..)| *distance <= max_distance)
+        .min_by_key(|(distance, ..)| *distance)
+        .map(|(_, name, code)| (name, code))
+}
+
+/// The number of insertions, deletions, substitutions and transpositions of adjacent
+/// characters needed to turn `a` into `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a = a.chars().collect::<Vec<_>>();
+    let b = b.chars().collect::<Vec<_>>();
+    // Each row holds the distances from a prefix of `a` to all prefixes of `b`.
+    let mut before_previous: Vec<usize> = Vec::new();
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+    for i in 1..=a.len() {
+        let mut current = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            current[j] = (previous[j] + 1)
+                .min(current[j - 1] + 1)
+                .min(previous[j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                current[j] = current[j].min(before_previous[j - 2] + 1);
+            }
+        }
+        before_previous = std::mem::replace(&mut previous, current);
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CountryStats;
+    use arch_mirrors_rs::Protocol;
+    use reflector_lib::schema;
+
+    #[test]
+    fn suggest_countries() {
+        assert_eq!(edit_distance("gemrany", "germany"), 1);
+        assert_eq!(edit_distance("france", "frankreich"), 5);
+        assert_eq!(edit_distance("", "de"), 2);
+
+        let status: Status = serde_json::from_str(
+            r#"{
+                "cutoff": 3600,
+                "last_check": "2026-10-16T00:00:00Z",
+                "num_checks": 1,
+                "check_frequency": 1,
+                "version": 3,
+                "urls": [{
+                    "url": "https://mirror.example.de/archlinux/",
+                    "protocol": "https",
+                    "last_sync": null,
+                    "completion_pct": null,
+                    "delay": null,
+                    "duration_average": null,
+                    "duration_stddev": null,
+                    "score": null,
+                    "active": true,
+                    "country": "Germany",
+                    "country_code": "DE",
+                    "isos": false,
+                    "ipv4": true,
+                    "ipv6": false,
+                    "details": ""
+                }]
+            }"#,
+        )
+        .unwrap();
+        assert!(validate(&["germany".to_owned(), "DE".to_owned()], &status).is_ok());
+        // Countries without mirrors are still known.
+        assert!(validate(&["Bhutan".to_owned(), "bt".to_owned()], &status).is_ok());
+        assert_eq!(
+            validate(&["Buthan".to_owned()], &status)
+                .unwrap_err()
+                .to_string(),
+            "unknown country 'Buthan', did you mean 'Bhutan (BT)'?"
+        );
+        assert_eq!(
+            validate(&["Gemrany".to_owned()], &status)
+                .unwrap_err()
+                .to_string(),
+            "unknown country 'Gemrany', did you mean 'Germany (DE)'?"
+        );
+        assert_eq!(
+            validate(&["Atlantis".to_owned()], &status)
+                .unwrap_err()
+                .to_string(),
+            "unknown country 'Atlantis', see --list-countries for the countries with mirrors"
+        );
+    }
+
+    #[test]
+    fn normalize_countries() {
+        assert!(CountryCode::all().map(CountryCode::as_str).is_sorted());
+        let countries = ["germany", " fr ", "*", "Atlantis", "Czechia"].map(str::to_owned);
+        assert_eq!(
+            normalize(&countries),
+            vec!["DE", "FR", "*", "Atlantis", "CZ"]
+        );
+    }
+
+    #[test]
+    fn countries_schema() {
+        let stats = [CountryStats {
+            country: "Sweden",
+            code: "SE",
+            count: 2,
+            protocols: [Protocol::Http, Protocol::Https].into_iter().collect(),
+            median_score: Some(1.5),
+            median_delay: None,
+        }];
+        let value = serde_json::to_value(schema::Document {
+            key: "countries",
+            value: &stats,
+        })
+        .unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "schema_version": 1,
+                "countries": [{
+                    "country": "Sweden",
+                    "code": "SE",
+                    "count": 2,
+                    "protocols": ["http", "https"],
+                    "median_score": 1.5,
+                    "median_delay": null,
+                }],
+            })
+        );
+    }
+}
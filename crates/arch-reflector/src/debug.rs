@@ -0,0 +1,99 @@
+//! Tracing individual mirrors through the pipeline for "--debug-mirror".
+use crate::rate::Ratings;
+use arch_mirrors_rs::{Mirror, Status};
+use jiff::Timestamp;
+use reflector_lib::filter::{self, Filters, Rejection};
+use std::io::{self, Write};
+
+/// The mirrors whose URL contains the pattern given to "--debug-mirror".
+pub struct MirrorTrace {
+    mirrors: Vec<Mirror>,
+}
+
+impl MirrorTrace {
+    /// Select the mirrors to trace from the unfiltered status.
+    pub fn new(pattern: &str, status: &Status) -> Self {
+        let mirrors = status
+            .urls
+            .iter()
+            .filter(|mirror| mirror.url.as_str().contains(pattern))
+            .cloned()
+            .collect::<Vec<_>>();
+        if mirrors.is_empty() {
+            tracing::warn!("no mirror in the status matches '{pattern}'");
+        }
+        Self { mirrors }
+    }
+
+    /// Report the raw status fields of each traced mirror and the decision of every filter.
+    pub fn report_filters(
+        &self,
+        filters: &Filters,
+        now: Timestamp,
+        statistics: bool,
+        mut out: impl Write,
+    ) -> anyhow::Result<()> {
+        for mirror in &self.mirrors {
+            writeln!(out, "[debug-mirror] {}", mirror.url)?;
+            writeln!(out, "[debug-mirror] status fields:")?;
+            serde_json::to_writer_pretty(&mut out, mirror)?;
+            writeln!(out)?;
+            let decisions = filter::evaluate(filters, mirror, now, statistics);
+            if decisions.is_empty() {
+                writeln!(out, "[debug-mirror] no filters apply")?;
+            }
+            for (criterion, rejection) in decisions {
+                match rejection {
+                    Some(rejection) => {
+                        writeln!(
+                            out,
+                            "[debug-mirror] filter {criterion}: rejected, {rejection}"
+                        )?;
+                    }
+                    None => writeln!(out, "[debug-mirror] filter {criterion}: passed")?,
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Report the rating and the final position of each traced mirror.
+    pub fn report_result(
+        &self,
+        status: &Status,
+        rejected: &[(Mirror, Rejection)],
+        ratings: &Ratings,
+        mut out: impl Write,
+    ) -> io::Result<()> {
+        for mirror in &self.mirrors {
+            writeln!(out, "[debug-mirror] {}", mirror.url)?;
+            if let Some(rating) = ratings.get(&mirror.url) {
+                writeln!(
+                    out,
+                    "[debug-mirror] rating: {:.2} KiB/s, {} bytes in {:?}, time to first byte {:?}",
+                    rating.rate / 1024.0,
+                    rating.bytes,
+                    rating.elapsed,
+                    rating.ttfb,
+                )?;
+            } else if let Some(err) = ratings.error(&mirror.url) {
+                writeln!(out, "[debug-mirror] rating: failed, {err}")?;
+            } else {
+                writeln!(out, "[debug-mirror] rating: not rated")?;
+            }
+            let rank = status.urls.iter().position(|m| m.url == mirror.url);
+            let rejection = rejected
+                .iter()
+                .find(|(m, _)| m.url == mirror.url)
+                .map(|(_, rejection)| rejection);
+            match (rank, rejection) {
+                (Some(rank), _) => writeln!(out, "[debug-mirror] result: rank {}", rank + 1)?,
+                (None, Some(rejection)) => {
+                    writeln!(out, "[debug-mirror] result: excluded, {rejection}")?;
+                }
+                (None, None) => writeln!(out, "[debug-mirror] result: excluded")?,
+            }
+        }
+        Ok(())
+    }
+}
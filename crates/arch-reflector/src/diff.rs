@@ -0,0 +1,106 @@
+//! Comparing a generated mirrorlist with an existing one for "--diff".
+use crate::save::server_urls;
+use std::collections::HashSet;
+use std::io::{self, Write};
+
+/// Print the servers that were added to, removed from or moved within the mirrorlist
+/// `old` to get the mirrorlist `new`, followed by a summary.
+///
+/// A server counts as moved if it is in both lists but its position relative to the other
+/// servers in both lists changed. Servers that merely shifted because others were added or
+/// removed before them are not reported.
+pub fn write_diff(old: &str, new: &str, mut out: impl Write) -> io::Result<()> {
+    let old = server_urls(old).collect::<Vec<_>>();
+    let new = server_urls(new).collect::<Vec<_>>();
+    let old_set = old.iter().copied().collect::<HashSet<_>>();
+    let new_set = new.iter().copied().collect::<HashSet<_>>();
+
+    let old_common = old
+        .iter()
+        .copied()
+        .filter(|server| new_set.contains(server))
+        .collect::<Vec<_>>();
+    let new_common = new
+        .iter()
+        .copied()
+        .filter(|server| old_set.contains(server))
+        .collect::<Vec<_>>();
+    let in_order = longest_common_subsequence(&old_common, &new_common);
+
+    let (mut added, mut moved) = (0, 0);
+    for (position, server) in new.iter().enumerate() {
+        if !old_set.contains(server) {
+            added += 1;
+            writeln!(out, "+ {server}  (added at {})", position + 1)?;
+        } else if !in_order.contains(server) {
+            moved += 1;
+            let old_position = old.iter().position(|old| old == server).unwrap_or_default();
+            writeln!(
+                out,
+                "~ {server}  (moved from {} to {})",
+                old_position + 1,
+                position + 1
+            )?;
+        }
+    }
+    let mut removed = 0;
+    for server in old.iter().filter(|server| !new_set.contains(*server)) {
+        removed += 1;
+        writeln!(out, "- {server}  (removed)")?;
+    }
+    writeln!(
+        out,
+        "{added} added, {removed} removed, {moved} moved, {} unchanged",
+        in_order.len()
+    )
+}
+
+/// The elements of the longest common subsequence of `a` and `b`.
+fn longest_common_subsequence<'a>(a: &[&'a str], b: &[&'a str]) -> HashSet<&'a str> {
+    // lengths[i][j] is the length of the longest common subsequence of a[i..] and b[j..].
+    let mut lengths = vec![vec![0_usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lengths[i][j] = if a[i] == b[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut common = HashSet::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        if a[i] == b[j] {
+            common.insert(a[i]);
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    common
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_changes() {
+        let old = "Server = a\nServer = b\nServer = c\nServer = d\n";
+        let new = "# comment\nServer = b\nServer = c\nServer = e\nServer = a\n";
+        let mut out = Vec::new();
+        write_diff(old, new, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "+ e  (added at 3)\n\
+             ~ a  (moved from 1 to 4)\n\
+             - d  (removed)\n\
+             1 added, 1 removed, 1 moved, 2 unchanged\n"
+        );
+    }
+}
@@ -0,0 +1,512 @@
+//! Diagnosing common problems for "reflector doctor", so bug reports can start from its
+//! findings instead of a round of questions.
+use crate::color::{Style, paint};
+use crate::{DEFAULT_MIRRORLIST, RunOptions, get_cache_dir, save};
+use anyhow::Result;
+use reqwest::Url;
+use rustix::fs::Access;
+use std::io::{self, Write};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::process::Output;
+use std::time::{Duration, Instant};
+use tokio::net::TcpStream;
+use tokio::process::Command;
+
+/// How severe a finding is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Level {
+    Ok,
+    Warning,
+    Error,
+}
+
+impl Level {
+    fn label(self) -> &'static str {
+        match self {
+            Self::Ok => "ok",
+            Self::Warning => "warning",
+            Self::Error => "error",
+        }
+    }
+
+    fn style(self) -> Style {
+        match self {
+            Self::Ok => Style::Good,
+            Self::Warning => Style::Fair,
+            Self::Error => Style::Bad,
+        }
+    }
+}
+
+/// The result of a single check.
+struct Finding {
+    level: Level,
+    check: &'static str,
+    message: String,
+}
+
+impl Finding {
+    fn new(level: Level, check: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            level,
+            check,
+            message: message.into(),
+        }
+    }
+}
+
+/// Run all checks and print their findings. Fails if any check found an error.
+pub async fn doctor(
+    run_options: &RunOptions,
+    http_client: &reqwest::Client,
+    url: &str,
+    mut out: impl Write,
+) -> Result<()> {
+    let mut findings = Vec::new();
+    check_network(run_options, url, &mut findings).await;
+    findings.push(check_status_api(http_client, url, run_options).await);
+    findings.push(check_program("rsync", "rsync").await);
+    #[cfg(feature = "ftp")]
+    findings.push(check_program("curl", "FTP").await);
+    findings.push(check_cache_dir(run_options));
+    findings.push(check_save_path(run_options));
+
+    let colored = run_options.color.enabled();
+    for finding in &findings {
+        let label = format!("{:<7}", finding.level.label());
+        writeln!(
+            out,
+            "{}  {}: {}",
+            paint(colored, &label, Some(finding.level.style())),
+            finding.check,
+            finding.message
+        )?;
+    }
+    let errors = findings
+        .iter()
+        .filter(|finding| finding.level == Level::Error)
+        .count();
+    if errors > 0 {
+        anyhow::bail!("{errors} of {} checks found problems", findings.len());
+    }
+    Ok(())
+}
+
+/// Resolve the host of the mirror status and connect to it over IPv4 and IPv6.
+async fn check_network(run_options: &RunOptions, url: &str, findings: &mut Vec<Finding>) {
+    let Some((host, port)) = Url::parse(url)
+        .ok()
+        .and_then(|url| Some((url.host_str()?.to_owned(), url.port_or_known_default()?)))
+    else {
+        findings.push(Finding::new(
+            Level::Ok,
+            "DNS",
+            format!("skipped, since {url} is not an HTTP(S) URL"),
+        ));
+        return;
+    };
+    let addresses = tokio::net::lookup_host((host.as_str(), port))
+        .await
+        .map(Iterator::collect::<Vec<_>>);
+    findings.push(resolved(&host, &addresses));
+    let Ok(addresses) = addresses else {
+        return;
+    };
+
+    if run_options.proxy.is_some() {
+        findings.push(Finding::new(
+            Level::Ok,
+            "IPv4/IPv6",
+            "skipped, since connections go through --proxy",
+        ));
+        return;
+    }
+    let timeout = Duration::from_secs(run_options.connection_timeout);
+    let mut connections = Vec::new();
+    for ipv6 in [false, true] {
+        let address = addresses
+            .iter()
+            .find(|address| address.is_ipv6() == ipv6)
+            .copied();
+        let result = match address {
+            Some(address) => tokio::time::timeout(timeout, TcpStream::connect(address))
+                .await
+                .ok()
+                .map(|result| result.map(drop)),
+            None => None,
+        };
+        connections.push(Connection { address, result });
+    }
+    findings.extend(connected(&host, [&connections[0], &connections[1]]));
+}
+
+/// The DNS finding for the addresses that `host` resolved to.
+fn resolved(host: &str, addresses: &io::Result<Vec<SocketAddr>>) -> Finding {
+    match addresses {
+        Ok(addresses) => Finding::new(
+            Level::Ok,
+            "DNS",
+            format!(
+                "{host} resolves to {} IPv4 and {} IPv6 addresses",
+                addresses.iter().filter(|address| address.is_ipv4()).count(),
+                addresses.iter().filter(|address| address.is_ipv6()).count()
+            ),
+        ),
+        Err(err) => Finding::new(
+            Level::Error,
+            "DNS",
+            format!(
+                "failed to resolve {host}: {err}; check /etc/resolv.conf and the network connection"
+            ),
+        ),
+    }
+}
+
+/// An attempt to connect to the first address of an IP version.
+struct Connection {
+    /// The address, if the host has one of this IP version.
+    address: Option<SocketAddr>,
+
+    /// The result of connecting, or `None` if it timed out or was not attempted.
+    result: Option<io::Result<()>>,
+}
+
+/// The findings for connecting to `host` over IPv4 and IPv6, in that order.
+fn connected(host: &str, [ipv4, ipv6]: [&Connection; 2]) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let mut reachable = false;
+    for (family, connection, hint) in [("IPv4", ipv4, "--ipv6"), ("IPv6", ipv6, "--ipv4")] {
+        let Some(address) = connection.address else {
+            findings.push(Finding::new(
+                Level::Ok,
+                family,
+                format!("skipped, since {host} has no {family} address"),
+            ));
+            continue;
+        };
+        let finding = match &connection.result {
+            Some(Ok(())) => {
+                reachable = true;
+                Finding::new(Level::Ok, family, format!("connected to {address}"))
+            }
+            Some(Err(err)) => Finding::new(
+                Level::Warning,
+                family,
+                format!(
+                    "failed to connect to {address}: {err}; use {hint} to only select mirrors that support the other protocol"
+                ),
+            ),
+            None => Finding::new(
+                Level::Warning,
+                family,
+                format!(
+                    "timed out connecting to {address}; use {hint} to only select mirrors that support the other protocol"
+                ),
+            ),
+        };
+        findings.push(finding);
+    }
+    if !reachable && (ipv4.address.is_some() || ipv6.address.is_some()) {
+        findings.push(Finding::new(
+            Level::Error,
+            "IPv4/IPv6",
+            format!("cannot connect to {host}; check the network connection and firewall, or use --proxy"),
+        ));
+    }
+    findings
+}
+
+/// Retrieve and parse the mirror status of the distribution.
+async fn check_status_api(
+    http_client: &reqwest::Client,
+    url: &str,
+    run_options: &RunOptions,
+) -> Finding {
+    let Some(provider) = run_options.provider(url) else {
+        return Finding::new(
+            Level::Ok,
+            "status API",
+            "skipped, since the mirrors of Arch Linux ARM are built in",
+        );
+    };
+    if !url.starts_with("http://") && !url.starts_with("https://") {
+        return Finding::new(
+            Level::Ok,
+            "status API",
+            format!("skipped, since the mirror status is read from {url}"),
+        );
+    }
+    let start = Instant::now();
+    let result = async {
+        let response = http_client.get(url).send().await?.error_for_status()?;
+        let status = provider
+            .parse(&response.bytes().await?)
+            .map_err(|err| anyhow::anyhow!(err))?;
+        anyhow::Ok(status.urls.len())
+    };
+    retrieved(url, result.await, start.elapsed())
+}
+
+/// The finding for retrieving the number of mirrors from the mirror status at `url`.
+fn retrieved(url: &str, result: Result<usize>, elapsed: Duration) -> Finding {
+    match result {
+        Ok(mirrors) => Finding::new(
+            Level::Ok,
+            "status API",
+            format!("retrieved {mirrors} mirrors from {url} in {elapsed:.2?}"),
+        ),
+        Err(err) => Finding::new(
+            Level::Error,
+            "status API",
+            format!(
+                "failed to retrieve the mirror status from {url}: {err:#}; check --url and --proxy, or raise --download-timeout"
+            ),
+        ),
+    }
+}
+
+/// Check that an external program used to rate mirrors of `protocol` can be run.
+async fn check_program(program: &'static str, protocol: &str) -> Finding {
+    let output = Command::new(program).arg("--version").output().await;
+    program_version(program, protocol, output)
+}
+
+/// The finding for the output of `program --version`.
+fn program_version(program: &'static str, protocol: &str, output: io::Result<Output>) -> Finding {
+    match output {
+        Ok(output) if output.status.success() => {
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            let version = stdout.lines().next().unwrap_or_default().trim();
+            Finding::new(Level::Ok, program, version)
+        }
+        Ok(output) => Finding::new(
+            Level::Warning,
+            program,
+            format!("{program} --version failed with {}", output.status),
+        ),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Finding::new(
+            Level::Warning,
+            program,
+            format!(
+                "{program} is not installed, so {protocol} mirrors cannot be rated; install it or exclude them with --protocol"
+            ),
+        ),
+        Err(err) => Finding::new(
+            Level::Warning,
+            program,
+            format!("failed to run {program}: {err}"),
+        ),
+    }
+}
+
+/// Check that files can be created in the cache directory.
+fn check_cache_dir(run_options: &RunOptions) -> Finding {
+    if run_options.no_cache {
+        return Finding::new(Level::Ok, "cache", "skipped, since --no-cache is given");
+    }
+    let result = get_cache_dir(run_options).and_then(|dir| {
+        std::fs::create_dir_all(&dir)?;
+        tempfile::tempfile_in(&dir)?;
+        Ok(dir)
+    });
+    cache_writable(result)
+}
+
+/// The finding for creating a file in the cache directory.
+fn cache_writable(result: io::Result<PathBuf>) -> Finding {
+    let check = "cache";
+    match result {
+        Ok(dir) => Finding::new(Level::Ok, check, format!("{} is writable", dir.display())),
+        Err(err) => Finding::new(
+            Level::Warning,
+            check,
+            format!(
+                "cannot write to the cache directory: {err}; use --cache-dir to choose another one, or --no-cache"
+            ),
+        ),
+    }
+}
+
+/// Check that the file given to "--save", or the default mirrorlist, can be written.
+fn check_save_path(run_options: &RunOptions) -> Finding {
+    let path = Path::new(run_options.save.as_deref().unwrap_or(DEFAULT_MIRRORLIST));
+    // New files are created next to the file and renamed into place, so the directory has
+    // to be writable as well.
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let access = rustix::fs::access(dir, Access::WRITE_OK);
+    save_writable(path, dir, access, save::find_escalation_program())
+}
+
+/// The finding for the write access to `dir`, the directory of the saved file `path`, given
+/// the program to escalate privileges with, if any.
+fn save_writable(
+    path: &Path,
+    dir: &Path,
+    access: rustix::io::Result<()>,
+    escalation_program: Option<PathBuf>,
+) -> Finding {
+    let check = "save";
+    match access {
+        Ok(()) => Finding::new(Level::Ok, check, format!("{} is writable", path.display())),
+        Err(rustix::io::Errno::NOENT) => Finding::new(
+            Level::Error,
+            check,
+            format!("{} does not exist", dir.display()),
+        ),
+        Err(err) => {
+            let hint = match escalation_program {
+                Some(program) => format!(
+                    "run reflector as root, or use --escalate to install it with {}",
+                    program.display()
+                ),
+                None => "run reflector as root".to_owned(),
+            };
+            Finding::new(
+                Level::Warning,
+                check,
+                format!("cannot write {}: {err}; {hint}", path.display()),
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn levels(findings: &[Finding]) -> Vec<(&str, Level)> {
+        findings
+            .iter()
+            .map(|finding| (finding.check, finding.level))
+            .collect()
+    }
+
+    #[test]
+    fn resolve_host() {
+        let addresses = vec![
+            "192.0.2.1:443".parse().unwrap(),
+            "192.0.2.2:443".parse().unwrap(),
+            "[2001:db8::1]:443".parse().unwrap(),
+        ];
+        let finding = resolved("archlinux.org", &Ok(addresses));
+        assert_eq!(finding.level, Level::Ok);
+        assert_eq!(
+            finding.message,
+            "archlinux.org resolves to 2 IPv4 and 1 IPv6 addresses"
+        );
+        let finding = resolved("archlinux.org", &Err(io::ErrorKind::NotFound.into()));
+        assert_eq!(finding.level, Level::Error);
+        assert!(finding.message.contains("/etc/resolv.conf"));
+    }
+
+    #[test]
+    fn connect_over_ipv4_and_ipv6() {
+        let ipv4 = "192.0.2.1:443".parse().ok();
+        let ipv6 = "[2001:db8::1]:443".parse().ok();
+        let connection = |address, result| Connection { address, result };
+
+        let findings = connected(
+            "archlinux.org",
+            [
+                &connection(ipv4, Some(Ok(()))),
+                &connection(ipv6, Some(Err(io::ErrorKind::ConnectionRefused.into()))),
+            ],
+        );
+        assert_eq!(
+            levels(&findings),
+            [("IPv4", Level::Ok), ("IPv6", Level::Warning)]
+        );
+        assert!(findings[1].message.contains("use --ipv4"));
+
+        let findings = connected(
+            "archlinux.org",
+            [&connection(ipv4, None), &connection(None, None)],
+        );
+        assert_eq!(
+            levels(&findings),
+            [
+                ("IPv4", Level::Warning),
+                ("IPv6", Level::Ok),
+                ("IPv4/IPv6", Level::Error)
+            ]
+        );
+        assert!(findings[0].message.starts_with("timed out"));
+    }
+
+    #[test]
+    fn retrieve_status() {
+        let url = "https://archlinux.org/mirrors/status/json/";
+        let finding = retrieved(url, Ok(3), Duration::from_millis(250));
+        assert_eq!(finding.level, Level::Ok);
+        assert!(finding.message.starts_with("retrieved 3 mirrors"));
+        let finding = retrieved(url, Err(anyhow::anyhow!("404")), Duration::ZERO);
+        assert_eq!(finding.level, Level::Error);
+        assert!(finding.message.contains("--download-timeout"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn run_program() {
+        use std::os::unix::process::ExitStatusExt;
+        use std::process::ExitStatus;
+
+        let output = |code, stdout: &str| Output {
+            status: ExitStatus::from_raw(code),
+            stdout: stdout.as_bytes().to_vec(),
+            stderr: Vec::new(),
+        };
+        let finding = program_version(
+            "rsync",
+            "rsync",
+            Ok(output(0, "rsync  version 3.3.0  protocol version 31\n...")),
+        );
+        assert_eq!(finding.level, Level::Ok);
+        assert_eq!(finding.message, "rsync  version 3.3.0  protocol version 31");
+        let finding = program_version("rsync", "rsync", Ok(output(1 << 8, "")));
+        assert_eq!(finding.level, Level::Warning);
+        let finding = program_version("curl", "FTP", Err(io::ErrorKind::NotFound.into()));
+        assert_eq!(finding.level, Level::Warning);
+        assert!(
+            finding
+                .message
+                .starts_with("curl is not installed, so FTP mirrors")
+        );
+    }
+
+    #[test]
+    fn write_cache() {
+        let finding = cache_writable(Ok(PathBuf::from("/var/cache/reflector")));
+        assert_eq!(finding.level, Level::Ok);
+        let finding = cache_writable(Err(io::ErrorKind::PermissionDenied.into()));
+        assert_eq!(finding.level, Level::Warning);
+        assert!(finding.message.contains("--no-cache"));
+    }
+
+    #[test]
+    fn write_save_path() {
+        let path = Path::new("/etc/pacman.d/mirrorlist");
+        let dir = Path::new("/etc/pacman.d");
+        assert_eq!(save_writable(path, dir, Ok(()), None).level, Level::Ok);
+        let finding = save_writable(path, dir, Err(rustix::io::Errno::NOENT), None);
+        assert_eq!(finding.level, Level::Error);
+        assert_eq!(finding.message, "/etc/pacman.d does not exist");
+        let finding = save_writable(
+            path,
+            dir,
+            Err(rustix::io::Errno::ACCESS),
+            Some(PathBuf::from("/usr/bin/sudo")),
+        );
+        assert_eq!(finding.level, Level::Warning);
+        assert!(
+            finding
+                .message
+                .ends_with("--escalate to install it with /usr/bin/sudo")
+        );
+        let finding = save_writable(path, dir, Err(rustix::io::Errno::ACCESS), None);
+        assert!(finding.message.ends_with("run reflector as root"));
+    }
+}
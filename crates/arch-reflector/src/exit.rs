@@ -0,0 +1,60 @@
+//! The exit status of reflector, so scripts and systemd can tell failures apart.
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+
+/// The reasons for failing with a specific exit status. Other errors exit with status 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exit {
+    /// The mirror status could not be retrieved.
+    Status = 1,
+    /// Fewer mirrors than required by "--min-mirrors" were left after filtering.
+    TooFewMirrors = 2,
+    /// The mirrorlist could not be written.
+    Write = 3,
+    /// The command line arguments or configuration files are invalid.
+    Usage = 64,
+}
+
+impl Exit {
+    /// The exit status for an error returned by reflector.
+    pub fn status_of(err: &anyhow::Error) -> u8 {
+        err.downcast_ref::<Failure>()
+            .map_or(1, |failure| failure.exit as u8)
+    }
+}
+
+/// An error that makes reflector exit with the status of `exit`.
+#[derive(Debug)]
+pub struct Failure {
+    pub exit: Exit,
+    pub error: anyhow::Error,
+}
+
+impl Display for Failure {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+impl Error for Failure {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(self.error.as_ref())
+    }
+}
+
+/// Attach an exit status to the error of a result.
+pub trait ExitWith<T> {
+    fn exit_with(self, exit: Exit) -> anyhow::Result<T>;
+}
+
+impl<T, E: Into<anyhow::Error>> ExitWith<T> for Result<T, E> {
+    fn exit_with(self, exit: Exit) -> anyhow::Result<T> {
+        self.map_err(|error| {
+            Failure {
+                exit,
+                error: error.into(),
+            }
+            .into()
+        })
+    }
+}
@@ -0,0 +1,98 @@
+//! The rates measured in previous runs, which "--smooth" blends with new measurements.
+use jiff::{SignedDuration, Timestamp};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// The name of the history file in the cache directory.
+pub const HISTORY_FILE_NAME: &str = "rates.json";
+
+/// The weight of a new measurement when maintaining the history without "--smooth".
+const DEFAULT_WEIGHT: f64 = 0.3;
+
+/// Rates measured longer ago than this no longer describe the mirror and are dropped.
+const MAX_AGE: SignedDuration = SignedDuration::from_hours(30 * 24);
+
+/// The exponentially weighted moving average of the rate of each mirror.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct History {
+    mirrors: HashMap<String, Entry>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct Entry {
+    /// The average rate in bytes per second.
+    rate: f64,
+    /// When the rate was last measured.
+    measured: Timestamp,
+}
+
+impl History {
+    /// Load the history. Missing or invalid files result in an empty history.
+    pub fn load(path: &Path) -> Self {
+        let mut history: Self = fs::read(path)
+            .ok()
+            .and_then(|content| serde_json::from_slice(&content).ok())
+            .unwrap_or_default();
+        let now = Timestamp::now();
+        history
+            .mirrors
+            .retain(|_, entry| now.duration_since(entry.measured) <= MAX_AGE);
+        history
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, serde_json::to_vec(self)?)
+    }
+
+    /// Blend a newly measured rate with the history, giving it `weight`. Mirrors without
+    /// history keep the measured rate.
+    pub fn smooth(&self, url: &str, rate: f64, weight: f64) -> f64 {
+        match self.mirrors.get(url) {
+            Some(entry) => weight * rate + (1.0 - weight) * entry.rate,
+            None => rate,
+        }
+    }
+
+    /// Add a newly measured rate to the history, blending it with the given weight or the
+    /// default one.
+    pub fn record(&mut self, url: &str, rate: f64, weight: Option<f64>, measured: Timestamp) {
+        let rate = self.smooth(url, rate, weight.unwrap_or(DEFAULT_WEIGHT));
+        self.mirrors
+            .insert(url.to_owned(), Entry { rate, measured });
+    }
+}
+
+/// Parse the weight given to "--smooth", which must be above 0 and at most 1.
+pub fn parse_weight(s: &str) -> Result<f64, String> {
+    let weight: f64 = s.parse().map_err(|err| format!("{err}"))?;
+    if weight > 0.0 && weight <= 1.0 {
+        Ok(weight)
+    } else {
+        Err("the weight must be above 0 and at most 1".to_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn smooth_rates() {
+        let url = "https://mirror.example.com/archlinux/";
+        let when = Timestamp::from_second(1_700_000_000).unwrap();
+        let mut history = History::default();
+        assert!((history.smooth(url, 100.0, 0.5) - 100.0).abs() < f64::EPSILON);
+        history.record(url, 100.0, Some(0.5), when);
+        assert!((history.smooth(url, 200.0, 0.5) - 150.0).abs() < f64::EPSILON);
+        history.record(url, 200.0, Some(0.5), when);
+        assert!((history.smooth(url, 300.0, 0.5) - 225.0).abs() < f64::EPSILON);
+        assert_eq!(
+            parse_weight("0"),
+            Err("the weight must be above 0 and at most 1".to_owned())
+        );
+        assert_eq!(parse_weight("1"), Ok(1.0));
+    }
+}
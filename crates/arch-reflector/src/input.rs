@@ -0,0 +1,276 @@
+//! Reading the candidate mirrors from an existing mirrorlist for "--input-mirrorlist", or
+//! from the mirrorlist published by distributions without a mirror status, instead of the
+//! mirror status.
+use anyhow::{Context, Result};
+use arch_mirrors_rs::extra::Extra;
+use arch_mirrors_rs::mirrorlist::{self, Line};
+use arch_mirrors_rs::provider::Error;
+use arch_mirrors_rs::{CountryCode, Mirror, MirrorProvider, Protocol, Status};
+use jiff::Timestamp;
+use reqwest::Url;
+use std::fs;
+use std::time::SystemTime;
+
+/// The mirrorlist published by a distribution at a URL, for distributions without a mirror
+/// status.
+pub struct Mirrorlist {
+    url: String,
+    repo_path: String,
+    /// Whether the servers are commented out, as with [`parse_generated`].
+    generated: bool,
+}
+
+impl Mirrorlist {
+    pub fn new(url: impl Into<String>, repo_path: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            repo_path: repo_path.into(),
+            generated: false,
+        }
+    }
+
+    /// A mirrorlist generated by the website of a distribution.
+    pub fn generated(url: impl Into<String>, repo_path: impl Into<String>) -> Self {
+        Self {
+            generated: true,
+            ..Self::new(url, repo_path)
+        }
+    }
+}
+
+impl MirrorProvider for Mirrorlist {
+    fn url(&self) -> &str {
+        &self.url
+    }
+
+    fn parse(&self, data: &[u8]) -> Result<Status, Error> {
+        let content = std::str::from_utf8(data)?;
+        let parse = if self.generated {
+            parse_generated
+        } else {
+            parse
+        };
+        Ok(parse(content, &self.repo_path, Timestamp::now())?)
+    }
+}
+
+/// Load the mirrors of a mirrorlist as a mirror status without any statistics, along with
+/// the modification time of the file.
+pub fn load(path: &str, repo_path: &str) -> Result<(Status, SystemTime)> {
+    let content = fs::read_to_string(path).with_context(|| format!("failed to read {path}"))?;
+    let mtime = fs::metadata(path)?.modified()?;
+    let status = parse(&content, repo_path, Timestamp::try_from(mtime)?)
+        .with_context(|| format!("invalid mirrorlist {path}"))?;
+    Ok((status, mtime))
+}
+
+/// Parse the mirrors of a mirrorlist as a mirror status without any statistics.
+pub fn parse(content: &str, repo_path: &str, last_check: Timestamp) -> Result<Status> {
+    let urls = parse_mirrorlist(content, repo_path)?;
+    if urls.is_empty() {
+        anyhow::bail!("the mirrorlist does not contain any Server lines");
+    }
+    Ok(status(urls, last_check))
+}
+
+/// Parse a list of mirrors for "--mirror-source", given as one URL per line or as a JSON
+/// array of URLs, as a mirror status without any statistics.
+pub fn parse_source(content: &str, repo_path: &str, last_check: Timestamp) -> Result<Status> {
+    let servers: Vec<String> = if content.trim_start().starts_with('[') {
+        serde_json::from_str(content)?
+    } else {
+        content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(str::to_owned)
+            .collect()
+    };
+    let urls = servers
+        .iter()
+        .map(|server| parse_server(server, repo_path))
+        .collect::<Result<Vec<_>>>()?;
+    if urls.is_empty() {
+        anyhow::bail!("the list does not contain any mirrors");
+    }
+    Ok(status(urls, last_check))
+}
+
+/// Parse a mirrorlist generated by the website of a distribution, in which all servers are
+/// commented out below a "## Country" heading, as a mirror status without any statistics.
+pub fn parse_generated(content: &str, repo_path: &str, last_check: Timestamp) -> Result<Status> {
+    let mut country = None;
+    let mut urls = Vec::new();
+    for line in mirrorlist::Mirrorlist::parse(content).lines {
+        let server = match line {
+            Line::Comment(comment) => {
+                if let Some(heading) = comment.strip_prefix("# ") {
+                    country = CountryCode::lookup(heading);
+                }
+                continue;
+            }
+            Line::Server { url, .. } => url,
+            _ => continue,
+        };
+        let mut mirror = parse_server(&server, repo_path)?;
+        if let Some(code) = country {
+            code.name().clone_into(&mut mirror.country);
+            code.as_str().clone_into(&mut mirror.country_code);
+        }
+        urls.push(mirror);
+    }
+    if urls.is_empty() {
+        anyhow::bail!("the mirrorlist does not contain any Server lines");
+    }
+    Ok(status(urls, last_check))
+}
+
+/// A mirror status of the given mirrors, without any statistics.
+fn status(urls: Vec<Mirror>, last_check: Timestamp) -> Status {
+    Status {
+        cutoff: 0,
+        last_check,
+        num_checks: 0,
+        check_frequency: 0,
+        urls,
+        version: 0,
+        extra: Extra::default(),
+    }
+}
+
+/// Parse the Server lines of a mirrorlist, in their original order, into mirrors. Commented
+/// out servers are ignored.
+fn parse_mirrorlist(content: &str, repo_path: &str) -> Result<Vec<Mirror>> {
+    mirrorlist::Mirrorlist::parse(content)
+        .servers()
+        .map(|server| parse_server(server, repo_path))
+        .collect()
+}
+
+/// Parse the URL of a Server line into a mirror without any statistics.
+fn parse_server(server: &str, repo_path: &str) -> Result<Mirror> {
+    // Cut the repository path, e.g. "$repo/os/$arch", to get the base URL of the mirror.
+    // Servers with another layout are cut at their first variable.
+    let base = server
+        .strip_suffix(repo_path)
+        .unwrap_or_else(|| server.split('$').next().unwrap_or(server));
+    let mut url = Url::parse(base).with_context(|| format!("invalid server {server}"))?;
+    if !url.path().ends_with('/') {
+        url.set_path(&format!("{}/", url.path()));
+    }
+    let protocol = url
+        .scheme()
+        .parse::<Protocol>()
+        .map_err(|err| anyhow::anyhow!("{server}: {err}"))?;
+    Ok(Mirror {
+        url,
+        protocol,
+        last_sync: None,
+        completion_pct: None,
+        delay: None,
+        duration_average: None,
+        duration_stddev: None,
+        score: None,
+        active: true,
+        country: String::new(),
+        country_code: String::new(),
+        isos: false,
+        ipv4: false,
+        ipv6: false,
+        details: String::new(),
+        extra: Extra::default(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_servers() {
+        let content = "\
+## Sweden
+Server = https://mirror.example.se/archlinux/$repo/os/$arch
+#Server = https://disabled.example.com/archlinux/$repo/os/$arch
+Server = rsync://mirror.example.de/arch/$repo/os/$arch
+Server=http://mirror.example.fr/arch
+";
+        let mirrors = parse_mirrorlist(content, "$repo/os/$arch").unwrap();
+        let urls = mirrors
+            .iter()
+            .map(|mirror| (mirror.url.as_str(), mirror.protocol))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            urls,
+            vec![
+                ("https://mirror.example.se/archlinux/", Protocol::Https),
+                ("rsync://mirror.example.de/arch/", Protocol::Rsync),
+                ("http://mirror.example.fr/arch/", Protocol::Http),
+            ]
+        );
+        assert!(parse_mirrorlist("Server = gopher://example.com/$repo", "$repo/os/$arch").is_err());
+        let artix = "Server = https://mirror.example.org/artix-linux/repos/$repo/os/$arch";
+        assert_eq!(
+            parse_mirrorlist(artix, "$repo/os/$arch").unwrap()[0]
+                .url
+                .as_str(),
+            "https://mirror.example.org/artix-linux/repos/"
+        );
+    }
+
+    #[test]
+    fn parse_mirror_source() {
+        let list = "\
+# chaotic-aur
+https://cdn-mirror.example.com/$repo/$arch
+
+http://mirror.example.org/chaotic-aur/
+";
+        let json =
+            r#"["https://cdn-mirror.example.com/", "http://mirror.example.org/chaotic-aur"]"#;
+        for content in [list, json] {
+            let status = parse_source(content, "$repo/$arch", Timestamp::UNIX_EPOCH).unwrap();
+            let urls = status
+                .urls
+                .iter()
+                .map(|mirror| mirror.url.as_str())
+                .collect::<Vec<_>>();
+            assert_eq!(
+                urls,
+                vec![
+                    "https://cdn-mirror.example.com/",
+                    "http://mirror.example.org/chaotic-aur/"
+                ]
+            );
+        }
+        assert!(parse_source("# empty\n", "$repo/$arch", Timestamp::UNIX_EPOCH).is_err());
+    }
+
+    #[test]
+    fn parse_generated_mirrorlist() {
+        let content = "\
+## Arch Linux 32 repository mirrorlist
+
+## Germany
+#Server = https://mirror.example.de/archlinux32/$arch/$repo/
+#Server = http://mirror.example.de/archlinux32/$arch/$repo/
+
+## Worldwide
+#Server = https://mirror.example.org/archlinux32/$arch/$repo/
+";
+        let status = parse_generated(content, "$arch/$repo", Timestamp::UNIX_EPOCH).unwrap();
+        let urls = status
+            .urls
+            .iter()
+            .map(|mirror| (mirror.url.as_str(), mirror.country_code.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            urls,
+            vec![
+                ("https://mirror.example.de/archlinux32/", "DE"),
+                ("http://mirror.example.de/archlinux32/", "DE"),
+                ("https://mirror.example.org/archlinux32/", ""),
+            ]
+        );
+    }
+}
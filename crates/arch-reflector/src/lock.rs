@@ -0,0 +1,37 @@
+//! An advisory lock that keeps concurrent runs of reflector, e.g. one started by the
+//! systemd timer and a manual one, from racing on the cache and the saved mirrorlist.
+use anyhow::{Context, Result};
+use rustix::fs::{FlockOperation, flock};
+use rustix::io::Errno;
+use std::fs::{File, OpenOptions};
+use std::path::Path;
+
+use crate::notify;
+
+/// The name of the lock file in the cache directory.
+pub const LOCK_FILE_NAME: &str = "reflector.lock";
+
+/// Lock the file at `path`, waiting for other runs to release it first. The lock is
+/// released when the returned file is closed, including when reflector crashes.
+pub async fn acquire(path: &Path) -> Result<File> {
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(path)
+        .with_context(|| format!("failed to open {}", path.display()))?;
+    match flock(&file, FlockOperation::NonBlockingLockExclusive) {
+        Ok(()) => return Ok(file),
+        Err(Errno::WOULDBLOCK) => {}
+        Err(err) => return Err(err).with_context(|| format!("failed to lock {}", path.display())),
+    }
+
+    tracing::info!(
+        "waiting for another run of reflector to release {}",
+        path.display()
+    );
+    notify::status("Waiting for another run of reflector to finish");
+    tokio::task::spawn_blocking(move || flock(&file, FlockOperation::LockExclusive).map(|()| file))
+        .await?
+        .with_context(|| format!("failed to lock {}", path.display()))
+}
@@ -1,28 +1,66 @@
-use anyhow::Result;
-use arch_mirrors_rs::{Mirror, Protocol, Status};
-use clap::{ArgAction, Args, Parser, ValueEnum, value_parser};
-use clap_verbosity_flag::Verbosity;
-use futures_util::StreamExt;
-use jiff::{Span, Timestamp};
+mod arch;
+mod archarm;
+mod asn;
+mod bench;
+mod color;
+mod config;
+mod continent;
+mod country;
+mod debug;
+mod diff;
+mod doctor;
+mod exit;
+mod history;
+mod input;
+mod lock;
+mod manjaro;
+mod metrics;
+mod neighbors;
+mod notify;
+mod rate;
+mod save;
+#[cfg(feature = "tui")]
+mod tui;
+
+use anyhow::{Context, Result};
+use arch::Distro;
+use arch_mirrors_rs::mirror::ScoreWeights;
+use arch_mirrors_rs::provider::ArchLinux;
+use arch_mirrors_rs::selector::{SortKey, sort_mirrors};
+use arch_mirrors_rs::{CachedStatus, Mirror, MirrorProvider, Protocol, Status, StatusFetcher};
+use clap::{
+    ArgAction, Args, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum, value_parser,
+};
+use clap_verbosity_flag::{Verbosity, WarnLevel};
+use color::{ColorChoice, Style, paint};
+use continent::Continent;
+use directories::BaseDirs;
+use exit::{Exit, ExitWith, Failure};
+use jiff::{SignedDuration, Timestamp};
+use rate::{Ratings, Threads, measure_latencies, rate_status};
+use reflector_lib::filter::{
+    self, Rejection, cap_per_country, filter_status, funnel_summary, group_hosts, truncate_status,
+};
+use reflector_lib::output::{Metadata, MirrorList, mirror_list, mirrorlist_writer};
+use reflector_lib::quality::{self, QualityWeights};
+use reflector_lib::{schema, sort, template};
 use regex::Regex;
-use reqwest::Url;
+use reqwest::header::{self, HeaderMap};
+use reqwest::{StatusCode, Url};
+use serde::{Deserialize, Serialize};
 use std::cmp::{Ordering, Reverse};
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::ffi::OsString;
 use std::fs::{self, File};
 use std::io::{self, Write};
 use std::path::{Path, PathBuf};
-use std::process::Stdio;
-use std::sync::Arc;
+use std::process::ExitCode;
 use std::time::{Duration, Instant, SystemTime};
-use tokio::sync::Semaphore;
-use tokio::task::JoinSet;
-use xdg::BaseDirectories;
 
-const URL: &str = "https://archlinux.org/mirrors/status/json/";
 const DEFAULT_CONNECTION_TIMEOUT: u64 = 5;
 const DEFAULT_DOWNLOAD_TIMEOUT: u64 = 5;
 const DEFAULT_CACHE_TIMEOUT: u64 = 300;
+const DEFAULT_MIRRORLIST: &str = "/etc/pacman.d/mirrorlist";
 
 #[derive(Debug, ValueEnum, Clone, Copy, PartialEq)]
 #[allow(
@@ -34,12 +72,47 @@ enum SortType {
     Age,
     /// download rate Rate,
     Rate,
+    /// connection latency, much faster to measure than the download rate
+    Latency,
     /// country name, either alphabetically or in the order given by the --country option
     Country,
     /// MirrorStatus score
     Score,
     /// MirrorStatus delay
     Delay,
+    /// score recomputed from the MirrorStatus fields with the weights of --score-weights
+    CustomScore,
+    /// blend of the download rate, latency, age and completion with the weights of
+    /// --quality-weights
+    Quality,
+}
+
+#[derive(Debug, ValueEnum, Clone, Copy, PartialEq)]
+enum ExportFormat {
+    /// human-readable, column-aligned table
+    Table,
+    /// JSON array of objects
+    Json,
+    /// comma-separated values with a header row
+    Csv,
+}
+
+#[derive(Debug, ValueEnum, Clone, Copy, PartialEq)]
+enum CountrySort {
+    /// country code
+    Code,
+    /// country name
+    Name,
+    /// number of mirrors, most first
+    Count,
+}
+
+#[derive(Debug, ValueEnum, Clone, Copy, PartialEq)]
+enum OutputFormat {
+    /// pacman mirrorlist
+    Mirrorlist,
+    /// JSON object with the selected mirrors and their details
+    Json,
 }
 
 #[derive(Parser, Debug)]
@@ -57,20 +130,65 @@ enum SortType {
 )]
 struct Cli {
     /// The URL from which to retrieve the mirror data in JSON format. If different from
-    /// the default, it must follow the same format.
-    #[arg(long, default_value = URL)]
+    /// the default, it must follow the same format. Use a "file://" URL or "-" to read a
+    /// snapshot of the mirror status from a file or STDIN, which is not cached.
+    #[arg(long, default_value = ArchLinux::URL, default_value_ifs([
+        ("distro", "manjaro", manjaro::URL),
+        ("distro", "artix", arch::ARTIX_URL),
+        ("distro", "archlinux32", arch::ARCHLINUX32_URL),
+    ]))]
     url: String,
 
-    /// Display a table of the distribution of servers by country.
+    /// Read default options from the given configuration file instead of
+    /// "/etc/xdg/reflector/defaults.conf" and "~/.config/reflector/reflector.conf".
+    #[arg(long, value_name = "filepath", conflicts_with = "no_config")]
+    config: Option<PathBuf>,
+
+    /// Do not read default options from any configuration file.
+    #[arg(long)]
+    no_config: bool,
+
+    /// Display a table of the distribution of servers by country. Filter options apply.
     #[arg(long)]
     list_countries: bool,
 
+    /// The order of "--list-countries".
+    #[arg(long, value_name = "order", default_value = "code")]
+    list_countries_sort: CountrySort,
+
+    /// The output format of "--list-countries". The JSON and CSV formats additionally
+    /// include the protocols, median score and median delay of each country.
+    #[arg(long, value_name = "format", default_value = "table")]
+    list_countries_format: ExportFormat,
+
     /// Print extra information to STDERR. Only works with some options.
     #[clap(flatten)]
-    verbose: Verbosity,
+    verbose: Verbosity<WarnLevel>,
 
     #[command(flatten)]
     run: RunOptions,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Restore a mirrorlist from a backup created with "--backup-count".
+    Restore(RestoreOptions),
+    /// Rate the given mirrors without retrieving the mirror status.
+    Rate(RateOptions),
+    /// Compare the download rate of two mirrors using several interleaved measurements.
+    Compare(CompareOptions),
+    /// Download actual packages from the top mirrors left after filtering and sorting, to
+    /// measure the throughput they sustain over larger transfers than the database.
+    Bench(BenchOptions),
+    /// Check the network, the mirror status, the programs used for rating and the
+    /// permissions of the cache and the mirrorlist, and print what to fix.
+    Doctor,
+    /// Write the reflector(1) man page in roff format to STDOUT.
+    #[command(hide = true)]
+    GenerateMan,
 }
 
 #[derive(Debug, Args)]
@@ -78,6 +196,69 @@ struct Cli {
     clippy::doc_markdown,
     reason = "This is used to generate the user facing help."
 )]
+struct RateOptions {
+    /// The base URLs of the mirrors to rate, e.g. "https://mirror.example.com/archlinux/".
+    #[arg(required = true, value_name = "url")]
+    urls: Vec<Url>,
+}
+
+#[derive(Debug, Args)]
+struct CompareOptions {
+    /// The base URL of the first mirror.
+    #[arg(value_name = "url-a")]
+    url_a: Url,
+
+    /// The base URL of the second mirror.
+    #[arg(value_name = "url-b")]
+    url_b: Url,
+
+    /// The number of measurements to take of each mirror.
+    #[arg(long, default_value_t = 5, value_name = "n", value_parser = value_parser!(u32).range(2..))]
+    rounds: u32,
+}
+
+#[derive(Debug, Args)]
+struct BenchOptions {
+    /// The number of mirrors to benchmark, taken from the top of the list. Only HTTP(S)
+    /// mirrors are benchmarked.
+    #[arg(long, default_value_t = 5, value_name = "n")]
+    mirrors: usize,
+
+    /// The approximate size of each package to download, e.g. "20MiB". The packages of the
+    /// extra repository closest to this size are used.
+    #[arg(long, default_value = "20MiB", value_name = "size", value_parser = rate::parse_size)]
+    size: u64,
+
+    /// The number of packages to download from each mirror.
+    #[arg(long, default_value_t = 1, value_name = "n", value_parser = value_parser!(u32).range(1..))]
+    packages: u32,
+
+    /// The number of seconds to wait for the download of each package.
+    #[arg(long, default_value_t = 60, value_name = "n")]
+    timeout: u64,
+}
+
+#[derive(Debug, Args)]
+struct RestoreOptions {
+    /// List the available backups instead of restoring one.
+    #[arg(long, default_value_t = false)]
+    list: bool,
+
+    /// The mirrorlist to restore.
+    #[arg(long, value_name = "filepath", default_value = DEFAULT_MIRRORLIST)]
+    mirrorlist: PathBuf,
+
+    /// The backup to restore, given by its timestamp or file name. Defaults to the most
+    /// recent backup.
+    backup: Option<String>,
+}
+
+#[derive(Debug, Args)]
+#[allow(
+    clippy::doc_markdown,
+    reason = "This is used to generate the user facing help."
+)]
+#[allow(clippy::struct_excessive_bools)]
 struct RunOptions {
     /// The number of seconds to wait before a connection times out.
     #[arg(long, default_value_t = DEFAULT_CONNECTION_TIMEOUT, value_name = "n")]
@@ -87,35 +268,441 @@ struct RunOptions {
     #[arg(long, default_value_t = DEFAULT_DOWNLOAD_TIMEOUT, value_name = "n")]
     download_timeout: u64,
 
+    /// The number of seconds to wait before rating a single mirror times out, including
+    /// retries with "--retry-rating". Unlike "--download-timeout", this also covers rsync
+    /// and FTP mirrors, so a stalled mirror cannot hold up the others.
+    #[arg(long, value_name = "n")]
+    rate_timeout: Option<u64>,
+
+    /// The number of times to retry retrieving the mirror status after a connection error,
+    /// a timeout or a server error, waiting exponentially longer between attempts.
+    #[arg(long, default_value_t = 0, value_name = "n")]
+    retries: u32,
+
+    /// Rate HTTP(S) and FTP mirrors by downloading only the given amount of their
+    /// database, e.g. "2MiB", instead of all of it. Mirrors that do not support partial
+    /// downloads are rated with a full download.
+    #[arg(long, value_name = "size", value_parser = rate::parse_size)]
+    download_size: Option<u64>,
+
+    /// Also retry rating a mirror up to "--retries" times when it fails.
+    #[arg(long, default_value_t = false, requires = "retries")]
+    retry_rating: bool,
+
     /// The cache timeout in seconds for the data retrieved from the Arch Linux Mirror
     /// Status API.
     #[arg(long, default_value_t = DEFAULT_CACHE_TIMEOUT, value_name = "n")]
     cache_timeout: u64,
 
+    /// The directory in which to cache the data retrieved from the Arch Linux Mirror
+    /// Status API. Defaults to the user's cache directory, e.g. ~/.cache.
+    #[arg(long, env = "REFLECTOR_CACHE_DIR", value_name = "dirpath")]
+    cache_dir: Option<PathBuf>,
+
+    /// The file in which to cache the data retrieved from the Arch Linux Mirror Status API.
+    /// Overrides "--cache-dir".
+    #[arg(long, env = "REFLECTOR_CACHE_FILE", value_name = "filepath")]
+    cache_file: Option<PathBuf>,
+
+    /// Use the cached mirror status regardless of "--cache-timeout" instead of retrieving
+    /// it. Fails if there is no cached mirror status.
+    #[arg(long, default_value_t = false)]
+    offline: bool,
+
+    /// Always retrieve the mirror status and do not cache it.
+    #[arg(long, default_value_t = false, conflicts_with = "offline")]
+    no_cache: bool,
+
+    /// Use the Server lines of an existing mirrorlist as the mirrors instead of the mirror
+    /// status, e.g. to reorder a curated selection by rate. Sorts by rate unless "--sort"
+    /// is given. Filters on the statistics of the mirror status, such as "--age" or
+    /// "--delay", do not apply.
+    #[arg(long, value_name = "filepath", conflicts_with = "offline")]
+    input_mirrorlist: Option<String>,
+
+    /// Use the mirrors listed in a file or at an HTTP(S) URL instead of the mirror status,
+    /// e.g. the mirrors of an unofficial repository. The list holds one URL per line, with
+    /// empty lines and "#" comments ignored, or is a JSON array of URLs. Server URLs are
+    /// cut at their first variable, e.g. "$repo". Sorts by rate unless "--sort" is given,
+    /// and filters that rely on the mirror status do not apply.
+    #[arg(long, value_name = "file|url", conflicts_with_all = ["offline", "input_mirrorlist"])]
+    mirror_source: Option<String>,
+
+    /// Fail if the mirror status is in a version of its format that is not supported,
+    /// instead of warning about it and reading it anyway. The mirror status of Arch Linux
+    /// rarely changes in ways that matter to reflector, so it is read by default.
+    #[arg(long, default_value_t = false)]
+    strict_status: bool,
+
+    /// Do not lock the cache directory. By default, reflector waits for other runs using
+    /// the same cache directory to finish, so they do not race on the cache or "--save".
+    #[arg(long, default_value_t = false)]
+    no_lock: bool,
+
+    /// Send all requests through the given HTTP(S) proxy, e.g. "http://proxy.example:3128",
+    /// both for retrieving the mirror status and for rating mirrors. rsync mirrors are
+    /// rated through the proxy using RSYNC_PROXY.
+    #[arg(long, value_name = "url")]
+    proxy: Option<Url>,
+
+    /// Trust the CA certificates in the given PEM file in addition to the system's, both
+    /// for retrieving the mirror status and for rating mirrors.
+    #[arg(long, value_name = "filepath")]
+    cacert: Option<PathBuf>,
+
+    /// Do not verify TLS certificates. This makes the connections vulnerable to
+    /// man-in-the-middle attacks and should only be used for testing.
+    #[arg(long, default_value_t = false)]
+    insecure: bool,
+
+    /// Authenticate with the client certificate in the given PEM file. Requires
+    /// "--client-key".
+    #[arg(long, value_name = "filepath", requires = "client_key")]
+    client_cert: Option<PathBuf>,
+
+    /// The PKCS #8 private key in PEM format for "--client-cert".
+    #[arg(long, value_name = "filepath", requires = "client_cert")]
+    client_key: Option<PathBuf>,
+
     /// Save the mirrorlist to the given file path.
     #[arg(long, value_name = "filepath")]
     save: Option<String>,
 
+    /// Instead of printing the mirrorlist, print which servers would be added to, removed
+    /// from or moved within the existing mirrorlist at the given path. Combined with
+    /// "--save", the mirrorlist is saved after printing the changes.
+    #[arg(
+        long,
+        value_name = "filepath",
+        conflicts_with_all = ["info", "format", "template", "template_file"]
+    )]
+    diff: Option<PathBuf>,
+
+    /// Do not ask for confirmation before overwriting an existing file with "--save".
+    #[arg(long, short = 'y', default_value_t = false)]
+    yes: bool,
+
+    /// If the file given to "--save" cannot be written due to missing permissions, install
+    /// it using sudo, doas or pkexec without asking first.
+    #[arg(long, default_value_t = false)]
+    escalate: bool,
+
+    /// When "--save" overwrites an existing file, keep the previous file as a timestamped
+    /// backup next to it (e.g. mirrorlist.2024-06-01T12:00:00.123) and retain at most n such
+    /// backups. Backups are disabled by default.
+    #[arg(long, default_value_t = 0, value_name = "n")]
+    backup_count: usize,
+
+    /// Write metrics of the selected mirrors, such as their rate, age and delay, and the
+    /// duration of the run to the given file in the Prometheus text format, e.g. for the
+    /// textfile collector of node_exporter.
+    #[arg(long, value_name = "filepath")]
+    metrics_file: Option<PathBuf>,
+
+    /// Write the outcome of rating each mirror to the given CSV file, including mirrors
+    /// that failed to rate or did not make it into the mirrorlist.
+    #[arg(long, value_name = "filepath")]
+    rate_report: Option<PathBuf>,
+
     /// Sort the mirrorlist by the given field.
     #[arg(long)]
     sort: Option<SortType>,
 
+    /// The weights of the terms of the score used by "--sort custom-score", e.g.
+    /// "delay=1,duration=1,stddev=4,completion=1". The score is the weighted sum of the
+    /// delay in hours, the average duration and the standard deviation of the checks,
+    /// divided by the completion percentage raised to the power of its weight. Lower is
+    /// better. Terms that are left out keep their default weight of 1, which gives the
+    /// MirrorStatus score.
+    #[arg(long, value_name = "weights")]
+    score_weights: Option<ScoreWeights>,
+
+    /// The weights of the measures blended by "--sort quality", e.g.
+    /// "rate=2,latency=1,age=1,completion=1". Each measure is scaled between the worst and
+    /// the best of the selected mirrors, and the mirrors are sorted by the weighted average,
+    /// best first. Measures that are left out keep their default weight of 1, and the rate
+    /// and the latency are only measured if their weight is above 0.
+    #[arg(long, value_name = "weights", value_parser = quality::parse_weights)]
+    quality_weights: Option<QualityWeights>,
+
+    /// The architecture to generate the mirrorlist for, e.g. "x86_64", "aarch64" or
+    /// "armv7h". Architectures other than x86_64 use the repository layout of ports such
+    /// as Arch Linux ARM ("$arch/$repo") for the Server lines and for rating.
+    #[arg(
+        long,
+        default_value = "x86_64",
+        default_value_ifs([("distro", "archarm", "aarch64"), ("distro", "archlinux32", "i686")]),
+        value_name = "arch"
+    )]
+    arch: String,
+
+    /// The distribution to generate the mirrorlist for. For Manjaro, the mirror status is
+    /// retrieved from the Manjaro mirror status unless "--url" is given, and the Server
+    /// lines point to the branch given to "--branch" with the "$repo/$arch" layout. For
+    /// Artix, the mirrors are read from the Artix mirrorlist unless "--url" is given, and
+    /// sorted by rate unless "--sort" is given, as filters that rely on the mirror status
+    /// do not apply. The same goes for the built-in mirrors of Arch Linux ARM, which are
+    /// for aarch64 unless "--arch" is given, and for the mirrorlist of Arch Linux 32,
+    /// which is for i686 unless "--arch" is given, e.g. "i486" or "pentium4".
+    #[arg(long, default_value = "arch", value_name = "distro")]
+    distro: Distro,
+
+    /// The Manjaro branch to generate the mirrorlist for with "--distro manjaro". Mirrors
+    /// that are not in sync with the branch are 0% complete.
+    #[arg(long, default_value = "stable", value_name = "branch")]
+    branch: manjaro::Branch,
+
+    /// The path of a repository relative to the base URL of a mirror, using pacman's
+    /// "$repo" and "$arch" variables, e.g. "$repo/$arch". Overrides the layout of
+    /// "--distro" and "--arch" for the Server lines and for rating.
+    #[arg(long, value_name = "path")]
+    repo_layout: Option<String>,
+
+    /// The repository whose database is downloaded to rate mirrors, e.g. the unofficial
+    /// repository of "--mirror-source". Defaults to extra, or world for Artix.
+    #[arg(long, value_name = "repo")]
+    rate_repo: Option<String>,
+
+    /// The preferred order of protocols for hosts that serve the mirror over several
+    /// protocols, e.g. "https,http,rsync". The entries of such a host are grouped at the
+    /// position of its first entry and ordered by this preference. Protocols that are not
+    /// listed come last.
+    #[arg(long, value_delimiter = ',', value_name = "protocols")]
+    protocol_preference: Vec<Protocol>,
+
+    /// Only keep one entry per host, using the protocol preferred by
+    /// "--protocol-preference", or the first entry if no preference is given.
+    #[arg(long, default_value_t = false)]
+    unique_hosts: bool,
+
+    /// Instead of only returning mirrors in the autonomous systems given to "--asn", move
+    /// them to the top of the sorted list.
+    #[arg(
+        long,
+        default_value_t = false,
+        requires = "asn",
+        conflicts_with = "stream"
+    )]
+    prefer_asn: bool,
+
+    /// Check that the database of each mirror left after filtering can be retrieved, with a
+    /// HEAD request for HTTP(S) and a file listing for rsync, or a HEAD request over HTTP(S)
+    /// if rsync is not installed, and drop the mirrors that are unreachable, even if the
+    /// mirror status reports them as fine.
+    #[arg(long, default_value_t = false)]
+    verify: bool,
+
+    /// Check that each HTTP(S) mirror left after "--isos" serves the ISO of the latest
+    /// release found on any of them, and drop the mirrors that do not. Other protocols are
+    /// not verified.
+    #[arg(long, default_value_t = false, requires = "isos")]
+    verify_isos: bool,
+
+    /// Rate the mirrors left after filtering and drop those that did not serve their
+    /// database over HTTP/2 or later, which performs better for parallel downloads than
+    /// HTTP/1.x. rsync and FTP mirrors are dropped as well.
+    #[arg(long, default_value_t = false)]
+    require_http2: bool,
+
+    /// Attempt HTTP/3 when rating HTTPS mirrors, falling back to HTTP/2 or HTTP/1.1 for
+    /// mirrors that do not support it. "--info" shows which mirrors were rated over HTTP/3.
+    #[cfg(feature = "http3")]
+    #[arg(long, default_value_t = false)]
+    http3: bool,
+
+    /// Move the mirrors that were rated over HTTP/3 to the top of the sorted list.
+    #[cfg(feature = "http3")]
+    #[arg(
+        long,
+        default_value_t = false,
+        requires = "http3",
+        conflicts_with = "stream"
+    )]
+    prefer_http3: bool,
+
+    /// Show the selected mirrors in a terminal interface, rating them in the background,
+    /// to check, uncheck and reorder them by hand before the mirrorlist is written.
+    #[cfg(feature = "tui")]
+    #[arg(long, default_value_t = false, conflicts_with_all = ["stream", "explain"])]
+    tui: bool,
+
     /// Use n threads for rating mirrors. This option will speed up the rating step but the
     /// results will be inaccurate if the local bandwidth is saturated at any point during
     /// the operation. If rating takes too long without this option then you should
     /// probably apply more filters to reduce the number of rated servers before using this
-    /// option.
-    #[arg(long, default_value_t = 0)]
-    threads: usize,
+    /// option. With "auto", rating starts with one mirror at a time and rates more at the
+    /// same time for as long as the combined download rate keeps growing, up to 16.
+    #[arg(long, value_name = "n|auto", default_value = "0", value_parser = rate::parse_threads)]
+    threads: Threads,
+
+    /// Use n concurrent processes for rating rsync mirrors, independently of the limit for
+    /// HTTP(S) mirrors given by "--threads". FTP mirrors are rated with curl and count
+    /// towards the same limit. Defaults to the value of "--threads".
+    #[arg(long, value_name = "n")]
+    rsync_threads: Option<usize>,
+
+    /// Wait a random time of about the given number of milliseconds, between half and one
+    /// and a half times as long, between starting to rate or otherwise probe two mirrors.
+    /// This keeps concurrent ratings from starting in bursts and skewing each other on a
+    /// slow connection, and is gentler on the mirrors.
+    #[arg(long, value_name = "ms")]
+    rate_delay: Option<u64>,
+
+    /// Pass an extra argument to rsync when rating rsync mirrors, e.g.
+    /// "--rsync-extra-args=--bwlimit=1000". May be given multiple times.
+    #[arg(long, value_name = "arg", allow_hyphen_values = true, action = ArgAction::Append)]
+    rsync_extra_args: Vec<String>,
+
+    /// Blend the rate of each rated mirror with the rates measured in previous runs,
+    /// giving the new measurement the given weight above 0 and at most 1, e.g. "--smooth
+    /// 0.5", or 0.3 if no weight is given. This keeps a single noisy measurement from
+    /// reordering the mirrors. The rates are kept in the cache directory unless
+    /// "--no-cache" is given.
+    #[arg(
+        long,
+        value_name = "weight",
+        num_args = 0..=1,
+        default_missing_value = "0.3",
+        value_parser = history::parse_weight
+    )]
+    smooth: Option<f64>,
 
     /// Print mirror information instead of a mirror list. Filter options apply.
     #[arg(long, default_value_t = false)]
     info: bool,
 
+    /// Colorize the tables of "--info" and "--list-countries", e.g. to highlight stale
+    /// mirrors. Output written by "--save" is never colorized.
+    #[arg(long, value_name = "when", default_value = "auto")]
+    color: ColorChoice,
+
+    /// Print the mirror information of "--info" as a column-aligned table, a JSON document
+    /// or CSV instead of a detailed listing.
+    #[arg(long, value_name = "format", requires = "info")]
+    info_format: Option<ExportFormat>,
+
+    /// The output format of the selected mirrors.
+    #[arg(
+        long,
+        value_name = "format",
+        default_value = "mirrorlist",
+        conflicts_with = "info"
+    )]
+    format: OutputFormat,
+
+    /// Write each selected mirror using the given template instead of a pacman mirrorlist,
+    /// e.g. "Server = {url}$repo/os/$arch" or "{url}" for a plain list of URLs. The
+    /// placeholders {url}, {host}, {protocol}, {country}, {country_code}, {last_sync},
+    /// {delay}, {score}, {rate} (in KiB/s) and {rank} are replaced by the values of the
+    /// mirror. Use "{{" and "}}" for literal braces.
+    #[arg(long, value_name = "template", conflicts_with_all = ["info", "format"])]
+    template: Option<String>,
+
+    /// Like "--template", but read the template from the given file.
+    #[arg(long, value_name = "filepath", conflicts_with_all = ["info", "format", "template"])]
+    template_file: Option<PathBuf>,
+
+    /// Instead of a mirror list, print why each mirror was kept or which filter rejected
+    /// it. If a URL substring is given, only matching mirrors are reported.
+    #[arg(
+        long,
+        value_name = "url-substring",
+        num_args = 0..=1,
+        default_missing_value = ""
+    )]
+    explain: Option<String>,
+
+    /// Trace the mirrors whose URL contains the given string through the whole run,
+    /// printing their status fields, every filter decision, their rating and their final
+    /// rank to STDERR.
+    #[arg(long, value_name = "url")]
+    debug_mirror: Option<String>,
+
+    /// With "--sort rate", print each mirror as soon as its rating completes instead of
+    /// waiting for all ratings to finish. Mirrors are printed in the order their ratings
+    /// complete, which favors faster mirrors when rating with multiple threads. Mirrors
+    /// that cannot be rated are left out.
+    #[arg(
+        long,
+        default_value_t = false,
+        conflicts_with_all = [
+            "save", "info", "explain", "format", "template", "template_file", "diff",
+            "protocol_preference", "min_mirrors",
+        ]
+    )]
+    stream: bool,
+
     #[command(flatten)]
     filters: Filters,
 }
 
+impl RunOptions {
+    /// Whether the mirrors come from a plain mirrorlist without the statistics of a mirror
+    /// status, so filters that rely on them do not apply.
+    fn lists_mirrors_only(&self) -> bool {
+        self.input_mirrorlist.is_some() || self.mirror_source.is_some() || !self.distro.has_status()
+    }
+
+    /// The provider of the mirror status of "--distro" at `url`, or nothing for Arch Linux
+    /// ARM, whose mirrors are built in.
+    fn provider(&self, url: &str) -> Option<Box<Provider>> {
+        let repo_path = self.repo_path();
+        Some(match self.distro {
+            // Unsupported versions of the mirror status are warned about instead of
+            // rejected, unless "--strict-status" is given.
+            Distro::Arch => Box::new(ArchLinux::new(url).lenient(!self.strict_status)),
+            Distro::Manjaro => Box::new(manjaro::Manjaro::new(url, self.branch)),
+            Distro::Artix => Box::new(input::Mirrorlist::new(url, repo_path)),
+            Distro::ArchLinux32 => Box::new(input::Mirrorlist::generated(url, repo_path)),
+            Distro::ArchArm => return None,
+        })
+    }
+
+    /// The default name of the file the mirror status of "--distro" is cached in, or nothing
+    /// for Arch Linux ARM, whose mirrors are built in and never cached.
+    fn cache_file_name(&self) -> Option<&'static str> {
+        Some(match self.distro {
+            Distro::Arch => "mirrorstatus.json",
+            Distro::Manjaro => match self.branch {
+                manjaro::Branch::Stable => "manjaro-stable-status.json",
+                manjaro::Branch::Testing => "manjaro-testing-status.json",
+                manjaro::Branch::Unstable => "manjaro-unstable-status.json",
+            },
+            Distro::Artix => "artix-mirrorlist.json",
+            Distro::ArchLinux32 => "archlinux32-mirrorlist.json",
+            Distro::ArchArm => return None,
+        })
+    }
+
+    /// The path of a repository relative to the base URL of a mirror, using pacman's
+    /// `$repo` and `$arch` variables.
+    fn repo_path(&self) -> &str {
+        self.repo_layout
+            .as_deref()
+            .unwrap_or_else(|| arch::repo_path(self.distro, &self.arch))
+    }
+
+    /// The repository whose database is downloaded to rate mirrors.
+    fn rated_repo(&self) -> &str {
+        self.rate_repo
+            .as_deref()
+            .unwrap_or_else(|| self.distro.rated_repo())
+    }
+
+    /// The path of the database used for rating relative to the base URL of a mirror.
+    fn db_path(&self) -> String {
+        let repo = self.rated_repo();
+        let path = self
+            .repo_path()
+            .replace("$repo", repo)
+            .replace("$arch", &self.arch);
+        format!("{path}/{repo}.db")
+    }
+}
+
+/// A provider of the mirror status that can be shared between threads.
+type Provider = dyn MirrorProvider + Send + Sync;
+
 #[derive(Parser, Debug)]
 #[command(
     next_help_heading = "filters\n\nThe following filters are inclusive, i.e. the returned list will only contain mirrors for which all of the given conditions are met.\n"
@@ -130,8 +717,9 @@ struct Filters {
     #[arg(long, value_name = "n")]
     delay: Option<f32>,
 
-    /// Restrict mirrors to selected countries. Countries may be given by name or country
-    /// code, or a mix of both. The case is ignored. Multiple countries be selected using
+    /// Restrict mirrors to selected countries. Countries may be given by name or ISO 3166
+    /// country code, or a mix of both, including countries without any mirrors yet. The
+    /// case is ignored. Multiple countries be selected using
     /// commas (e.g. --country France,Germany) or by passing this option multiple times
     /// (e.g.  -c fr -c de). Use "--list-countries" to display a table of available
     /// countries along with their country codes. When sorting by country, this option may
@@ -151,6 +739,23 @@ struct Filters {
     #[arg(long, short, value_name = "country name or code", value_delimiter=',', action = ArgAction::Append)]
     country: Vec<String>,
 
+    /// Also include mirrors from the n countries nearest to each country given to
+    /// "--country", e.g. "--country lu --neighbors 3". The distances are measured between
+    /// the approximate population centers of the countries. Your own country is not
+    /// detected, so it has to be given with "--country".
+    #[arg(long, value_name = "n", requires = "country")]
+    neighbors: Option<usize>,
+
+    /// Restrict mirrors to the selected continents, e.g. "--continent EU,NA". Continents
+    /// may be given by code or name. Combined with "--country", mirrors have to match both.
+    #[arg(
+        long,
+        value_name = "continent",
+        value_delimiter = ',',
+        ignore_case = true
+    )]
+    continent: Vec<Continent>,
+
     /// Return the n fastest mirrors that meet the other criteria. Do not use this option
     /// without other filtering options.
     #[arg(long, short, value_name = "n")]
@@ -168,7 +773,7 @@ struct Filters {
     #[arg(long, short, value_name = "n")]
     latest: Option<usize>,
 
-    /// Limit the list to the n servers with the highest score.
+    /// Limit the list to the n servers with the best score, which is the lowest.
     #[arg(long, value_name = "n")]
     score: Option<usize>,
 
@@ -176,6 +781,16 @@ struct Filters {
     #[arg(long, short, value_name = "n")]
     number: Option<usize>,
 
+    /// Fail without writing or saving the mirrorlist if fewer than n mirrors are left
+    /// after filtering and rating. Use 0 to allow an empty mirrorlist.
+    #[arg(long, value_name = "n", default_value_t = 1)]
+    min_mirrors: usize,
+
+    /// Return at most n mirrors from the same country, so a single well-connected country
+    /// cannot take up the whole list. Mirrors without a country are not limited.
+    #[arg(long, value_name = "n")]
+    max_per_country: Option<usize>,
+
     /// Match one of the given protocols, e.g. "https" or "ftp". Multiple protocols may be
     /// selected using commas (e.g. "https,http") or by passing this option multiple times.
     #[arg(long, short, value_delimiter=',', value_name = "protocol", action = ArgAction::Append)]
@@ -197,154 +812,1176 @@ struct Filters {
     /// Only return mirrors that support IPv6.
     #[arg(long, default_value_t = false)]
     ipv6: bool,
+
+    /// Only return mirrors hosted in one of the given autonomous systems, e.g. the one of
+    /// your ISP or university network, as in "--asn AS3320,680". The AS of each mirror is
+    /// looked up with the whois service of Team Cymru.
+    #[arg(long, value_name = "asn", value_delimiter = ',', value_parser = asn::parse_asn)]
+    asn: Vec<u32>,
+
+    /// Retrieve the lastsync file of each HTTP(S) mirror and only return mirrors whose
+    /// actual last synchronization is at most n hours older than reported by the mirror
+    /// status, which can itself be hours old. Mirrors whose lastsync file cannot be
+    /// retrieved are excluded. Other protocols are not verified.
+    #[arg(long, value_name = "n")]
+    verify_sync: Option<f32>,
 }
 
-fn get_cache_file(name: Option<&str>) -> io::Result<PathBuf> {
-    let name = name.unwrap_or("mirrorstatus.json");
-    let base_dirs = BaseDirectories::new();
-    let cache_dir = base_dirs
-        .get_cache_home()
-        .unwrap_or_else(|| PathBuf::from("~/.cache"));
-    fs::create_dir_all(&cache_dir)?;
-    Ok(cache_dir.join(name))
+impl Filters {
+    /// The filters that apply to each mirror on its own.
+    fn mirror_filters(&self) -> filter::Filters {
+        filter::Filters {
+            age: self.age,
+            delay: self.delay,
+            completion_percent: self.completion_percent,
+            country: self.country.clone(),
+            continent: self
+                .continent
+                .iter()
+                .map(|&continent| continent.into())
+                .collect(),
+            protocol: self.protocol.clone(),
+            include: self.include.clone(),
+            exclude: self.exclude.clone(),
+            isos: self.isos,
+            ipv4: self.ipv4,
+            ipv6: self.ipv6,
+        }
+    }
 }
 
-/// Retrieve the mirror status JSON object. The downloaded data will be cached locally and
-/// re-used within the cache timeout period. Returns the object and the local cache's
-/// modification time.
+/// The directory containing the cache file, which is created if it does not exist.
+fn get_cache_dir(run_options: &RunOptions) -> io::Result<PathBuf> {
+    let cache_dir = match (&run_options.cache_file, &run_options.cache_dir) {
+        (Some(cache_file), _) => cache_file
+            .parent()
+            .map_or_else(PathBuf::new, Path::to_path_buf),
+        (None, Some(cache_dir)) => cache_dir.clone(),
+        // This is $XDG_CACHE_HOME or ~/.cache on Linux, ~/Library/Caches on macOS and
+        // %LOCALAPPDATA% on Windows.
+        (None, None) => BaseDirs::new()
+            .map(|base_dirs| base_dirs.cache_dir().to_path_buf())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no cache directory found"))?,
+    };
+    if !cache_dir.as_os_str().is_empty() {
+        fs::create_dir_all(&cache_dir)?;
+    }
+    Ok(cache_dir)
+}
+
+fn get_cache_file(run_options: &RunOptions, file_name: &str) -> io::Result<PathBuf> {
+    let cache_dir = get_cache_dir(run_options)?;
+    Ok(match &run_options.cache_file {
+        Some(cache_file) => cache_file.clone(),
+        None => cache_dir.join(file_name),
+    })
+}
+
+/// Lock the cache directory for the rest of the run unless "--no-lock" is given. Runs
+/// continue without the lock if it cannot be taken, as they do without a cache.
+async fn lock_cache_dir(run_options: &RunOptions) -> Option<File> {
+    if run_options.no_lock {
+        return None;
+    }
+    let path = get_cache_dir(run_options)
+        .map(|cache_dir| cache_dir.join(lock::LOCK_FILE_NAME))
+        .inspect_err(|err| tracing::warn!("not locking the cache directory: {err}"))
+        .ok()?;
+    lock::acquire(&path)
+        .await
+        .inspect_err(|err| tracing::warn!("not locking the cache directory: {err:#}"))
+        .ok()
+}
+
+/// Retrieve the mirror status of a provider. The normalized status will be cached locally
+/// and re-used within the cache timeout period, or regardless of its age with "--offline".
+/// Returns the status and the local cache's modification time.
+#[tracing::instrument(name = "fetch", skip_all)]
 async fn get_mirror_status(
     http_client: &reqwest::Client,
     run_options: &RunOptions,
-    url: &str,
+    provider: &Provider,
     cache_file_path: Option<PathBuf>,
 ) -> Result<(Status, SystemTime)> {
-    let Some(cache_file_path) = cache_file_path else {
-        let loaded = http_client.get(url).send().await?.json().await?;
-        return Ok((loaded, SystemTime::now()));
+    let url = provider.url();
+    let decode = |data: &[u8]| {
+        let status = provider.parse(data).map_err(|err| anyhow::anyhow!(err))?;
+        if !status.is_supported() {
+            tracing::warn!(
+                "the mirror status is in version {}, but only version {} is supported; its \
+                 format may have changed",
+                status.version,
+                Status::VERSION
+            );
+        }
+        Ok(status)
+    };
+    if let Some(local) = read_local_status(url, decode)? {
+        return Ok(local);
+    }
+    let cache = cache_file_path.map(|path| {
+        CachedStatus::new(http_client, path).ttl(Duration::from_secs(run_options.cache_timeout))
+    });
+    if run_options.offline {
+        let Some(cache) = cache else {
+            anyhow::bail!("--offline requires a cache file");
+        };
+        let no_cache = || {
+            format!(
+                "no cached mirror status available at {}",
+                cache.path().display()
+            )
+        };
+        return cache
+            .cached()
+            .with_context(no_cache)?
+            .with_context(no_cache);
+    }
+
+    let Some(cache) = cache else {
+        let body = send(run_options, || http_client.get(url))
+            .await?
+            .bytes()
+            .await?;
+        return Ok((decode(&body)?, SystemTime::now()));
     };
 
-    let mtime = cache_file_path
-        .metadata()
-        .ok()
-        .and_then(|meta| meta.modified().ok());
-    let is_valid = mtime
-        .and_then(|mtime| SystemTime::now().duration_since(mtime).ok())
-        .filter(|elapsed| elapsed.as_secs() <= run_options.cache_timeout)
-        .is_some();
-    if let Some(mtime) = mtime {
-        if is_valid {
-            let loaded = serde_json::from_reader(File::open(cache_file_path)?)?;
-            return Ok((loaded, mtime));
-        }
-    }
-    let loaded = http_client.get(url).send().await?.json().await?;
-    let to_write = serde_json::to_string_pretty(&loaded)?;
-    fs::write(cache_file_path, to_write)?;
+    if let Some(cached) = cache.fresh()? {
+        tracing::debug!("the cached mirror status is still valid");
+        return Ok(cached);
+    }
+
+    // Ask the server to only send the mirror status if it changed since it was cached.
+    let validators_path = CacheValidators::path(cache.path());
+    let validators = if cache.modified().is_some() {
+        CacheValidators::load(&validators_path)
+    } else {
+        CacheValidators::default()
+    };
+    let response = send(run_options, || {
+        let mut request = http_client.get(url);
+        if let Some(etag) = &validators.etag {
+            request = request.header(header::IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &validators.last_modified {
+            request = request.header(header::IF_MODIFIED_SINCE, last_modified);
+        }
+        request
+    })
+    .await?;
+    if response.status() == StatusCode::NOT_MODIFIED {
+        tracing::debug!("the mirror status has not changed since it was cached");
+        let now = SystemTime::now();
+        File::options()
+            .write(true)
+            .open(cache.path())?
+            .set_modified(now)?;
+        let (loaded, _) = cache
+            .cached()?
+            .context("the cached mirror status was removed")?;
+        return Ok((loaded, now));
+    }
+
+    let validators = CacheValidators::from_headers(response.headers());
+    let loaded = decode(&response.bytes().await?)?;
+    cache.store(&loaded)?;
+    fs::write(validators_path, serde_json::to_vec(&validators)?)?;
     Ok((loaded, SystemTime::now()))
 }
 
+/// Read the mirror status from STDIN for "-" or from the file of a "file://" URL, e.g. a
+/// snapshot downloaded in advance. Other URLs result in nothing.
+fn read_local_status(
+    url: &str,
+    decode: impl Fn(&[u8]) -> Result<Status>,
+) -> Result<Option<(Status, SystemTime)>> {
+    if url == "-" {
+        let mut body = Vec::new();
+        io::Read::read_to_end(&mut io::stdin().lock(), &mut body)?;
+        let status = decode(&body).context("invalid mirror status on STDIN")?;
+        return Ok(Some((status, SystemTime::now())));
+    }
+    let Some(url) = Url::parse(url).ok().filter(|url| url.scheme() == "file") else {
+        return Ok(None);
+    };
+    let path = url
+        .to_file_path()
+        .map_err(|()| anyhow::anyhow!("{url} does not refer to a local file"))?;
+    let body = fs::read(&path).with_context(|| format!("failed to read {}", path.display()))?;
+    let mtime = fs::metadata(&path)?.modified()?;
+    let status =
+        decode(&body).with_context(|| format!("invalid mirror status in {}", path.display()))?;
+    Ok(Some((status, mtime)))
+}
+
+/// Send a request for the mirror status, retrying transient failures up to "--retries"
+/// times.
+async fn send(
+    run_options: &RunOptions,
+    request: impl Fn() -> reqwest::RequestBuilder,
+) -> Result<reqwest::Response> {
+    let is_transient = |err: &reqwest::Error| {
+        err.is_connect()
+            || err.is_timeout()
+            || err.status().is_some_and(|status| {
+                status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+            })
+    };
+    let response = reflector_lib::retry::retry(
+        run_options.retries,
+        "retrieving the mirror status",
+        is_transient,
+        || async { request().send().await?.error_for_status() },
+    )
+    .await?;
+    Ok(response)
+}
+
+/// The response headers of the cached mirror status that allow conditional requests.
+#[derive(Default, Serialize, Deserialize)]
+struct CacheValidators {
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+impl CacheValidators {
+    /// The file next to the cache file in which the validators are stored.
+    fn path(cache_file_path: &Path) -> PathBuf {
+        let mut path = cache_file_path.as_os_str().to_owned();
+        path.push(".headers");
+        PathBuf::from(path)
+    }
+
+    /// Load the stored validators. Missing or invalid files result in no validators.
+    fn load(path: &Path) -> Self {
+        fs::read(path)
+            .ok()
+            .and_then(|content| serde_json::from_slice(&content).ok())
+            .unwrap_or_default()
+    }
+
+    fn from_headers(headers: &HeaderMap) -> Self {
+        let get = |name| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_owned)
+        };
+        Self {
+            etag: get(header::ETAG),
+            last_modified: get(header::LAST_MODIFIED),
+        }
+    }
+}
+
 #[derive(PartialEq, Eq, Hash)]
 struct Country<'a> {
     country: &'a str,
     code: &'a str,
 }
 
-fn count_countries<'a>(
-    mirrors: impl IntoIterator<Item = &'a Mirror>,
-) -> HashMap<Country<'a>, usize> {
-    let mut counts = HashMap::new();
+/// Aggregated statistics of the mirrors of a single country.
+#[derive(Serialize)]
+struct CountryStats<'a> {
+    country: &'a str,
+    code: &'a str,
+    count: usize,
+    protocols: BTreeSet<Protocol>,
+    median_score: Option<f64>,
+    /// The median delay in seconds.
+    median_delay: Option<f64>,
+}
+
+fn median(mut values: Vec<f64>) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(f64::total_cmp);
+    let mid = values.len() / 2;
+    if values.len() % 2 == 0 {
+        Some(f64::midpoint(values[mid - 1], values[mid]))
+    } else {
+        Some(values[mid])
+    }
+}
+
+/// Collect per-country statistics, sorted by country code.
+fn country_stats<'a>(mirrors: impl IntoIterator<Item = &'a Mirror>) -> Vec<CountryStats<'a>> {
+    let mut grouped = HashMap::<Country<'a>, Vec<&'a Mirror>>::new();
     for mirror in mirrors {
         if mirror.country_code.is_empty() {
             continue;
         }
-        counts
+        grouped
             .entry(Country {
                 country: mirror.country.as_ref(),
                 code: mirror.country_code.as_ref(),
             })
-            .and_modify(|e| *e += 1)
-            .or_insert(1);
+            .or_default()
+            .push(mirror);
     }
-    counts
+    let mut stats = grouped
+        .into_iter()
+        .map(|(country, mirrors)| CountryStats {
+            country: country.country,
+            code: country.code,
+            count: mirrors.len(),
+            protocols: mirrors.iter().map(|mirror| mirror.protocol).collect(),
+            median_score: median(mirrors.iter().filter_map(|mirror| mirror.score).collect()),
+            median_delay: median(
+                mirrors
+                    .iter()
+                    .filter_map(|mirror| mirror.delay.map(f64::from))
+                    .collect(),
+            ),
+        })
+        .collect::<Vec<_>>();
+    stats.sort_by(|c1, c2| c1.code.cmp(c2.code));
+    stats
 }
 
-struct Metadata<'a> {
-    when: Timestamp,
-    origin: &'a str,
-    retrieved: SystemTime,
+fn build_http_client(run_options: &RunOptions) -> Result<reqwest::Client> {
+    let read =
+        |path: &Path| fs::read(path).with_context(|| format!("failed to read {}", path.display()));
+    let mut fetcher = StatusFetcher::new()
+        .timeout(Duration::from_secs(run_options.download_timeout))
+        .connect_timeout(Duration::from_secs(run_options.connection_timeout))
+        .user_agent(concat!("reflector/", env!("CARGO_PKG_VERSION")));
+    if let Some(proxy) = &run_options.proxy {
+        fetcher = fetcher.proxy(proxy.as_str());
+    }
+    let mut builder = fetcher
+        .client_builder()?
+        .tls_danger_accept_invalid_certs(run_options.insecure);
+    // reqwest only supports HTTP/3 with rustls.
+    #[cfg(feature = "http3")]
+    if run_options.http3 {
+        builder = builder.tls_backend_rustls();
+    }
+    if let Some(cacert) = &run_options.cacert {
+        let certs = reqwest::Certificate::from_pem_bundle(&read(cacert)?)
+            .with_context(|| format!("invalid certificates in {}", cacert.display()))?;
+        builder = builder.tls_certs_merge(certs);
+    }
+    if let (Some(cert), Some(key)) = (&run_options.client_cert, &run_options.client_key) {
+        let identity = reqwest::Identity::from_pkcs8_pem(&read(cert)?, &read(key)?)
+            .context("invalid client certificate or key")?;
+        builder = builder.identity(identity);
+    }
+    Ok(builder.build()?)
 }
 
 async fn run(options: &Cli) -> anyhow::Result<()> {
-    let http_client = reqwest::Client::builder()
-        .timeout(Duration::from_secs(options.run.download_timeout))
-        .connect_timeout(Duration::from_secs(options.run.connection_timeout))
-        .build()?;
-    let cache_file = get_cache_file(None).ok();
+    match &options.command {
+        Some(Command::Restore(restore_options)) => {
+            let _lock = lock_cache_dir(&options.run).await;
+            save::restore(restore_options, &options.run)
+        }
+        Some(Command::Rate(rate_options)) => {
+            let http_client = build_http_client(&options.run)?;
+            rate::rate_urls(rate_options, &options.run, &http_client, io::stdout()).await
+        }
+        Some(Command::Compare(compare_options)) => {
+            let http_client = build_http_client(&options.run)?;
+            rate::compare(compare_options, &options.run, &http_client, io::stdout()).await
+        }
+        Some(Command::Bench(bench_options)) => {
+            let _lock = lock_cache_dir(&options.run).await;
+            bench(options, bench_options).await
+        }
+        Some(Command::Doctor) => {
+            let http_client = build_http_client(&options.run)?;
+            doctor::doctor(&options.run, &http_client, &options.url, io::stdout()).await
+        }
+        Some(Command::GenerateMan) => {
+            clap_mangen::Man::new(config::command(Cli::command()).name("reflector"))
+                .render(&mut io::stdout())?;
+            Ok(())
+        }
+        None => {
+            let _lock = lock_cache_dir(&options.run).await;
+            generate(options).await
+        }
+    }
+}
+
+/// Parse the template given to "--template" or "--template-file", if any.
+fn load_template(run_options: &RunOptions) -> Result<Option<template::Template>> {
+    let template = match (&run_options.template, &run_options.template_file) {
+        (Some(template), _) => Some(template.parse()?),
+        (None, Some(path)) => Some(
+            fs::read_to_string(path)
+                .with_context(|| format!("failed to read {}", path.display()))?
+                .trim_end_matches('\n')
+                .parse()
+                .with_context(|| format!("invalid template in {}", path.display()))?,
+        ),
+        (None, None) => None,
+    };
+    Ok(template)
+}
+
+/// The cache file to use, or nothing if the mirror status should not be cached.
+fn cache_file_path(run_options: &RunOptions) -> Option<PathBuf> {
+    if run_options.no_cache {
+        return None;
+    }
+    let file_name = run_options.cache_file_name()?;
+    get_cache_file(run_options, file_name)
+        .inspect(|cache_file| tracing::info!("using the cache file {}", cache_file.display()))
+        .inspect_err(|err| tracing::warn!("not caching the mirror status: {err}"))
+        .ok()
+}
+
+/// Retrieve, filter and sort the mirror status and benchmark the top HTTP(S) mirrors.
+async fn bench(options: &Cli, bench_options: &BenchOptions) -> Result<()> {
+    let http_client = build_http_client(&options.run)?;
+    let (mut status, _) = retrieve_status(options, &http_client)
+        .await
+        .exit_with(Exit::Status)?;
+    country::validate(&options.run.filters.country, &status).exit_with(Exit::Usage)?;
+    let statistics = !options.run.lists_mirrors_only();
+    let filters = options.run.filters.mirror_filters();
+    let mut rejected = filter_status(&filters, &mut status, statistics);
+    status
+        .urls
+        .retain(|mirror| matches!(mirror.protocol, Protocol::Http | Protocol::Https));
+    let mut ratings = Ratings::default();
+    select_mirrors(
+        &options.run,
+        &http_client,
+        &mut status,
+        &mut rejected,
+        &mut ratings,
+        false,
+    )
+    .await?;
+    status.urls.truncate(bench_options.mirrors);
+    bench::bench(
+        bench_options,
+        &options.run,
+        &http_client,
+        &status.urls,
+        io::stdout(),
+    )
+    .await
+}
+
+/// Retrieve the mirror status, or read the mirrors of "--input-mirrorlist".
+async fn retrieve_status(
+    options: &Cli,
+    http_client: &reqwest::Client,
+) -> Result<(Status, SystemTime)> {
+    notify::status("Retrieving the mirror status");
+    if let Some(path) = &options.run.input_mirrorlist {
+        return input::load(path, options.run.repo_path());
+    }
+    if let Some(source) = &options.run.mirror_source {
+        return load_mirror_source(&options.run, http_client, source).await;
+    }
+    fetch_status(options, http_client).await
+}
+
+/// Read the mirrors of "--mirror-source" from a file or an HTTP(S) URL. The list is not
+/// cached.
+async fn load_mirror_source(
+    run_options: &RunOptions,
+    http_client: &reqwest::Client,
+    source: &str,
+) -> Result<(Status, SystemTime)> {
+    let (content, mtime) = if source.starts_with("http://") || source.starts_with("https://") {
+        let content = send(run_options, || http_client.get(source))
+            .await?
+            .text()
+            .await?;
+        (content, SystemTime::now())
+    } else {
+        let content =
+            fs::read_to_string(source).with_context(|| format!("failed to read {source}"))?;
+        (content, fs::metadata(source)?.modified()?)
+    };
+    let status = input::parse_source(
+        &content,
+        run_options.repo_path(),
+        Timestamp::try_from(mtime)?,
+    )
+    .with_context(|| format!("invalid mirror source {source}"))?;
+    Ok((status, mtime))
+}
+
+/// Retrieve the mirror status of the distribution given to "--distro".
+async fn fetch_status(
+    options: &Cli,
+    http_client: &reqwest::Client,
+) -> Result<(Status, SystemTime)> {
+    let run_options = &options.run;
+    let Some(provider) = run_options.provider(&options.url) else {
+        return Ok((archarm::status(Timestamp::now()), SystemTime::now()));
+    };
+    let cache_file = cache_file_path(run_options);
+    get_mirror_status(http_client, run_options, provider.as_ref(), cache_file).await
+}
+
+/// Retrieve, filter and sort the mirror status and write the resulting mirrorlist.
+async fn generate(options: &Cli) -> anyhow::Result<()> {
+    let started = Instant::now();
+    let template = load_template(&options.run)?;
+    let http_client = build_http_client(&options.run)?;
+
     let when = Timestamp::now();
-    let (mut status, mtime) =
-        get_mirror_status(&http_client, &options.run, &options.url, cache_file).await?;
+    let (mut status, mtime) = retrieve_status(options, &http_client)
+        .await
+        .exit_with(Exit::Status)?;
+    country::validate(&options.run.filters.country, &status).exit_with(Exit::Usage)?;
+    let statistics = !options.run.lists_mirrors_only();
 
     if options.list_countries {
-        list_countries(&status);
+        return list_countries(options, &mut status, io::stdout());
+    }
+
+    let trace = options
+        .run
+        .debug_mirror
+        .as_deref()
+        .map(|pattern| debug::MirrorTrace::new(pattern, &status));
+    if let Some(trace) = &trace {
+        trace.report_filters(
+            &options.run.filters.mirror_filters(),
+            when,
+            statistics,
+            io::stderr(),
+        )?;
+    }
+
+    notify::status(&format!("Filtering {} mirrors", status.urls.len()));
+    let filters = options.run.filters.mirror_filters();
+    let mut rejected = filter_status(&filters, &mut status, statistics);
+
+    let metadata = Metadata {
+        when,
+        command: Some(std::env::args().collect::<Vec<_>>().join(" ")),
+        origin: options
+            .run
+            .input_mirrorlist
+            .as_deref()
+            .or(options.run.mirror_source.as_deref())
+            .unwrap_or(&options.url),
+        retrieved: Timestamp::try_from(mtime).unwrap_or(when),
+        repo_path: options.run.repo_path(),
+    };
+
+    let mut ratings = Ratings::default();
+    let streaming = options.run.stream && options.run.sort == Some(SortType::Rate);
+    select_mirrors(
+        &options.run,
+        &http_client,
+        &mut status,
+        &mut rejected,
+        &mut ratings,
+        streaming,
+    )
+    .await?;
+    if streaming {
+        stream_rated(
+            &options.run,
+            &http_client,
+            &metadata,
+            &mut status,
+            &mut rejected,
+            &mut ratings,
+        )
+        .await?;
+    }
+
+    tracing::info!(
+        phase = "filter",
+        "{}",
+        funnel_summary(&rejected, status.urls.len())
+    );
+
+    if let Some(trace) = &trace {
+        trace.report_result(&status, &rejected, &ratings, io::stderr())?;
+    }
+
+    if let Some(path) = &options.run.rate_report {
+        save_rate_report(path, &status, &rejected, &ratings)?;
+    }
+
+    if let Some(pattern) = options.run.explain.as_deref() {
+        explain(&status, &rejected, pattern, io::stdout())?;
         return Ok(());
     }
+    check_min_mirrors(&options.run.filters, &status)?;
+    if !streaming {
+        let output = render(
+            &options.run,
+            template.as_ref(),
+            &metadata,
+            &status,
+            &ratings,
+        )?;
+        write_output(&options.run, &status, &output)?;
+    }
+
+    if let Some(path) = &options.run.metrics_file {
+        let mirror_list = mirror_list(&metadata, &status, &ratings);
+        metrics::save(path, &mirror_list, started.elapsed())
+            .with_context(|| format!("failed to write metrics to {}", path.display()))
+            .exit_with(Exit::Write)?;
+    }
+    Ok(())
+}
+
+/// Fail if fewer mirrors than "--min-mirrors" requires are left.
+fn check_min_mirrors(filters: &Filters, status: &Status) -> Result<()> {
+    if status.urls.len() < filters.min_mirrors {
+        return Err(Failure {
+            exit: Exit::TooFewMirrors,
+            error: anyhow::anyhow!(
+                "{} mirrors are left after filtering, but --min-mirrors requires {}",
+                status.urls.len(),
+                filters.min_mirrors
+            ),
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// Print the changes of "--diff", and save the output or print it to STDOUT.
+fn write_output(run_options: &RunOptions, status: &Status, output: &[u8]) -> Result<()> {
+    if let Some(path) = &run_options.diff {
+        let existing = match fs::read_to_string(path) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+            result => result.with_context(|| format!("failed to read {}", path.display()))?,
+        };
+        diff::write_diff(&existing, &String::from_utf8_lossy(output), io::stdout())?;
+    }
+
+    if let Some(path) = run_options.save.as_ref() {
+        notify::status(&format!("Saving the mirrorlist to {path}"));
+        save::save(Path::new(path), status, output, run_options).exit_with(Exit::Write)?;
+    } else if run_options.diff.is_none() {
+        io::stdout().write_all(output).exit_with(Exit::Write)?;
+    }
+
+    notify::status(&format!("Selected {} mirrors", status.urls.len()));
+    Ok(())
+}
+
+/// Render the selected mirrors in the requested output format.
+fn render(
+    run_options: &RunOptions,
+    template: Option<&template::Template>,
+    metadata: &Metadata,
+    status: &Status,
+    ratings: &Ratings,
+) -> Result<Vec<u8>> {
+    // Colors only make sense when the output is printed to the terminal.
+    let colored =
+        run_options.color.enabled() && run_options.save.is_none() && run_options.diff.is_none();
+    let mut output = Vec::new();
+    if run_options.info {
+        match run_options.info_format {
+            None => print_mirror_info(status, ratings, metadata, colored, &mut output)?,
+            Some(ExportFormat::Json) => schema::write_json(
+                &mut output,
+                "mirrorlist",
+                &mirror_list(metadata, status, ratings),
+            )?,
+            Some(format) => {
+                write_info_table(
+                    format,
+                    &mirror_list(metadata, status, ratings),
+                    colored,
+                    &mut output,
+                )?;
+            }
+        }
+    } else if let Some(template) = template {
+        template.render(status, ratings, &mut output)?;
+    } else if run_options.format == OutputFormat::Json {
+        schema::write_json(
+            &mut output,
+            "mirrorlist",
+            &mirror_list(metadata, status, ratings),
+        )?;
+    } else {
+        mirrorlist_writer(metadata, status).write(&mut output, &status.urls)?;
+    }
+    Ok(output)
+}
+
+/// Apply the "--latest", "--score", "--fastest" and "--number" limits and sort the
+/// remaining mirrors. With `defer_rating`, sorting by rate and the "--number" limit are
+/// left to [`stream_rated`].
+async fn select_mirrors(
+    run_options: &RunOptions,
+    http_client: &reqwest::Client,
+    status: &mut Status,
+    rejected: &mut Vec<(Mirror, Rejection)>,
+    ratings: &mut Ratings,
+    defer_rating: bool,
+) -> Result<()> {
+    let in_asn = if run_options.filters.asn.is_empty() {
+        HashSet::new()
+    } else {
+        select_asn(run_options, status, rejected).await?
+    };
+
+    if let Some(tolerance) = run_options.filters.verify_sync {
+        verify_sync(run_options, http_client, status, tolerance, rejected).await;
+    }
+
+    if run_options.verify {
+        verify_reachable(run_options, http_client, status, rejected).await;
+    }
 
-    filter_status(&options.run.filters, &mut status);
+    if run_options.verify_isos {
+        verify_isos(run_options, http_client, status, rejected).await;
+    }
 
-    if let Some(n) = options.run.filters.latest {
+    if run_options.require_http2 {
+        require_http2(run_options, http_client, status, ratings, rejected).await;
+    }
+
+    if let Some(n) = run_options.filters.latest {
         if n > 0 {
-            sort_status(SortType::Age, &options.run, &http_client, &mut status).await;
-            status.urls.truncate(n);
+            sort_status(SortType::Age, run_options, http_client, status, ratings).await;
+            truncate_status(status, n, "--latest", rejected);
         }
     }
 
-    if let Some(n) = options.run.filters.score {
+    if let Some(n) = run_options.filters.score {
         if n > 0 {
-            sort_status(SortType::Score, &options.run, &http_client, &mut status).await;
-            status.urls.truncate(n);
+            sort_status(SortType::Score, run_options, http_client, status, ratings).await;
+            truncate_status(status, n, "--score", rejected);
         }
     }
 
-    if let Some(n) = options.run.filters.fastest {
+    if let Some(n) = run_options.filters.fastest {
         if n > 0 {
-            sort_status(SortType::Rate, &options.run, &http_client, &mut status).await;
-            status.urls.truncate(n);
+            sort_status(SortType::Rate, run_options, http_client, status, ratings).await;
+            truncate_status(status, n, "--fastest", rejected);
         }
-    } else if let Some(sort_type) = options.run.sort {
-        if sort_type != SortType::Rate {
-            sort_status(sort_type, &options.run, &http_client, &mut status).await;
+    } else if let Some(sort_type) = run_options.sort {
+        if !defer_rating {
+            sort_status(sort_type, run_options, http_client, status, ratings).await;
         }
     }
 
-    if let Some(n) = options.run.filters.number {
-        status.urls.truncate(n);
+    #[cfg(feature = "http3")]
+    if run_options.prefer_http3 {
+        status.urls.sort_by_key(|mirror| {
+            let rating = ratings.get(&mirror.url);
+            rating.and_then(|rating| rating.http_version) != Some(reqwest::Version::HTTP_3)
+        });
+    }
+    if run_options.prefer_asn {
+        status
+            .urls
+            .sort_by_key(|mirror| !in_asn.contains(&mirror.url));
+    }
+    if !defer_rating {
+        limit_mirrors(run_options, status, rejected);
+    }
+    #[cfg(feature = "tui")]
+    if run_options.tui {
+        choose_mirrors(run_options, http_client, status, rejected, ratings).await?;
+    }
+    Ok(())
+}
+
+/// Look up the autonomous systems of the mirrors and return the URLs of those in one given
+/// to "--asn". Without "--prefer-asn", all other mirrors are rejected.
+async fn select_asn(
+    run_options: &RunOptions,
+    status: &mut Status,
+    rejected: &mut Vec<(Mirror, Rejection)>,
+) -> Result<HashSet<Url>> {
+    let asns = asn::lookup(status, Duration::from_secs(run_options.download_timeout))
+        .await
+        .context("failed to look up the autonomous systems of the mirrors")?;
+    let asn_of = |mirror: &Mirror| asns.get(mirror.url.host_str().unwrap_or_default()).copied();
+    let in_asn = status
+        .urls
+        .iter()
+        .filter(|mirror| asn_of(mirror).is_some_and(|asn| run_options.filters.asn.contains(&asn)))
+        .map(|mirror| mirror.url.clone())
+        .collect::<HashSet<_>>();
+    if !run_options.prefer_asn {
+        for mirror in std::mem::take(&mut status.urls) {
+            if in_asn.contains(&mirror.url) {
+                status.urls.push(mirror);
+            } else {
+                let asn = asn_of(&mirror);
+                rejected.push((mirror, Rejection::Asn(asn)));
+            }
+        }
     }
+    Ok(in_asn)
+}
 
-    let metadata = Metadata {
-        when,
-        origin: options.url.as_ref(),
-        retrieved: mtime,
-    };
+/// Reject the mirrors whose lastsync file is older than their reported last
+/// synchronization by more than `tolerance` hours, or could not be retrieved.
+async fn verify_sync(
+    run_options: &RunOptions,
+    http_client: &reqwest::Client,
+    status: &mut Status,
+    tolerance: f32,
+    rejected: &mut Vec<(Mirror, Rejection)>,
+) {
+    let tolerance = SignedDuration::from_secs_f32(tolerance * 3600.0);
+    let last_syncs = rate::fetch_last_syncs(run_options, http_client, status).await;
+    for mirror in std::mem::take(&mut status.urls) {
+        let rejection = match (last_syncs.get(&mirror.url), mirror.last_sync) {
+            (Some(None), _) => Some(Rejection::LastSync(None)),
+            (Some(Some(actual)), Some(reported)) => {
+                let lag = reported.duration_since(*actual);
+                (lag > tolerance).then_some(Rejection::LastSync(Some(lag)))
+            }
+            // Mirrors that were not verified are kept.
+            (None, _) | (Some(Some(_)), None) => None,
+        };
+        match rejection {
+            Some(rejection) => rejected.push((mirror, rejection)),
+            None => status.urls.push(mirror),
+        }
+    }
+}
+
+/// Reject the mirrors whose database could not be retrieved.
+async fn verify_reachable(
+    run_options: &RunOptions,
+    http_client: &reqwest::Client,
+    status: &mut Status,
+    rejected: &mut Vec<(Mirror, Rejection)>,
+) {
+    let mut errors = rate::check_reachable(run_options, http_client, status).await;
+    for mirror in std::mem::take(&mut status.urls) {
+        match errors.remove(&mirror.url) {
+            Some(err) => rejected.push((mirror, Rejection::Unreachable(err))),
+            None => status.urls.push(mirror),
+        }
+    }
+}
+
+/// Reject the HTTP(S) mirrors that do not serve the ISO of the latest release.
+async fn verify_isos(
+    run_options: &RunOptions,
+    http_client: &reqwest::Client,
+    status: &mut Status,
+    rejected: &mut Vec<(Mirror, Rejection)>,
+) {
+    let versions = rate::fetch_iso_versions(run_options, http_client, status).await;
+    let latest = versions
+        .values()
+        .filter_map(|version| version.as_ref().ok())
+        .max()
+        .cloned();
+    tracing::info!(
+        "the latest ISO release is {}",
+        latest.as_deref().unwrap_or("unknown")
+    );
+    for mirror in std::mem::take(&mut status.urls) {
+        let rejection = match versions.get(&mirror.url) {
+            Some(Err(err)) => Some(Rejection::NoCurrentIso(err.clone())),
+            Some(Ok(version)) if Some(version) != latest.as_ref() => Some(Rejection::NoCurrentIso(
+                format!("serves the release {version}"),
+            )),
+            // Mirrors that were not verified are kept.
+            Some(Ok(_)) | None => None,
+        };
+        match rejection {
+            Some(rejection) => rejected.push((mirror, rejection)),
+            None => status.urls.push(mirror),
+        }
+    }
+}
+
+/// Rate the HTTP(S) mirrors and reject those whose rating response used HTTP/1.x, along
+/// with all other mirrors.
+async fn require_http2(
+    run_options: &RunOptions,
+    http_client: &reqwest::Client,
+    status: &mut Status,
+    ratings: &mut Ratings,
+    rejected: &mut Vec<(Mirror, Rejection)>,
+) {
+    for mirror in std::mem::take(&mut status.urls) {
+        if matches!(mirror.protocol, Protocol::Http | Protocol::Https) {
+            status.urls.push(mirror);
+        } else {
+            rejected.push((mirror, Rejection::HttpVersion(None)));
+        }
+    }
+    rate_status(run_options, http_client, status, ratings, &mut |_, _| {}).await;
+    for mirror in std::mem::take(&mut status.urls) {
+        match ratings
+            .get(&mirror.url)
+            .and_then(|rating| rating.http_version)
+        {
+            Some(version) if version >= reqwest::Version::HTTP_2 => status.urls.push(mirror),
+            version => {
+                let version = version.map(|version| format!("{version:?}"));
+                rejected.push((mirror, Rejection::HttpVersion(version)));
+            }
+        }
+    }
+}
+
+/// Apply the options that limit the sorted list: "--unique-hosts", "--max-per-country" and
+/// "--number".
+fn limit_mirrors(
+    run_options: &RunOptions,
+    status: &mut Status,
+    rejected: &mut Vec<(Mirror, Rejection)>,
+) {
+    group_hosts(
+        status,
+        &run_options.protocol_preference,
+        run_options.unique_hosts,
+        rejected,
+    );
+    if let Some(n) = run_options.filters.max_per_country {
+        cap_per_country(status, n, "--max-per-country", rejected);
+    }
+    if let Some(n) = run_options.filters.number {
+        truncate_status(status, n, "--number", rejected);
+    }
+}
 
-    match (options.run.info, options.run.save.as_ref()) {
-        (true, Some(path)) => {
-            File::create(path).and_then(move |file| print_mirror_info(&status, file))?;
+/// Rate the mirrors and print a Server line to STDOUT as soon as each rating completes,
+/// applying "--unique-hosts", "--max-per-country" and "--number" as they are printed.
+/// Since the ratings run concurrently, faster mirrors tend to finish first. Afterwards,
+/// `status` holds the printed mirrors sorted by rate, and the others are rejected.
+async fn stream_rated(
+    run_options: &RunOptions,
+    http_client: &reqwest::Client,
+    metadata: &Metadata<'_>,
+    status: &mut Status,
+    rejected: &mut Vec<(Mirror, Rejection)>,
+    ratings: &mut Ratings,
+) -> Result<()> {
+    let mut out = io::stdout();
+    let writer = mirrorlist_writer(metadata, status);
+    writer.write_header(&mut out)?;
+    out.flush()?;
+
+    let mut limits = StreamLimits::new(run_options, status);
+    let mut result = Ok(());
+    let mut on_rated = |url: &Url| {
+        if result.is_ok() && limits.admit(url) {
+            result = writer
+                .write_server(&mut out, url)
+                .and_then(|()| out.flush());
         }
-        (false, Some(path)) => {
-            File::create(path).and_then(move |file| format_output(&metadata, &status, file))?;
+    };
+    // Mirrors rated before, e.g. for "--require-http2", are not rated again.
+    for mirror in &status.urls {
+        if ratings.contains(&mirror.url) {
+            on_rated(&mirror.url);
         }
-        (true, None) => {
-            print_mirror_info(&status, io::stdout())?;
+    }
+    rate_status(run_options, http_client, status, ratings, &mut |url, _| {
+        on_rated(url);
+    })
+    .await;
+    result?;
+
+    sort_by_rate(status, ratings);
+    limits.apply(status, rejected);
+    Ok(())
+}
+
+/// The limits of the sorted list applied to the mirrors printed by [`stream_rated`], in
+/// the order their ratings complete.
+struct StreamLimits {
+    unique_hosts: bool,
+    max_per_country: usize,
+    number: usize,
+    /// The protocol and country code of each mirror.
+    mirrors: HashMap<Url, (Protocol, String)>,
+    /// The protocol of the printed entry of each host.
+    hosts: HashMap<String, Protocol>,
+    /// The number of printed mirrors of each country.
+    countries: HashMap<String, usize>,
+    printed: usize,
+    /// Whether each mirror was printed, or why it was not.
+    decisions: HashMap<Url, Option<Rejection>>,
+}
+
+impl StreamLimits {
+    fn new(run_options: &RunOptions, status: &Status) -> Self {
+        Self {
+            unique_hosts: run_options.unique_hosts,
+            max_per_country: run_options.filters.max_per_country.unwrap_or(usize::MAX),
+            number: run_options.filters.number.unwrap_or(usize::MAX),
+            mirrors: status
+                .urls
+                .iter()
+                .map(|mirror| {
+                    let key = (mirror.protocol, mirror.country_code.clone());
+                    (mirror.url.clone(), key)
+                })
+                .collect(),
+            hosts: HashMap::new(),
+            countries: HashMap::new(),
+            printed: 0,
+            decisions: HashMap::new(),
         }
-        (false, None) => {
-            format_output(&metadata, &status, io::stdout())?;
+    }
+
+    /// Whether the mirror at `url`, which was just rated, is to be printed.
+    fn admit(&mut self, url: &Url) -> bool {
+        let Some((protocol, country_code)) = self.mirrors.get(url) else {
+            return false;
+        };
+        let host = url.host_str().unwrap_or_default();
+        let count = self
+            .countries
+            .get(country_code)
+            .copied()
+            .unwrap_or_default();
+        let rejection = match self.hosts.get(host) {
+            Some(kept) if self.unique_hosts => Some(Rejection::DuplicateHost(*kept)),
+            // Mirrors without a country are not capped together.
+            _ if !country_code.is_empty() && count >= self.max_per_country => {
+                Some(Rejection::Limit("--max-per-country"))
+            }
+            _ if self.printed >= self.number => Some(Rejection::Limit("--number")),
+            _ => None,
+        };
+        let admitted = rejection.is_none();
+        if admitted {
+            self.hosts.entry(host.to_owned()).or_insert(*protocol);
+            *self.countries.entry(country_code.clone()).or_default() += 1;
+            self.printed += 1;
+        }
+        self.decisions.insert(url.clone(), rejection);
+        admitted
+    }
+
+    /// Keep the printed mirrors of `status` and reject the others.
+    fn apply(mut self, status: &mut Status, rejected: &mut Vec<(Mirror, Rejection)>) {
+        for mirror in std::mem::take(&mut status.urls) {
+            match self.decisions.remove(&mirror.url) {
+                Some(None) => status.urls.push(mirror),
+                Some(Some(rejection)) => rejected.push((mirror, rejection)),
+                None => rejected.push((mirror, Rejection::NotRated)),
+            }
         }
     }
+}
 
+/// Let the user check, uncheck and reorder the mirrors for "--tui" while they are rated in
+/// the background. Unchecked mirrors are rejected.
+#[cfg(feature = "tui")]
+async fn choose_mirrors(
+    run_options: &RunOptions,
+    http_client: &reqwest::Client,
+    status: &mut Status,
+    rejected: &mut Vec<(Mirror, Rejection)>,
+    ratings: &mut Ratings,
+) -> Result<()> {
+    use futures_util::future::{self, Either};
+    use std::io::IsTerminal;
+    use std::pin::pin;
+
+    if !io::stdout().is_terminal() {
+        anyhow::bail!(
+            "--tui requires the output to be a terminal, use --save to write the mirrorlist"
+        );
+    }
+    let entries = status.urls.iter().map(tui::Entry::new).collect();
+    let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+    let mut chooser = tokio::task::spawn_blocking(move || tui::choose(entries, receiver));
+    let mut on_rated = move |url: &Url, rating: &rate::Rating| {
+        sender.send((url.clone(), rating.rate)).ok();
+    };
+    // Stop rating as soon as the user is done, and let the interface know when rating is
+    // complete by closing the channel.
+    let chosen = {
+        let rating = pin!(rate_status(
+            run_options,
+            http_client,
+            status,
+            ratings,
+            &mut on_rated
+        ));
+        match future::select(&mut chooser, rating).await {
+            Either::Left((chosen, _)) => Some(chosen),
+            Either::Right(((), _)) => None,
+        }
+    };
+    drop(on_rated);
+    let chosen = match chosen {
+        Some(chosen) => chosen,
+        None => chooser.await,
+    }??;
+    let Some(chosen) = chosen else {
+        anyhow::bail!("cancelled the selection of mirrors");
+    };
+
+    let (mut kept, unchecked): (Vec<_>, Vec<_>) = std::mem::take(&mut status.urls)
+        .into_iter()
+        .partition(|mirror| chosen.contains(&mirror.url));
+    kept.sort_by_key(|mirror| chosen.iter().position(|url| *url == mirror.url));
+    status.urls = kept;
+    rejected.extend(
+        unchecked
+            .into_iter()
+            .map(|mirror| (mirror, Rejection::Unchecked)),
+    );
+    Ok(())
+}
+
+/// Print why each mirror whose URL contains `pattern` was kept or rejected.
+fn explain(
+    status: &Status,
+    rejected: &[(Mirror, Rejection)],
+    pattern: &str,
+    mut out: impl Write,
+) -> io::Result<()> {
+    for (mirror, rejection) in rejected {
+        if mirror.url.as_str().contains(pattern) {
+            writeln!(out, "{} ({}): {rejection}", mirror.url, mirror.country)?;
+        }
+    }
+    for (rank, mirror) in status.urls.iter().enumerate() {
+        if mirror.url.as_str().contains(pattern) {
+            writeln!(
+                out,
+                "{} ({}): kept at rank {}",
+                mirror.url,
+                mirror.country,
+                rank + 1
+            )?;
+        }
+    }
     Ok(())
 }
 
-fn print_mirror_info(status: &Status, mut out: impl Write) -> io::Result<()> {
+/// Format a duration as a short, human-readable string, e.g. "3h 12m".
+fn format_duration(duration: SignedDuration) -> String {
+    let minutes = duration.as_secs().max(0) / 60;
+    let (days, hours, minutes) = (minutes / 1440, minutes / 60 % 24, minutes % 60);
+    if days > 0 {
+        format!("{days}d {hours}h {minutes}m")
+    } else if hours > 0 {
+        format!("{hours}h {minutes}m")
+    } else {
+        format!("{minutes}m")
+    }
+}
+
+fn print_mirror_info(
+    status: &Status,
+    ratings: &Ratings,
+    metadata: &Metadata,
+    colored: bool,
+    mut out: impl Write,
+) -> io::Result<()> {
     const WIDTH: usize = 16;
     fn write_optional<T: std::fmt::Display>(
         out: &mut impl Write,
@@ -357,8 +1994,8 @@ fn print_mirror_info(status: &Status, mut out: impl Write) -> io::Result<()> {
             writeln!(out, "{name:WIDTH$}: None")
         }
     }
-    for mirror in &status.urls {
-        writeln!(out, "{}$repo/os/$arch", mirror.url)?;
+    for (rank, mirror) in status.urls.iter().enumerate() {
+        writeln!(out, "{}{}", mirror.url, metadata.repo_path)?;
         writeln!(out, "{0:1$}: {2}", "active", WIDTH, mirror.active)?;
         write_optional(&mut out, "completion_pct", mirror.completion_pct.as_ref())?;
         writeln!(out, "{0:1$}: {2}", "country", WIDTH, mirror.country)?;
@@ -376,295 +2013,358 @@ fn print_mirror_info(status: &Status, mut out: impl Write) -> io::Result<()> {
         )?;
         write_optional(&mut out, "duration_stddev", mirror.duration_stddev.as_ref())?;
         writeln!(out, "{0:1$}: {2}", "ipv4", WIDTH, mirror.ipv4)?;
-        writeln!(out, "{0:1$}: {2}", "ipv4", WIDTH, mirror.ipv6)?;
+        writeln!(out, "{0:1$}: {2}", "ipv6", WIDTH, mirror.ipv6)?;
         writeln!(out, "{0:1$}: {2}", "isos", WIDTH, mirror.isos)?;
         write_optional(&mut out, "last_sync", mirror.last_sync.as_ref())?;
         writeln!(out, "{0:1$}: {2}", "protocol", WIDTH, mirror.protocol)?;
-        write_optional(&mut out, "score", mirror.score.as_ref())?;
+        let score = mirror
+            .score
+            .map(|score| paint(colored, &score.to_string(), Some(Style::for_score(score))));
+        write_optional(&mut out, "score", score.as_ref())?;
+        let age = mirror.age(metadata.when).map(|age| {
+            paint(
+                colored,
+                &format_duration(age),
+                Some(Style::for_age(age.as_secs())),
+            )
+        });
+        write_optional(&mut out, "age", age.as_ref())?;
+        if !ratings.is_empty() {
+            let rating = ratings.get(&mirror.url);
+            let rate = rating.map(|rating| format!("{:.2} KiB/s", rating.rate / 1024.0));
+            write_optional(&mut out, "rate", rate.as_ref())?;
+            let ttfb = rating
+                .and_then(|rating| rating.ttfb)
+                .map(|ttfb| format!("{} ms", ttfb.as_millis()));
+            write_optional(&mut out, "ttfb", ttfb.as_ref())?;
+            let http_version = rating
+                .and_then(|rating| rating.http_version)
+                .map(|version| format!("{version:?}"));
+            write_optional(&mut out, "http_version", http_version.as_ref())?;
+            writeln!(out, "{0:1$}: {2}", "rank", WIDTH, rank + 1)?;
+        }
         writeln!(out)?;
     }
     Ok(())
 }
 
-fn format_output(metadata: &Metadata, status: &Status, mut out: impl Write) -> io::Result<()> {
-    let command = std::env::args().collect::<Vec<_>>().join(" ");
-    let retrieved = Timestamp::try_from(metadata.retrieved).unwrap_or(metadata.when);
+fn save_rate_report(
+    path: &Path,
+    status: &Status,
+    rejected: &[(Mirror, Rejection)],
+    ratings: &Ratings,
+) -> Result<()> {
+    let mirrors = status
+        .urls
+        .iter()
+        .chain(rejected.iter().map(|(mirror, _)| mirror));
+    File::create(path)
+        .and_then(|file| write_rate_report(mirrors, ratings, io::BufWriter::new(file)))
+        .with_context(|| format!("failed to write the rate report to {}", path.display()))
+}
+
+/// Write the rating of each rated mirror as CSV for "--rate-report", fastest first and
+/// followed by the mirrors that failed to rate.
+fn write_rate_report<'a>(
+    mirrors: impl Iterator<Item = &'a Mirror>,
+    ratings: &Ratings,
+    mut out: impl Write,
+) -> io::Result<()> {
+    let mut rated = mirrors
+        .filter(|mirror| ratings.get(&mirror.url).is_some() || ratings.error(&mirror.url).is_some())
+        .collect::<Vec<_>>();
+    rated.sort_by(|a, b| {
+        let rate = |mirror: &Mirror| ratings.get(&mirror.url).map(|rating| rating.rate);
+        rate(b).partial_cmp(&rate(a)).unwrap_or(Ordering::Equal)
+    });
+
     writeln!(
         out,
-        "################################################################################\n\
-         ################# Arch Linux mirrorlist generated by Reflector #################\n\
-         ################################################################################\n"
+        "url,protocol,country,country_code,bytes,elapsed_seconds,rate_bytes_per_second,error"
     )?;
-    writeln!(
-        out,
-        "# With:       {}\n# When:       {}\n# From:       {}\n# Retrieved:  {}\n# Last Check: {}\n",
-        command, metadata.when, metadata.origin, retrieved, status.last_check
+    for mirror in rated {
+        let rating = ratings.get(&mirror.url);
+        writeln!(
+            out,
+            "{},{},{},{},{},{},{},{}",
+            csv_field(mirror.url.as_str()),
+            mirror.protocol,
+            csv_field(&mirror.country),
+            csv_field(&mirror.country_code),
+            rating
+                .map(|rating| rating.bytes.to_string())
+                .unwrap_or_default(),
+            rating
+                .map(|rating| rating.elapsed.as_secs_f64().to_string())
+                .unwrap_or_default(),
+            rating
+                .map(|rating| rating.rate.to_string())
+                .unwrap_or_default(),
+            csv_field(ratings.error(&mirror.url).unwrap_or_default()),
+        )?;
+    }
+    out.flush()
+}
+
+/// Write the selected mirrors as a column-aligned table or as CSV for "--info-format".
+fn write_info_table(
+    format: ExportFormat,
+    mirror_list: &MirrorList,
+    colored: bool,
+    mut out: impl Write,
+) -> io::Result<()> {
+    fn optional<T: std::fmt::Display>(value: Option<T>) -> String {
+        value.map(|value| value.to_string()).unwrap_or_default()
+    }
+
+    if format == ExportFormat::Csv {
+        writeln!(
+            out,
+            "rank,url,protocol,country,country_code,last_sync,age_seconds,delay,score,\
+             completion_pct,rate_bytes_per_second,ttfb_seconds,http_version"
+        )?;
+        for mirror in &mirror_list.mirrors {
+            writeln!(
+                out,
+                "{},{},{},{},{},{},{},{},{},{},{},{},{}",
+                mirror.rank,
+                csv_field(mirror.url),
+                mirror.protocol,
+                csv_field(mirror.country),
+                csv_field(mirror.country_code),
+                optional(mirror.last_sync),
+                optional(mirror.age_seconds),
+                optional(mirror.delay),
+                optional(mirror.score),
+                optional(mirror.completion_pct),
+                optional(mirror.rate_bytes_per_second),
+                optional(mirror.ttfb_seconds),
+                optional(mirror.http_version.as_deref()),
+            )?;
+        }
+        return Ok(());
+    }
+
+    let header = [
+        "Rank",
+        "URL",
+        "Country",
+        "Score",
+        "Delay",
+        "Completion",
+        "Age",
+        "Rate",
+    ];
+    let rows = mirror_list
+        .mirrors
+        .iter()
+        .map(|mirror| {
+            [
+                mirror.rank.to_string(),
+                mirror.url.to_owned(),
+                mirror.country_code.to_owned(),
+                optional(mirror.score.map(|score| format!("{score:.2}"))),
+                optional(mirror.delay.map(|delay| format!("{delay}s"))),
+                optional(
+                    mirror
+                        .completion_pct
+                        .map(|pct| format!("{:.1}%", pct * 100.0)),
+                ),
+                optional(
+                    mirror
+                        .age_seconds
+                        .map(|age| format_duration(SignedDuration::from_secs(age))),
+                ),
+                optional(
+                    mirror
+                        .rate_bytes_per_second
+                        .map(|rate| format!("{:.2} KiB/s", rate / 1024.0)),
+                ),
+            ]
+        })
+        .collect::<Vec<_>>();
+    let widths = header.map(str::len);
+    let widths = rows.iter().fold(widths, |mut widths, row| {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+        widths
+    });
+    let write_row = |out: &mut dyn Write, cells: &[&str], styles: [Option<Style>; 8]| {
+        let line = cells
+            .iter()
+            .zip(widths)
+            .zip(styles)
+            .map(|((cell, width), style)| paint(colored, &format!("{cell:width$}"), style))
+            .collect::<Vec<_>>()
+            .join(" ");
+        writeln!(out, "{}", line.trim_end())
+    };
+    write_row(&mut out, &header, [Some(Style::Header); 8])?;
+    let underline = header.map(|name| "=".repeat(name.len()));
+    write_row(
+        &mut out,
+        &underline.each_ref().map(String::as_str),
+        [None; 8],
     )?;
-    for mirror in &status.urls {
-        writeln!(out, "Server = {}$repo/os/$arch", mirror.url)?;
+    for (row, mirror) in rows.iter().zip(&mirror_list.mirrors) {
+        let mut styles = [None; 8];
+        styles[3] = mirror.score.map(Style::for_score);
+        styles[6] = mirror.age_seconds.map(Style::for_age);
+        write_row(&mut out, &row.each_ref().map(String::as_str), styles)?;
     }
     Ok(())
 }
 
+/// Sort the mirrors by their measured rate, fastest first. Unrated mirrors go last.
+fn sort_by_rate(status: &mut Status, ratings: &Ratings) {
+    ratings.sort_by_rate(&mut status.urls);
+}
+
 async fn sort_status(
     sort_type: SortType,
     run_options: &RunOptions,
     http_client: &reqwest::Client,
     status: &mut Status,
+    ratings: &mut Ratings,
 ) {
     match sort_type {
-        SortType::Age => status.urls.sort_by_key(|mir| mir.last_sync),
+        SortType::Age => sort_mirrors(&mut status.urls, SortKey::Age, &[]),
         SortType::Rate => {
-            let rates = rate_status(run_options, http_client, status).await;
-            status
-                .urls
-                .sort_by(|a, b| match (rates.get(&a.url), rates.get(&b.url)) {
-                    (Some(rate_a), Some(rate_b)) => rate_a
-                        .partial_cmp(rate_b)
-                        .unwrap_or(Ordering::Equal)
-                        .reverse(),
-                    (Some(_), None) => Ordering::Less,
-                    (None, Some(_)) => Ordering::Greater,
-                    (None, None) => Ordering::Equal,
-                });
-        }
-        SortType::Country => status.urls.sort_by(|a, b| a.country.cmp(&b.country)),
-        SortType::Score => status.urls.sort_by(|a, b| {
-            a.score
-                .partial_cmp(&b.score)
-                .unwrap_or(Ordering::Equal)
-                .reverse()
-        }),
-        SortType::Delay => status.urls.sort_by_key(|mir| Reverse(mir.delay)),
-    }
-}
-
-#[allow(clippy::cast_precision_loss)]
-async fn rate_status(
-    run_options: &RunOptions,
-    http_client: &reqwest::Client,
-    status: &Status,
-) -> HashMap<Url, f64> {
-    const DB_FILENAME: &str = "extra.db";
-    const DB_SUBPATH: &str = "extra/os/x86_64/extra.db";
-
-    let mut task_set = JoinSet::<anyhow::Result<(Url, f64)>>::new();
-    let mut rates = HashMap::with_capacity(status.urls.len());
-    let semaphore = Arc::new(Semaphore::new(run_options.threads.max(1)));
-    let connection_timeout = run_options.connection_timeout;
-
-    for mirror in &status.urls {
-        let url = mirror.url.clone();
-        let semaphore = semaphore.clone();
-        match mirror.protocol {
-            Protocol::Http | Protocol::Https => {
-                let task_client = http_client.clone();
-                task_set.spawn(async move {
-                    let _guard = semaphore.acquire().await?;
-                    let db_url = url.join(DB_SUBPATH)?;
-                    let start = Instant::now();
-                    let mut content_length = 0;
-                    let mut stream = task_client.get(db_url).send().await?.bytes_stream();
-                    while let Some(chunk) = stream.next().await {
-                        content_length += chunk?.len();
-                    }
-                    let micros = Instant::elapsed(&start).as_secs_f64();
-                    let rate = (content_length as f64) / micros;
-                    Ok((url, rate))
-                });
-            }
-            Protocol::Rsync => {
-                task_set.spawn(async move {
-                    let _guard = semaphore.acquire().await?;
-                    let temp_dir = tempfile::TempDir::new()?;
-                    let db_url = url.join(DB_SUBPATH)?;
-
-                    let start = Instant::now();
-                    let exit_status = tokio::process::Command::new("rsync")
-                        .arg("-avL")
-                        .arg("--no-h")
-                        .arg("--no-motd")
-                        .arg(format!("--contimeout={connection_timeout}"))
-                        .arg(db_url.as_str())
-                        .arg(temp_dir.path())
-                        .stdout(Stdio::null())
-                        .stderr(Stdio::null())
-                        .spawn()?
-                        .wait()
-                        .await?;
-
-                    if !exit_status.success() {
-                        return Err(anyhow::anyhow!(exit_status));
-                    }
-
-                    let micros = Instant::elapsed(&start).as_secs_f64();
-                    let file_path = Path::join(temp_dir.path(), DB_FILENAME);
-                    let content_length = std::fs::metadata(file_path)?.len();
-
-                    let rate = (content_length as f64) / micros;
-                    Ok((url, rate))
-                });
-            }
+            rate_status(run_options, http_client, status, ratings, &mut |_, _| {}).await;
+            sort_by_rate(status, ratings);
         }
-    }
-
-    while let Some(result) = task_set.join_next().await {
-        match result {
-            Ok(Ok((url, rate))) => {
-                rates.insert(url, rate);
-            }
-            Ok(Err(err)) => eprintln!("error while rating mirror: {err}"),
-            Err(err) => eprintln!("error while rating mirror: {err}"),
-        }
-    }
-
-    rates
-}
-
-#[allow(clippy::cast_sign_loss)]
-#[allow(clippy::cast_possible_truncation)]
-fn filter_status(filters: &Filters, status: &mut Status) {
-    let now = Timestamp::now();
-    let min_completion_pct = f64::from(filters.completion_percent) / 100.0;
-    let max_age = filters
-        .age
-        .and_then(|age| Span::new().try_hours(age as i64).ok());
-    status.urls.retain(move |mirror| {
-        if let Some(last_sync) = mirror.last_sync {
-            // Filter by age. The age is given in hours and converted to seconds. Servers
-            // with a last refresh older than the age are omitted.
-            if let Some(max_age) = max_age {
-                if matches!(max_age.compare(Span::new()), Ok(Ordering::Greater))
-                    && last_sync + max_age < now
-                {
-                    return false;
-                }
-            }
-        } else {
-            // Filter unsynced mirrors.
-            return false;
+        SortType::Latency => {
+            let latencies = measure_latencies(run_options, http_client, status).await;
+            sort::sort_by_latency(&mut status.urls, &latencies);
         }
-
-        // Filter by completion "percent" [0-1].
-        if let Some(completion_pct) = mirror.completion_pct {
-            if completion_pct < min_completion_pct {
-                return false;
-            }
+        SortType::Country => sort_mirrors(
+            &mut status.urls,
+            SortKey::Country,
+            &run_options.filters.country,
+        ),
+        SortType::Score => sort_mirrors(&mut status.urls, SortKey::Score, &[]),
+        SortType::Delay => sort_mirrors(&mut status.urls, SortKey::Delay, &[]),
+        SortType::CustomScore => {
+            let weights = run_options.score_weights.unwrap_or_default();
+            sort::sort_by_weighted_score(&mut status.urls, &weights);
         }
-
-        if !filters.country.is_empty() {
-            let country_matches = filters.country.iter().any(|c| {
-                let trimmed = c.trim();
-                if trimmed == "*" {
-                    return true;
-                }
-                // All country names are in English and all country codes are in ASCII.
-                trimmed.eq_ignore_ascii_case(mirror.country.as_str())
-                    || trimmed.eq_ignore_ascii_case(mirror.country_code.as_str())
-            });
-            if !country_matches {
-                return false;
+        SortType::Quality => {
+            let weights = run_options.quality_weights.unwrap_or_default();
+            if weights.rate > 0.0 {
+                rate_status(run_options, http_client, status, ratings, &mut |_, _| {}).await;
             }
-        }
-
-        // Filter by protocols.
-        if !filters.protocol.is_empty() && !filters.protocol.contains(&mirror.protocol) {
-            return false;
-        }
-
-        // Filter by include expressions.
-        if !filters.include.is_empty()
-            && !filters
-                .include
-                .iter()
-                .any(|re| re.is_match(mirror.url.as_str()))
-        {
-            return false;
-        }
-
-        // Filter by include expressions.
-        if !filters.exclude.is_empty()
-            && filters
-                .exclude
-                .iter()
-                .any(|re| re.is_match(mirror.url.as_str()))
-        {
-            return false;
-        }
-
-        // Filter by delay. The delay is given as a float of hours and must be
-        // converted to seconds.
-        if let Some(delay) = filters.delay {
-            let max_delay = (delay * 3600.0) as u32;
-            if let Some(mirror_delay) = mirror.delay {
-                if mirror_delay > max_delay {
-                    return false;
-                }
+            let latencies = if weights.latency > 0.0 {
+                measure_latencies(run_options, http_client, status).await
             } else {
-                return false;
-            }
-        }
-
-        // Filter by ISO hosing.
-        if filters.isos && !mirror.isos {
-            return false;
-        }
-
-        // Filter by IPv4 support.
-        if filters.ipv4 && !mirror.ipv4 {
-            return false;
+                HashMap::new()
+            };
+            quality::sort_by_quality(
+                &mut status.urls,
+                &weights,
+                ratings,
+                &latencies,
+                Timestamp::now(),
+            );
         }
+    }
+}
 
-        // Filter by IPv6 support.
-        if filters.ipv6 && !mirror.ipv6 {
-            return false;
+/// Print the statistics of the countries with mirrors left after filtering.
+fn list_countries(options: &Cli, status: &mut Status, mut out: impl Write) -> Result<()> {
+    filter_status(
+        &options.run.filters.mirror_filters(),
+        status,
+        !options.run.lists_mirrors_only(),
+    );
+    let mut countries = country_stats(&status.urls);
+    match options.list_countries_sort {
+        CountrySort::Code => {}
+        CountrySort::Name => countries.sort_by(|c1, c2| c1.country.cmp(c2.country)),
+        CountrySort::Count => countries.sort_by_key(|c| Reverse(c.count)),
+    }
+    match options.list_countries_format {
+        ExportFormat::Table => write_country_table(&countries, options.run.color.enabled(), out)?,
+        ExportFormat::Json => schema::write_json(out, "countries", &countries)?,
+        ExportFormat::Csv => {
+            writeln!(
+                out,
+                "country,code,count,protocols,median_score,median_delay"
+            )?;
+            for stat in &countries {
+                let protocols = stat
+                    .protocols
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(";");
+                writeln!(
+                    out,
+                    "{},{},{},{},{},{}",
+                    csv_field(stat.country),
+                    csv_field(stat.code),
+                    stat.count,
+                    protocols,
+                    stat.median_score.map(|v| v.to_string()).unwrap_or_default(),
+                    stat.median_delay.map(|v| v.to_string()).unwrap_or_default(),
+                )?;
+            }
         }
-
-        true
-    });
+    }
+    Ok(())
 }
 
-fn list_countries(status: &Status) {
-    let counts = count_countries(&status.urls);
-    let mut sorted = vec![];
-    for (country, count) in counts {
-        sorted.push((country, count));
+/// Quote a CSV field if it contains a delimiter, quote or line break.
+fn csv_field(value: &str) -> std::borrow::Cow<'_, str> {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\"")).into()
+    } else {
+        value.into()
     }
-    sorted.sort_by(|c1, c2| c1.0.code.cmp(c2.0.code));
+}
 
-    let country_width = sorted
+fn write_country_table(
+    stats: &[CountryStats],
+    colored: bool,
+    mut out: impl Write,
+) -> io::Result<()> {
+    let country_width = stats
         .iter()
-        .map(|(c, _)| c.country.len())
+        .map(|c| c.country.len())
         .max()
         .unwrap_or(0)
         .max("Country".len());
-    let code_width = sorted
+    let code_width = stats
         .iter()
-        .map(|(c, _)| c.code.len())
+        .map(|c| c.code.len())
         .max()
         .unwrap_or(0)
         .max("Code".len());
-    let count_width = sorted
+    let count_width = stats
         .iter()
-        .map(|(_, c)| c.ilog(10) as usize)
+        .map(|c| c.count.ilog(10) as usize)
         .max()
         .unwrap_or(0)
         .max("Count".len());
 
-    println!(
+    let header = format!(
         "{0:1$} {2:3$} {4:5$}",
         "Country", country_width, "Code", code_width, "Count", count_width
     );
-    println!(
+    writeln!(out, "{}", paint(colored, &header, Some(Style::Header)))?;
+    writeln!(
+        out,
         "{0:1$} {2:3$} {4:5$}",
         "=======", country_width, "====", code_width, "=====", count_width
-    );
-    for (country, count) in sorted {
-        println!(
+    )?;
+    for stat in stats {
+        writeln!(
+            out,
             "{0:1$} {2:3$} {4:5$}",
-            country.country, country_width, country.code, code_width, count, count_width
-        );
+            stat.country, country_width, stat.code, code_width, stat.count, count_width
+        )?;
     }
+    Ok(())
 }
 
 fn convert_arg_line_to_args(content: &str, _prefix: char) -> Vec<argfile::Argument> {
@@ -678,29 +2378,125 @@ fn convert_arg_line_to_args(content: &str, _prefix: char) -> Vec<argfile::Argume
         .collect()
 }
 
-fn main() {
-    let cli = match argfile::expand_args(convert_arg_line_to_args, argfile::PREFIX) {
-        Ok(args) => Cli::parse_from(args),
+/// Whether STDERR is connected to the journal, as for services run by systemd. Processes
+/// started from such a service, e.g. an interactive shell, inherit `JOURNAL_STREAM` but
+/// not its STDERR, so the device and inode it holds are compared with those of STDERR.
+#[cfg(target_os = "linux")]
+fn stderr_is_journal() -> bool {
+    let Some(journal_stream) = std::env::var_os("JOURNAL_STREAM") else {
+        return false;
+    };
+    let Some((dev, ino)) = journal_stream
+        .to_str()
+        .and_then(|value| value.split_once(':'))
+        .and_then(|(dev, ino)| Some((dev.parse::<u64>().ok()?, ino.parse::<u64>().ok()?)))
+    else {
+        return false;
+    };
+    rustix::fs::fstat(io::stderr()).is_ok_and(|stat| stat.st_dev == dev && stat.st_ino == ino)
+}
+
+/// The journal is only available on Linux.
+#[cfg(not(target_os = "linux"))]
+fn stderr_is_journal() -> bool {
+    false
+}
+
+/// Set up logging. When running under systemd, log messages are sent to the journal using
+/// its native protocol so that their priority and structured fields are preserved.
+/// Otherwise, they are written to STDERR.
+fn init_logging(verbose: Verbosity<WarnLevel>) {
+    use tracing_subscriber::prelude::*;
+
+    let level = verbose.tracing_level_filter();
+    if stderr_is_journal() {
+        if let Ok(layer) = tracing_journald::layer() {
+            tracing_subscriber::registry()
+                .with(layer.with_filter(level))
+                .init();
+            return;
+        }
+    }
+    tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_writer(io::stderr)
+        .without_time()
+        .with_target(false)
+        .init();
+}
+
+/// Parse the command line arguments with `command`, as built by [`config::command`].
+fn parse_args(command: &mut clap::Command, args: Vec<OsString>) -> clap::error::Result<Cli> {
+    let mut matches = command.try_get_matches_from_mut(args)?;
+    Cli::from_arg_matches_mut(&mut matches).map_err(|err| err.format(command))
+}
+
+fn main() -> ExitCode {
+    let mut command = config::command(Cli::command());
+    let args = argfile::expand_args(convert_arg_line_to_args, argfile::PREFIX)
+        .and_then(|args| config::merge_args(&command, args));
+    let mut cli = match args.map(|args| parse_args(&mut command, args)) {
+        Ok(Ok(cli)) => cli,
+        // Printing the help or version also ends up here.
+        Ok(Err(err)) if !err.use_stderr() => err.exit(),
+        Ok(Err(err)) => {
+            // The error cannot be printed if STDERR is closed, but there is nothing to do
+            // about that.
+            let _ = err.print();
+            return ExitCode::from(Exit::Usage as u8);
+        }
         Err(err) => {
             eprintln!("error: {err}");
-            return;
+            return ExitCode::from(Exit::Usage as u8);
         }
     };
+    init_logging(cli.verbose);
+
+    let filters = &mut cli.run.filters;
+    filters.country = country::normalize(&filters.country);
+    if let Some(n) = filters.neighbors {
+        filters.country = neighbors::expand_countries(&filters.country, n);
+    }
+    if cli.run.lists_mirrors_only() && cli.run.sort.is_none() {
+        cli.run.sort = Some(SortType::Rate);
+    }
 
     let maybe_runtime = tokio::runtime::Builder::new_multi_thread()
         .enable_all()
-        .worker_threads(cli.run.threads.max(1))
+        .worker_threads(cli.run.threads.max().max(1))
         .build();
 
     let result = match maybe_runtime {
         Ok(runtime) => runtime.block_on(run(&cli)),
         Err(err) => {
             eprintln!("error: {err}");
-            return;
+            return ExitCode::FAILURE;
         }
     };
 
-    if let Err(err) = result {
-        eprintln!("error: {err}");
+    match result {
+        Ok(()) => {
+            // Reflector does not provide a service to wait for, so it only reports that it
+            // is ready once it is done. Starting the service then waits for the mirrorlist.
+            notify::notify("READY=1");
+            ExitCode::SUCCESS
+        }
+        // Output piped into e.g. `head` is expected to be cut short. Exit quietly then.
+        Err(err) if is_broken_pipe(&err) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {err}");
+            notify::status(&format!("Failed: {err}"));
+            ExitCode::from(Exit::status_of(&err))
+        }
     }
 }
+
+fn is_broken_pipe(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| {
+        let kind = cause
+            .downcast_ref::<io::Error>()
+            .map(io::Error::kind)
+            .or_else(|| cause.downcast_ref::<serde_json::Error>()?.io_error_kind());
+        kind == Some(io::ErrorKind::BrokenPipe)
+    })
+}
@@ -0,0 +1,218 @@
+//! Reading the mirror status of Manjaro for "--distro manjaro". It lists whether each
+//! mirror is in sync with the stable, testing and unstable branches instead of the
+//! statistics of the Arch Linux mirror status, so its mirrors are turned into the mirrors of
+//! the selected branch for filtering, sorting and rating.
+use anyhow::{Context, Result};
+use arch_mirrors_rs::extra::Extra;
+use arch_mirrors_rs::provider::Error;
+use arch_mirrors_rs::{CountryCode, Mirror, MirrorProvider, Protocol, Status};
+use clap::ValueEnum;
+use jiff::{SignedDuration, Timestamp};
+use reqwest::Url;
+use serde::Deserialize;
+
+/// The default URL of the mirror status of Manjaro.
+pub const URL: &str = "https://repo.manjaro.org/status.json";
+
+/// The branches of Manjaro, in the order of their sync states in the mirror status.
+#[derive(Debug, ValueEnum, Clone, Copy, PartialEq, Eq)]
+pub enum Branch {
+    Stable,
+    Testing,
+    Unstable,
+}
+
+impl Branch {
+    /// The path of the branch relative to the base URL of a mirror.
+    fn path(self) -> &'static str {
+        match self {
+            Self::Stable => "stable/",
+            Self::Testing => "testing/",
+            Self::Unstable => "unstable/",
+        }
+    }
+}
+
+/// The mirror status of Manjaro at a URL, as the mirror status of a branch.
+pub struct Manjaro {
+    url: String,
+    branch: Branch,
+}
+
+impl Manjaro {
+    pub fn new(url: impl Into<String>, branch: Branch) -> Self {
+        Self {
+            url: url.into(),
+            branch,
+        }
+    }
+}
+
+impl MirrorProvider for Manjaro {
+    fn url(&self) -> &str {
+        &self.url
+    }
+
+    fn parse(&self, data: &[u8]) -> Result<Status, Error> {
+        let mirrors = serde_json::from_slice(data)?;
+        Ok(to_status(mirrors, self.branch, Timestamp::now())?)
+    }
+}
+
+/// A mirror in the mirror status of Manjaro.
+#[derive(Debug, Deserialize)]
+struct MirrorStatus {
+    /// The sync state of each branch: 1 if in sync, 0 if not and -1 if unknown.
+    branches: Vec<i8>,
+    /// The name of the country, with underscores instead of spaces.
+    country: String,
+    /// The time since the last sync as "hours:minutes", or "-1" if unknown.
+    last_sync: String,
+    protocols: Vec<String>,
+    url: String,
+}
+
+/// Turn the mirrors of the mirror status of Manjaro into a mirror status of `branch`, with
+/// a mirror for each protocol. Mirrors that are not known to be in sync with the branch are
+/// 0% complete.
+fn to_status(mirrors: Vec<MirrorStatus>, branch: Branch, last_check: Timestamp) -> Result<Status> {
+    let mut urls = Vec::new();
+    for mirror in mirrors {
+        let name = mirror.country.replace('_', " ");
+        let (country, country_code) = match CountryCode::lookup(&name) {
+            Some(code) => (code.name().to_owned(), code.to_string()),
+            None => (name, String::new()),
+        };
+        let delay = parse_last_sync(&mirror.last_sync);
+        let in_sync = mirror.branches.get(branch as usize) == Some(&1);
+        // The URL is given with one of the protocols, and served the same way with others.
+        let rest = mirror
+            .url
+            .split_once("://")
+            .map_or(mirror.url.as_str(), |(_, rest)| rest);
+        for scheme in &mirror.protocols {
+            let Ok(protocol) = scheme.parse::<Protocol>() else {
+                tracing::debug!(mirror_url = %mirror.url, "ignoring the unsupported protocol {scheme}");
+                continue;
+            };
+            let mut url = Url::parse(&format!("{scheme}://{rest}"))
+                .with_context(|| format!("invalid mirror {}", mirror.url))?;
+            if !url.path().ends_with('/') {
+                url.set_path(&format!("{}/", url.path()));
+            }
+            urls.push(Mirror {
+                url: url.join(branch.path())?,
+                protocol,
+                last_sync: delay.and_then(|delay| {
+                    last_check
+                        .checked_sub(SignedDuration::from_secs(delay.into()))
+                        .ok()
+                }),
+                completion_pct: Some(if in_sync { 1.0 } else { 0.0 }),
+                delay,
+                duration_average: None,
+                duration_stddev: None,
+                score: None,
+                active: true,
+                country: country.clone(),
+                country_code: country_code.clone(),
+                isos: false,
+                // The mirror status of Manjaro does not say, and mirrors are practically
+                // always reachable over IPv4.
+                ipv4: true,
+                ipv6: false,
+                details: String::new(),
+                extra: Extra::default(),
+            });
+        }
+    }
+    Ok(Status {
+        cutoff: 0,
+        last_check,
+        num_checks: 0,
+        check_frequency: 0,
+        urls,
+        version: 0,
+        extra: Extra::default(),
+    })
+}
+
+/// Parse the time since the last sync, given as "hours:minutes", into seconds.
+fn parse_last_sync(last_sync: &str) -> Option<u32> {
+    let (hours, minutes) = last_sync.split_once(':')?;
+    let hours = hours.parse::<u32>().ok()?;
+    let minutes = minutes.parse::<u32>().ok()?;
+    hours
+        .checked_mul(3600)?
+        .checked_add(minutes.checked_mul(60)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn convert_branch() {
+        let mirrors: Vec<MirrorStatus> = serde_json::from_str(
+            r#"[
+                {
+                    "branches": [1, 0, -1],
+                    "country": "United_States",
+                    "last_sync": "02:30",
+                    "protocols": ["https", "http"],
+                    "url": "https://mirror.example.com/manjaro/"
+                },
+                {
+                    "branches": [0, 1, 1],
+                    "country": "Atlantis",
+                    "last_sync": "-1",
+                    "protocols": ["gopher", "rsync"],
+                    "url": "http://mirror.example.org/manjaro"
+                }
+            ]"#,
+        )
+        .unwrap();
+        let last_check: Timestamp = "2026-10-16T12:00:00Z".parse().unwrap();
+        let status = to_status(mirrors, Branch::Stable, last_check).unwrap();
+        let urls = status
+            .urls
+            .iter()
+            .map(|mirror| {
+                (
+                    mirror.url.as_str(),
+                    mirror.country_code.as_str(),
+                    mirror.completion_pct,
+                    mirror.delay,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            urls,
+            vec![
+                (
+                    "https://mirror.example.com/manjaro/stable/",
+                    "US",
+                    Some(1.0),
+                    Some(9000)
+                ),
+                (
+                    "http://mirror.example.com/manjaro/stable/",
+                    "US",
+                    Some(1.0),
+                    Some(9000)
+                ),
+                (
+                    "rsync://mirror.example.org/manjaro/stable/",
+                    "",
+                    Some(0.0),
+                    None
+                ),
+            ]
+        );
+        assert_eq!(
+            status.urls[0].last_sync,
+            Some("2026-10-16T09:30:00Z".parse().unwrap())
+        );
+        assert_eq!(status.urls[0].country, "United States");
+    }
+}
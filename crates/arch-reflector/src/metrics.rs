@@ -0,0 +1,184 @@
+//! Exporting metrics of the selected mirrors for "--metrics-file" in the text format of
+//! Prometheus, to be picked up by the textfile collector of `node_exporter`.
+use reflector_lib::output::{MirrorEntry, MirrorList};
+use std::fmt::Display;
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::time::Duration;
+
+/// Atomically replace the file at `path` with the metrics, so the collector never reads a
+/// partially written file.
+pub fn save(path: &Path, mirror_list: &MirrorList, duration: Duration) -> io::Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let mut file = tempfile::NamedTempFile::new_in(dir)?;
+    // Temporary files are only readable by their owner, but the collector usually runs as
+    // another user.
+    file.as_file()
+        .set_permissions(fs::Permissions::from_mode(0o644))?;
+    write_metrics(&mut file, mirror_list, duration)?;
+    file.persist(path)?;
+    Ok(())
+}
+
+/// Write the metrics of the selected mirrors and of the run.
+pub fn write_metrics(
+    mut out: impl Write,
+    mirror_list: &MirrorList,
+    duration: Duration,
+) -> io::Result<()> {
+    write_family(
+        &mut out,
+        "reflector_run_duration_seconds",
+        "The time the last run of reflector took.",
+    )?;
+    writeln!(
+        out,
+        "reflector_run_duration_seconds {}",
+        duration.as_secs_f64()
+    )?;
+    write_family(
+        &mut out,
+        "reflector_last_run_timestamp_seconds",
+        "The time of the last run of reflector.",
+    )?;
+    writeln!(
+        out,
+        "reflector_last_run_timestamp_seconds {}",
+        mirror_list.generated.as_second()
+    )?;
+    write_family(
+        &mut out,
+        "reflector_mirrors_selected",
+        "The number of mirrors in the mirrorlist.",
+    )?;
+    writeln!(
+        out,
+        "reflector_mirrors_selected {}",
+        mirror_list.mirrors.len()
+    )?;
+
+    write_mirror_family(
+        &mut out,
+        mirror_list,
+        "reflector_mirror_rank",
+        "The position of the mirror in the mirrorlist, starting at 1.",
+        |mirror| Some(mirror.rank.to_string()),
+    )?;
+    write_mirror_family(
+        &mut out,
+        mirror_list,
+        "reflector_mirror_rate_bytes_per_second",
+        "The measured download rate of the mirror.",
+        |mirror| mirror.rate_bytes_per_second.map(|rate| rate.to_string()),
+    )?;
+    write_mirror_family(
+        &mut out,
+        mirror_list,
+        "reflector_mirror_age_seconds",
+        "The time since the last synchronization of the mirror.",
+        |mirror| mirror.age_seconds.map(|age| age.to_string()),
+    )?;
+    write_mirror_family(
+        &mut out,
+        mirror_list,
+        "reflector_mirror_delay_seconds",
+        "The sync delay of the mirror reported by the mirror status.",
+        |mirror| mirror.delay.map(|delay| delay.to_string()),
+    )?;
+    write_mirror_family(
+        &mut out,
+        mirror_list,
+        "reflector_mirror_score",
+        "The score of the mirror reported by the mirror status, where lower is better.",
+        |mirror| mirror.score.map(|score| score.to_string()),
+    )
+}
+
+fn write_family(out: &mut impl Write, name: &str, help: &str) -> io::Result<()> {
+    writeln!(out, "# HELP {name} {help}")?;
+    writeln!(out, "# TYPE {name} gauge")
+}
+
+/// Write a metric with one sample per mirror, leaving out the mirrors without a value.
+fn write_mirror_family<T: Display>(
+    out: &mut impl Write,
+    mirror_list: &MirrorList,
+    name: &str,
+    help: &str,
+    value: impl Fn(&MirrorEntry) -> Option<T>,
+) -> io::Result<()> {
+    write_family(out, name, help)?;
+    for mirror in &mirror_list.mirrors {
+        if let Some(value) = value(mirror) {
+            writeln!(
+                out,
+                "{name}{{url=\"{}\",protocol=\"{}\",country=\"{}\"}} {value}",
+                escape(mirror.url),
+                mirror.protocol,
+                escape(mirror.country_code),
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Escape a label value.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arch_mirrors_rs::Protocol;
+    use jiff::Timestamp;
+
+    #[test]
+    fn metrics_format() {
+        let when = Timestamp::from_second(1_700_000_000).unwrap();
+        let list = MirrorList {
+            generated: when,
+            origin: "https://archlinux.org/mirrors/status/json/",
+            retrieved: when,
+            last_check: when,
+            mirrors: vec![MirrorEntry {
+                rank: 1,
+                url: "https://mirror.example.com/archlinux/",
+                server: "https://mirror.example.com/archlinux/$repo/os/$arch".to_owned(),
+                protocol: Protocol::Https,
+                country: "Sweden",
+                country_code: "SE",
+                last_sync: Some(when),
+                age_seconds: Some(60),
+                delay: None,
+                score: Some(1.5),
+                completion_pct: Some(1.0),
+                rate_bytes_per_second: Some(2048.0),
+                ttfb_seconds: None,
+                http_version: None,
+            }],
+        };
+        let mut out = Vec::new();
+        write_metrics(&mut out, &list, Duration::from_millis(1500)).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let labels =
+            r#"{url="https://mirror.example.com/archlinux/",protocol="https",country="SE"}"#;
+        assert!(out.contains("reflector_run_duration_seconds 1.5\n"));
+        assert!(out.contains("reflector_last_run_timestamp_seconds 1700000000\n"));
+        assert!(out.contains("reflector_mirrors_selected 1\n"));
+        assert!(out.contains(&format!(
+            "reflector_mirror_rate_bytes_per_second{labels} 2048\n"
+        )));
+        assert!(out.contains(&format!("reflector_mirror_age_seconds{labels} 60\n")));
+        assert!(!out.contains(&format!("reflector_mirror_delay_seconds{labels}")));
+        assert_eq!(escape("a\"b\\c"), r#"a\"b\\c"#);
+    }
+}
@@ -0,0 +1,156 @@
+//! Expanding the selected countries with their nearest neighbors for "--neighbors".
+
+/// Countries with Arch Linux mirrors, given by their code, the name used by the mirror
+/// status and the approximate location of their population center in degrees latitude and
+/// longitude. The location of large countries is moved towards where most of their mirrors
+/// are, so the distances reflect the network rather than the geometric center.
+const COUNTRIES: &[(&str, &str, f64, f64)] = &[
+    ("AE", "United Arab Emirates", 24.47, 54.37),
+    ("AL", "Albania", 41.15, 20.17),
+    ("AM", "Armenia", 40.07, 45.04),
+    ("AR", "Argentina", -34.60, -58.38),
+    ("AT", "Austria", 47.52, 14.55),
+    ("AU", "Australia", -33.87, 148.0),
+    ("AZ", "Azerbaijan", 40.14, 47.58),
+    ("BA", "Bosnia and Herzegovina", 43.92, 17.68),
+    ("BD", "Bangladesh", 23.68, 90.36),
+    ("BE", "Belgium", 50.50, 4.47),
+    ("BG", "Bulgaria", 42.73, 25.49),
+    ("BR", "Brazil", -22.0, -46.0),
+    ("BY", "Belarus", 53.71, 27.95),
+    ("CA", "Canada", 45.5, -75.0),
+    ("CH", "Switzerland", 46.82, 8.23),
+    ("CL", "Chile", -33.45, -70.67),
+    ("CN", "China", 32.0, 114.0),
+    ("CO", "Colombia", 4.57, -74.30),
+    ("CR", "Costa Rica", 9.75, -83.75),
+    ("CZ", "Czechia", 49.82, 15.47),
+    ("DE", "Germany", 51.17, 10.45),
+    ("DK", "Denmark", 56.26, 9.50),
+    ("EC", "Ecuador", -1.83, -78.18),
+    ("EE", "Estonia", 58.60, 25.01),
+    ("ES", "Spain", 40.46, -3.75),
+    ("FI", "Finland", 61.92, 25.75),
+    ("FR", "France", 46.23, 2.21),
+    ("GB", "United Kingdom", 53.0, -1.5),
+    ("GE", "Georgia", 42.32, 43.36),
+    ("GR", "Greece", 39.07, 21.82),
+    ("HK", "Hong Kong", 22.40, 114.11),
+    ("HR", "Croatia", 45.10, 15.20),
+    ("HU", "Hungary", 47.16, 19.50),
+    ("ID", "Indonesia", -6.2, 106.8),
+    ("IE", "Ireland", 53.41, -8.24),
+    ("IL", "Israel", 31.05, 34.85),
+    ("IN", "India", 20.59, 78.96),
+    ("IR", "Iran", 32.43, 53.69),
+    ("IS", "Iceland", 64.13, -21.9),
+    ("IT", "Italy", 41.87, 12.57),
+    ("JP", "Japan", 36.20, 138.25),
+    ("KE", "Kenya", -1.29, 36.82),
+    ("KG", "Kyrgyzstan", 41.20, 74.77),
+    ("KH", "Cambodia", 12.57, 104.99),
+    ("KR", "South Korea", 36.5, 127.5),
+    ("KZ", "Kazakhstan", 47.0, 71.0),
+    ("LT", "Lithuania", 55.17, 23.88),
+    ("LU", "Luxembourg", 49.82, 6.13),
+    ("LV", "Latvia", 56.88, 24.60),
+    ("MA", "Morocco", 31.79, -7.09),
+    ("MD", "Moldova", 47.41, 28.37),
+    ("MK", "North Macedonia", 41.61, 21.75),
+    ("MU", "Mauritius", -20.35, 57.55),
+    ("MX", "Mexico", 21.0, -101.0),
+    ("MY", "Malaysia", 4.21, 101.98),
+    ("NC", "New Caledonia", -20.90, 165.62),
+    ("NL", "Netherlands", 52.13, 5.29),
+    ("NO", "Norway", 60.47, 8.47),
+    ("NP", "Nepal", 28.39, 84.12),
+    ("NZ", "New Zealand", -40.90, 174.89),
+    ("PA", "Panama", 8.54, -80.78),
+    ("PE", "Peru", -9.19, -75.02),
+    ("PH", "Philippines", 12.88, 121.77),
+    ("PK", "Pakistan", 30.38, 69.35),
+    ("PL", "Poland", 51.92, 19.15),
+    ("PT", "Portugal", 39.40, -8.22),
+    ("PY", "Paraguay", -23.44, -58.44),
+    ("RE", "Réunion", -21.12, 55.54),
+    ("RO", "Romania", 45.94, 24.97),
+    ("RS", "Serbia", 44.02, 21.01),
+    ("RU", "Russia", 55.76, 37.62),
+    ("SA", "Saudi Arabia", 23.89, 45.08),
+    ("SE", "Sweden", 60.13, 18.64),
+    ("SG", "Singapore", 1.35, 103.82),
+    ("SI", "Slovenia", 46.15, 14.99),
+    ("SK", "Slovakia", 48.67, 19.70),
+    ("TH", "Thailand", 15.87, 100.99),
+    ("TR", "Turkey", 38.96, 35.24),
+    ("TW", "Taiwan", 23.70, 120.96),
+    ("UA", "Ukraine", 48.38, 31.17),
+    ("US", "United States", 39.8, -89.0),
+    ("UZ", "Uzbekistan", 41.38, 64.59),
+    ("VN", "Vietnam", 14.06, 108.28),
+    ("ZA", "South Africa", -28.0, 26.0),
+];
+
+/// The mean radius of the Earth in kilometers.
+const EARTH_RADIUS: f64 = 6371.0;
+
+/// Append the codes of the `n` countries nearest to each of the given countries. Countries
+/// may be given by name or code, as for "--country". The "*" glob and unknown countries
+/// are left as they are.
+pub fn expand_countries(countries: &[String], n: usize) -> Vec<String> {
+    let mut expanded = countries.to_vec();
+    let selected = countries
+        .iter()
+        .filter(|country| country.trim() != "*")
+        .filter_map(|country| {
+            let location = lookup(country);
+            if location.is_none() {
+                tracing::warn!(
+                    "no location known for country '{country}', ignoring it for --neighbors"
+                );
+            }
+            location
+        })
+        .collect::<Vec<_>>();
+
+    for &(code, _, lat, lon) in &selected {
+        let mut others = COUNTRIES
+            .iter()
+            .filter(|(other, ..)| *other != code)
+            .map(|&(other, _, other_lat, other_lon)| {
+                (other, distance((lat, lon), (other_lat, other_lon)))
+            })
+            .collect::<Vec<_>>();
+        others.sort_by(|(_, a), (_, b)| a.total_cmp(b));
+        for (other, _) in others.into_iter().take(n) {
+            if !expanded
+                .iter()
+                .any(|country| lookup(country).is_some_and(|(known, ..)| known == other))
+            {
+                tracing::info!("--neighbors adds {other} as a neighbor of {code}");
+                expanded.push(other.to_owned());
+            }
+        }
+    }
+    expanded
+}
+
+/// Find a country by its name or code, ignoring case.
+fn lookup(country: &str) -> Option<(&'static str, &'static str, f64, f64)> {
+    let country = country.trim();
+    COUNTRIES
+        .iter()
+        .find(|(code, name, ..)| {
+            code.eq_ignore_ascii_case(country) || name.eq_ignore_ascii_case(country)
+        })
+        .copied()
+}
+
+/// The great-circle distance in kilometers between two points given in degrees.
+fn distance((lat_a, lon_a): (f64, f64), (lat_b, lon_b): (f64, f64)) -> f64 {
+    let (lat_a, lat_b) = (lat_a.to_radians(), lat_b.to_radians());
+    let d_lat = lat_b - lat_a;
+    let d_lon = (lon_b - lon_a).to_radians();
+    let h = (d_lat / 2.0).sin().powi(2) + lat_a.cos() * lat_b.cos() * (d_lon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS * h.sqrt().asin()
+}
@@ -0,0 +1,62 @@
+//! Notifying systemd about the progress of reflector when it runs as a service with
+//! `Type=notify`, using the protocol of `sd_notify(3)`.
+use std::env;
+use std::ffi::OsStr;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::net::UnixDatagram;
+use std::sync::OnceLock;
+
+/// Send a notification such as "READY=1" to the service manager, if reflector was started
+/// by one. Notifications are best effort, so failures are only logged.
+pub fn notify(state: &str) {
+    let Some(path) = env::var_os("NOTIFY_SOCKET") else {
+        return;
+    };
+    if let Err(err) = send(&path, state) {
+        tracing::debug!("failed to notify the service manager: {err}");
+    }
+}
+
+/// Set the status text shown by "systemctl status". Starting a new step counts as
+/// [progress].
+pub fn status(text: &str) {
+    notify(&format!("STATUS={text}"));
+    progress();
+}
+
+/// Tell the watchdog of the service manager that reflector made progress, e.g. because a
+/// mirror was rated. If reflector makes no progress for the whole watchdog timeout, e.g.
+/// because every rating is stuck on a stalled download, the service manager stops it.
+pub fn progress() {
+    static WATCHDOG: OnceLock<bool> = OnceLock::new();
+    if *WATCHDOG.get_or_init(watchdog_enabled) {
+        notify("WATCHDOG=1");
+    }
+}
+
+fn send(path: &OsStr, state: &str) -> io::Result<()> {
+    let socket = UnixDatagram::unbound()?;
+    // Rather drop a notification than wait for a service manager that is not reading them.
+    socket.set_nonblocking(true)?;
+    // Paths starting with '@' refer to sockets in the abstract namespace.
+    #[cfg(target_os = "linux")]
+    if let Some(name) = path.as_bytes().strip_prefix(b"@") {
+        use std::os::linux::net::SocketAddrExt;
+        let address = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+        socket.send_to_addr(state.as_bytes(), &address)?;
+        return Ok(());
+    }
+    socket.send_to(state.as_bytes(), path)?;
+    Ok(())
+}
+
+/// Whether the service manager expects keep-alive pings from this process.
+fn watchdog_enabled() -> bool {
+    if let Ok(pid) = env::var("WATCHDOG_PID") {
+        if pid.parse() != Ok(std::process::id()) {
+            return false;
+        }
+    }
+    env::var("WATCHDOG_USEC").is_ok_and(|usec| usec.parse::<u64>().is_ok_and(|usec| usec > 0))
+}
@@ -0,0 +1,587 @@
+//! Measuring the download rate of mirrors.
+use crate::history::{HISTORY_FILE_NAME, History};
+use crate::{CompareOptions, RateOptions, RunOptions, notify};
+use anyhow::Result;
+use arch_mirrors_rs::{Protocol, Status};
+use futures_util::FutureExt;
+use jiff::Timestamp;
+use reflector_lib::rate::{Prober, interleave_countries};
+use reqwest::Url;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::Duration;
+
+pub use reflector_lib::rate::{Rating, Ratings};
+
+/// Rate all mirrors of `status` that have not been rated yet and add them to `ratings`.
+/// `on_rated` is called as soon as each successful rating completes. Mirrors that could
+/// not be rated are logged as warnings.
+#[tracing::instrument(name = "rate", skip_all)]
+pub async fn rate_status(
+    run_options: &RunOptions,
+    http_client: &reqwest::Client,
+    status: &Status,
+    ratings: &mut Ratings,
+    on_rated: &mut dyn FnMut(&Url, &Rating),
+) {
+    let mirrors = interleave_countries(
+        status
+            .urls
+            .iter()
+            .filter(|mirror| !ratings.contains(&mirror.url)),
+    )
+    .into_iter()
+    .map(|mirror| (mirror.url.clone(), mirror.protocol))
+    .collect::<Vec<_>>();
+    notify::status(&format!("Rating {} mirrors", mirrors.len()));
+    let history_path = history_path(run_options);
+    let mut history = history_path
+        .as_deref()
+        .map(History::load)
+        .unwrap_or_default();
+    let mut on_result = |url: &Url, result: &Result<Rating>| {
+        notify::progress();
+        if let Ok(rating) = result {
+            on_rated(url, &smoothed(&history, run_options.smooth, url, *rating));
+        }
+    };
+    let mut results: HashMap<_, _> = prober(run_options, http_client)
+        .rate_all(mirrors, &mut on_result)
+        .await
+        .into_iter()
+        .map(|(url, result)| {
+            let result = match result {
+                Ok(rating) => {
+                    tracing::debug!(
+                        mirror_url = %url,
+                        phase = "rate",
+                        "rated at {:.2} KiB/s ({} bytes in {:?}, time to first byte: {:?})",
+                        rating.rate / 1024.0,
+                        rating.bytes,
+                        rating.elapsed,
+                        rating.ttfb,
+                    );
+                    Ok(rating)
+                }
+                Err(err) => {
+                    tracing::warn!(mirror_url = %url, phase = "rate", "error while rating mirror: {err}");
+                    Err(err.to_string())
+                }
+            };
+            (url, result)
+        })
+        .collect();
+
+    let now = Timestamp::now();
+    for (url, result) in &mut results {
+        if let Ok(rating) = result {
+            let measured = rating.rate;
+            *rating = smoothed(&history, run_options.smooth, url, *rating);
+            history.record(url.as_str(), measured, run_options.smooth, now);
+        }
+    }
+    if let Some(path) = history_path {
+        if let Err(err) = history.save(&path) {
+            tracing::warn!(
+                "failed to save the rate history to {}: {err}",
+                path.display()
+            );
+        }
+    }
+    ratings.extend(results);
+}
+
+/// The rating with its rate blended with the history if "--smooth" is given.
+fn smoothed(history: &History, weight: Option<f64>, url: &Url, rating: Rating) -> Rating {
+    match weight {
+        Some(weight) => Rating {
+            rate: history.smooth(url.as_str(), rating.rate, weight),
+            ..rating
+        },
+        None => rating,
+    }
+}
+
+/// The file in which the rates measured in previous runs are kept, if caching is enabled.
+fn history_path(run_options: &RunOptions) -> Option<PathBuf> {
+    if run_options.no_cache {
+        return None;
+    }
+    crate::get_cache_dir(run_options)
+        .map(|cache_dir| cache_dir.join(HISTORY_FILE_NAME))
+        .ok()
+}
+
+/// Measure the latency of all mirrors of `status`. Mirrors whose latency could not be
+/// measured are logged as warnings and left out.
+#[tracing::instrument(name = "latency", skip_all)]
+pub async fn measure_latencies(
+    run_options: &RunOptions,
+    http_client: &reqwest::Client,
+    status: &Status,
+) -> HashMap<Url, Duration> {
+    let mirrors = interleave_countries(&status.urls)
+        .into_iter()
+        .map(|mirror| (mirror.url.clone(), mirror.protocol));
+    prober(run_options, http_client)
+        .probe_all(
+        mirrors,
+        |probe, url, protocol| async move { probe.latency(&url, protocol).await }.boxed(),
+        &mut |_, _| notify::progress(),
+    )
+    .await
+    .into_iter()
+    .filter_map(|(url, result)| match result {
+        Ok(latency) => {
+            tracing::debug!(mirror_url = %url, phase = "latency", "latency of {latency:?}");
+            Some((url, latency))
+        }
+        Err(err) => {
+            tracing::warn!(mirror_url = %url, phase = "latency", "error while measuring latency: {err}");
+            None
+        }
+    })
+    .collect()
+}
+
+/// Retrieve the lastsync file of all HTTP(S) mirrors of `status`, which holds the time of
+/// their last synchronization. Mirrors whose file could not be retrieved are logged as
+/// warnings and map to nothing.
+#[tracing::instrument(name = "verify", skip_all)]
+pub async fn fetch_last_syncs(
+    run_options: &RunOptions,
+    http_client: &reqwest::Client,
+    status: &Status,
+) -> HashMap<Url, Option<Timestamp>> {
+    let mirrors = interleave_countries(&status.urls)
+        .into_iter()
+        .filter(|mirror| matches!(mirror.protocol, Protocol::Http | Protocol::Https))
+        .map(|mirror| (mirror.url.clone(), mirror.protocol))
+        .collect::<Vec<_>>();
+    notify::status(&format!(
+        "Verifying the last synchronization of {} mirrors",
+        mirrors.len()
+    ));
+    prober(run_options, http_client)
+        .probe_all(
+        mirrors,
+        |probe, url, _| async move { probe.last_sync(&url).await }.boxed(),
+        &mut |_, _| notify::progress(),
+    )
+    .await
+    .into_iter()
+    .map(|(url, result)| match result {
+        Ok(last_sync) => {
+            tracing::debug!(mirror_url = %url, phase = "verify", "last synchronized at {last_sync}");
+            (url, Some(last_sync))
+        }
+        Err(err) => {
+            tracing::warn!(mirror_url = %url, phase = "verify", "error while retrieving lastsync: {err}");
+            (url, None)
+        }
+    })
+    .collect()
+}
+
+/// Check that the database of each mirror of `status` can be retrieved, returning the
+/// errors of the mirrors where it could not.
+#[tracing::instrument(name = "verify", skip_all)]
+pub async fn check_reachable(
+    run_options: &RunOptions,
+    http_client: &reqwest::Client,
+    status: &Status,
+) -> HashMap<Url, String> {
+    let mirrors = interleave_countries(&status.urls)
+        .into_iter()
+        .map(|mirror| (mirror.url.clone(), mirror.protocol))
+        .collect::<Vec<_>>();
+    notify::status(&format!(
+        "Verifying that {} mirrors are reachable",
+        mirrors.len()
+    ));
+    prober(run_options, http_client)
+        .probe_all(
+            mirrors,
+            |probe, url, protocol| async move { probe.reachable(&url, protocol).await }.boxed(),
+            &mut |_, _| notify::progress(),
+        )
+        .await
+        .into_iter()
+        .filter_map(|(url, result)| {
+            let err = result.err()?;
+            tracing::debug!(mirror_url = %url, phase = "verify", "unreachable: {err}");
+            Some((url, err.to_string()))
+        })
+        .collect()
+}
+
+/// Retrieve the release of the ISO in the "iso/latest" directory of all HTTP(S) mirrors of
+/// `status`, after checking that the ISO itself can be retrieved. Maps the mirrors where
+/// this failed to the error.
+#[tracing::instrument(name = "verify", skip_all)]
+pub async fn fetch_iso_versions(
+    run_options: &RunOptions,
+    http_client: &reqwest::Client,
+    status: &Status,
+) -> HashMap<Url, Result<String, String>> {
+    let mirrors = interleave_countries(&status.urls)
+        .into_iter()
+        .filter(|mirror| matches!(mirror.protocol, Protocol::Http | Protocol::Https))
+        .map(|mirror| (mirror.url.clone(), mirror.protocol))
+        .collect::<Vec<_>>();
+    notify::status(&format!("Verifying the ISOs of {} mirrors", mirrors.len()));
+    prober(run_options, http_client)
+        .probe_all(
+        mirrors,
+        |probe, url, _| async move { probe.iso_version(&url).await }.boxed(),
+        &mut |_, _| notify::progress(),
+    )
+    .await
+    .into_iter()
+    .map(|(url, result)| {
+        let result = result
+            .inspect(|version| {
+                tracing::debug!(mirror_url = %url, phase = "verify", "serves the ISO of {version}");
+            })
+            .map_err(|err| {
+                tracing::debug!(mirror_url = %url, phase = "verify", "error while verifying the ISO: {err}");
+                err.to_string()
+            });
+        (url, result)
+    })
+    .collect()
+}
+
+/// A prober with the settings of the command line.
+fn prober(run_options: &RunOptions, http_client: &reqwest::Client) -> Prober {
+    let mut prober = Prober::new(http_client.clone())
+        .db_path(run_options.db_path())
+        .connection_timeout(Duration::from_secs(run_options.connection_timeout))
+        .rsync_extra_args(&run_options.rsync_extra_args)
+        .threads(run_options.threads.max())
+        .adaptive(run_options.threads == Threads::Auto);
+    if let Some(proxy) = &run_options.proxy {
+        prober = prober.proxy(proxy.clone());
+    }
+    // Ratings are only retried with "--retry-rating".
+    if run_options.retry_rating {
+        prober = prober.retries(run_options.retries);
+    }
+    if let Some(download_size) = run_options.download_size {
+        prober = prober.download_size(download_size);
+    }
+    if let Some(rsync_threads) = run_options.rsync_threads {
+        prober = prober.rsync_threads(rsync_threads);
+    }
+    if let Some(rate_delay) = run_options.rate_delay {
+        prober = prober.spacing(Duration::from_millis(rate_delay));
+    }
+    if let Some(rate_timeout) = run_options.rate_timeout {
+        prober = prober.rate_timeout(Duration::from_secs(rate_timeout));
+    }
+    #[cfg(feature = "http3")]
+    {
+        prober = prober.http3(run_options.http3);
+    }
+    prober
+}
+
+/// Rate explicitly given mirrors and print the results, fastest first.
+pub async fn rate_urls(
+    rate_options: &RateOptions,
+    run_options: &RunOptions,
+    http_client: &reqwest::Client,
+    mut out: impl Write,
+) -> Result<()> {
+    let mirrors = rate_options
+        .urls
+        .iter()
+        .map(base_url)
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut results = prober(run_options, http_client)
+        .rate_all(mirrors, &mut |_, _| notify::progress())
+        .await
+        .into_iter()
+        .collect::<Vec<_>>();
+    results.sort_by(|(_, a), (_, b)| match (a, b) {
+        (Ok(a), Ok(b)) => b.rate.partial_cmp(&a.rate).unwrap_or(Ordering::Equal),
+        (Ok(_), Err(_)) => Ordering::Less,
+        (Err(_), Ok(_)) => Ordering::Greater,
+        (Err(_), Err(_)) => Ordering::Equal,
+    });
+    for (url, result) in results {
+        match result {
+            Ok(rating) => {
+                let ttfb = rating.ttfb.map_or_else(
+                    || "n/a".to_owned(),
+                    |ttfb| format!("{} ms", ttfb.as_millis()),
+                );
+                writeln!(
+                    out,
+                    "{url}  {:.2} KiB/s  (time to first byte: {ttfb})",
+                    rating.rate / 1024.0
+                )?;
+            }
+            Err(err) => writeln!(out, "{url}  error: {err}")?,
+        }
+    }
+    Ok(())
+}
+
+/// Rate two mirrors in alternating order and print which one is faster.
+#[allow(clippy::cast_precision_loss)]
+pub async fn compare(
+    compare_options: &CompareOptions,
+    run_options: &RunOptions,
+    http_client: &reqwest::Client,
+    mut out: impl Write,
+) -> Result<()> {
+    let (url_a, protocol_a) = base_url(&compare_options.url_a)?;
+    let (url_b, protocol_b) = base_url(&compare_options.url_b)?;
+    let probe = prober(run_options, http_client);
+
+    let mut rates_a = Vec::new();
+    let mut rates_b = Vec::new();
+    for round in 0..compare_options.rounds {
+        // Alternate which mirror goes first so neither consistently benefits from warm
+        // caches or suffers from transient congestion.
+        let mut probes = [
+            (&url_a, protocol_a, &mut rates_a),
+            (&url_b, protocol_b, &mut rates_b),
+        ];
+        if round % 2 == 1 {
+            probes.reverse();
+        }
+        for (url, protocol, rates) in probes {
+            match probe.rate(url, protocol).await {
+                Ok(rating) => rates.push(rating.rate),
+                Err(err) => {
+                    tracing::warn!(mirror_url = %url, phase = "rate", "error while rating mirror: {err}");
+                }
+            }
+        }
+    }
+
+    let stats_a = Stats::new(&rates_a);
+    let stats_b = Stats::new(&rates_b);
+    for (url, stats) in [(&url_a, &stats_a), (&url_b, &stats_b)] {
+        match stats {
+            Some(stats) => writeln!(
+                out,
+                "{url}  {:.2} ± {:.2} KiB/s  ({} measurements)",
+                stats.mean / 1024.0,
+                stats.stddev / 1024.0,
+                stats.count
+            )?,
+            None => writeln!(out, "{url}  every measurement failed")?,
+        }
+    }
+    let verdict = verdict((&url_a, stats_a.as_ref()), (&url_b, stats_b.as_ref()))?;
+    writeln!(out, "{verdict}")?;
+    Ok(())
+}
+
+/// Which of two mirrors is faster and whether the difference is significant.
+#[allow(clippy::cast_precision_loss)]
+fn verdict(
+    (url_a, stats_a): (&Url, Option<&Stats>),
+    (url_b, stats_b): (&Url, Option<&Stats>),
+) -> Result<String> {
+    // A mirror that failed every measurement or never downloaded anything has no rate to
+    // compare with.
+    let failed = |stats: Option<&Stats>| stats.is_none_or(|stats| stats.mean <= 0.0);
+    let (stats_a, stats_b) = match (stats_a, stats_b) {
+        _ if failed(stats_a) && failed(stats_b) => {
+            anyhow::bail!("not enough successful measurements to compare the mirrors")
+        }
+        _ if failed(stats_a) => return Ok(format!("{url_a} failed, so {url_b} is faster")),
+        _ if failed(stats_b) => return Ok(format!("{url_b} failed, so {url_a} is faster")),
+        (Some(stats_a), Some(stats_b)) => (stats_a, stats_b),
+        _ => unreachable!("mirrors without measurements have failed"),
+    };
+
+    // Welch's t-test. A |t| of about 2 or more means the difference is unlikely to be
+    // caused by measurement noise alone.
+    let standard_error = (stats_a.variance() / stats_a.count as f64
+        + stats_b.variance() / stats_b.count as f64)
+        .sqrt();
+    let t = if standard_error > 0.0 {
+        (stats_a.mean - stats_b.mean) / standard_error
+    } else {
+        0.0
+    };
+    let (faster, slower) = if stats_a.mean >= stats_b.mean {
+        ((url_a, stats_a), (url_b, stats_b))
+    } else {
+        ((url_b, stats_b), (url_a, stats_a))
+    };
+    let percent = (faster.1.mean / slower.1.mean - 1.0) * 100.0;
+    let confidence = if t.abs() >= 2.0 {
+        "the difference is significant"
+    } else {
+        "the difference is within the measurement noise"
+    };
+    Ok(format!(
+        "{} is {percent:.1}% faster than {} (t = {t:.2}, {confidence})",
+        faster.0, slower.0
+    ))
+}
+
+/// The sample mean and standard deviation of a set of measurements.
+struct Stats {
+    count: usize,
+    mean: f64,
+    stddev: f64,
+}
+
+impl Stats {
+    #[allow(clippy::cast_precision_loss)]
+    fn new(values: &[f64]) -> Option<Self> {
+        if values.is_empty() {
+            return None;
+        }
+        let count = values.len();
+        let mean = values.iter().sum::<f64>() / count as f64;
+        let stddev = if count > 1 {
+            (values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (count - 1) as f64).sqrt()
+        } else {
+            0.0
+        };
+        Some(Self {
+            count,
+            mean,
+            stddev,
+        })
+    }
+
+    fn variance(&self) -> f64 {
+        self.stddev.powi(2)
+    }
+}
+
+/// Determine the protocol of a mirror from its URL and make sure the URL ends with a slash,
+/// since paths are joined relative to it.
+fn base_url(url: &Url) -> Result<(Url, Protocol)> {
+    let protocol = url
+        .scheme()
+        .parse::<Protocol>()
+        .map_err(|err| anyhow::anyhow!("{url}: {err}"))?;
+    let mut url = url.clone();
+    if !url.path().ends_with('/') {
+        url.set_path(&format!("{}/", url.path()));
+    }
+    Ok((url, protocol))
+}
+
+/// The most mirrors that are rated at the same time with "--threads auto".
+const MAX_AUTO_THREADS: usize = 16;
+
+/// The number of mirrors to rate at the same time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Threads {
+    /// Always rate this many mirrors at the same time.
+    Fixed(usize),
+    /// Adapt the number of mirrors to the available bandwidth.
+    Auto,
+}
+
+impl Threads {
+    /// The most mirrors that are rated at the same time.
+    pub fn max(self) -> usize {
+        match self {
+            Self::Fixed(threads) => threads,
+            Self::Auto => MAX_AUTO_THREADS,
+        }
+    }
+}
+
+/// Parse a number of threads or "auto".
+pub fn parse_threads(s: &str) -> Result<Threads, String> {
+    match s.trim() {
+        "auto" => Ok(Threads::Auto),
+        s => s
+            .parse()
+            .map(Threads::Fixed)
+            .map_err(|_| format!("invalid number of threads '{s}', expected a number or 'auto'")),
+    }
+}
+
+/// Parse a size in bytes with an optional binary or decimal unit, e.g. "2MiB" or "500kB".
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    const UNITS: &[(&str, u64)] = &[
+        ("", 1),
+        ("B", 1),
+        ("KiB", 1 << 10),
+        ("MiB", 1 << 20),
+        ("GiB", 1 << 30),
+        ("kB", 1000),
+        ("MB", 1000 * 1000),
+        ("GB", 1000 * 1000 * 1000),
+    ];
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number = number
+        .parse::<u64>()
+        .map_err(|_| format!("invalid size '{s}'"))?;
+    let multiplier = UNITS
+        .iter()
+        .find(|(name, _)| *name == unit.trim())
+        .map(|(_, multiplier)| *multiplier)
+        .ok_or_else(|| format!("unknown unit '{unit}', expected B, KiB, MiB, GiB, kB, MB or GB"))?;
+    match number.checked_mul(multiplier) {
+        Some(0) => Err("the size must be greater than zero".to_owned()),
+        Some(size) => Ok(size),
+        None => Err(format!("size '{s}' is too large")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_sizes() {
+        assert_eq!(parse_size("2MiB"), Ok(2 * 1024 * 1024));
+        assert_eq!(parse_size("500kB"), Ok(500_000));
+        assert_eq!(parse_size("1024"), Ok(1024));
+        assert_eq!(parse_size("3 GB"), Ok(3_000_000_000));
+        assert!(parse_size("0B").is_err());
+        assert!(parse_size("2XB").is_err());
+        assert!(parse_size("MiB").is_err());
+    }
+
+    #[test]
+    fn compare_failed_mirrors() {
+        let url_a = Url::parse("https://a.example.se/arch/").unwrap();
+        let url_b = Url::parse("https://b.example.de/arch/").unwrap();
+        let stats = Stats::new(&[100.0, 100.0]).unwrap();
+        let zero = Stats::new(&[0.0]).unwrap();
+        assert_eq!(
+            verdict((&url_a, Some(&stats)), (&url_b, Some(&zero))).unwrap(),
+            format!("{url_b} failed, so {url_a} is faster")
+        );
+        assert_eq!(
+            verdict((&url_a, None), (&url_b, Some(&stats))).unwrap(),
+            format!("{url_a} failed, so {url_b} is faster")
+        );
+        assert!(verdict((&url_a, None), (&url_b, Some(&zero))).is_err());
+        let faster = Stats::new(&[150.0, 150.0]).unwrap();
+        assert!(
+            verdict((&url_a, Some(&stats)), (&url_b, Some(&faster)))
+                .unwrap()
+                .starts_with(&format!("{url_b} is 50.0% faster than {url_a}"))
+        );
+    }
+
+    #[test]
+    fn parse_thread_counts() {
+        assert_eq!(parse_threads("4"), Ok(Threads::Fixed(4)));
+        assert_eq!(parse_threads("auto"), Ok(Threads::Auto));
+        assert_eq!(Threads::Auto.max(), MAX_AUTO_THREADS);
+        assert!(parse_threads("many").is_err());
+    }
+}
@@ -0,0 +1,476 @@
+//! Writing the generated mirrorlist to disk.
+use crate::{RestoreOptions, RunOptions};
+use anyhow::Result;
+use arch_mirrors_rs::Status;
+use jiff::civil::DateTime;
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Programs that can be used to install the mirrorlist with elevated privileges, in order
+/// of preference.
+const ESCALATION_PROGRAMS: &[&str] = &["sudo", "doas", "pkexec"];
+
+/// Write `contents` to `path`. If the file already exists and the session is interactive,
+/// a summary of the change is shown and the user is asked for confirmation first, unless
+/// "--yes" was given.
+///
+/// If the file cannot be written due to missing permissions, the contents are written to
+/// a temporary file which is then installed using a privilege escalation program. This
+/// happens automatically with "--escalate", and after confirmation in interactive sessions.
+///
+/// With "--backup-count n", the previous file is kept as a timestamped backup next to it and
+/// all but the n most recent backups are removed.
+pub fn save(path: &Path, status: &Status, contents: &[u8], run_options: &RunOptions) -> Result<()> {
+    let interactive = is_interactive();
+    if !run_options.yes
+        && interactive
+        && path.exists()
+        && !confirm_overwrite(path, status, contents)?
+    {
+        anyhow::bail!("not overwriting {}", path.display());
+    }
+    install(path, contents, run_options)
+}
+
+/// Restore a backup created by [`save`], or list the available backups.
+pub fn restore(options: &RestoreOptions, run_options: &RunOptions) -> Result<()> {
+    let path = options.mirrorlist.as_path();
+    let backups = list_backups(path)?;
+    if options.list {
+        let mut out = io::stdout().lock();
+        for backup in &backups {
+            let servers = fs::read_to_string(&backup.path)
+                .map(|content| server_urls(&content).count())
+                .unwrap_or_default();
+            writeln!(
+                out,
+                "{}  {}  ({servers} servers)",
+                backup.time,
+                backup.path.display()
+            )?;
+        }
+        return Ok(());
+    }
+
+    let Some(backup) = find_backup(&backups, options.backup.as_deref()) else {
+        anyhow::bail!("no matching backup of {} found", path.display());
+    };
+    let contents = fs::read(&backup.path)?;
+    install(path, &contents, run_options)?;
+    eprintln!("restored {} from {}", path.display(), backup.path.display());
+    Ok(())
+}
+
+/// The backup named by its time or file name, or the most recent one if none is `wanted`.
+fn find_backup<'a>(backups: &'a [Backup], wanted: Option<&str>) -> Option<&'a Backup> {
+    match wanted {
+        Some(wanted) => backups.iter().find(|backup| {
+            backup.time.to_string() == wanted
+                || backup.path.file_name().is_some_and(|name| name == wanted)
+        }),
+        None => backups.first(),
+    }
+}
+
+/// Atomically replace `path` with `contents`, rotating backups and escalating privileges as
+/// configured.
+fn install(path: &Path, contents: &[u8], run_options: &RunOptions) -> Result<()> {
+    let interactive = is_interactive();
+    let now = jiff::Zoned::now().datetime();
+    let backups = plan_backups(path, run_options.backup_count, now)?;
+    match write_with_backups(path, contents, &backups) {
+        Err(err) if err.kind() == io::ErrorKind::PermissionDenied => {
+            let Some(program) = find_escalation_program() else {
+                return Err(err.into());
+            };
+            let escalate = run_options.escalate
+                || (interactive
+                    && prompt(&format!(
+                        "Permission denied writing {}. Install it using {}?",
+                        path.display(),
+                        program.display()
+                    ))?);
+            if !escalate {
+                return Err(err.into());
+            }
+            install_escalated(&program, path, contents, &backups)
+        }
+        result => Ok(result?),
+    }
+}
+
+/// A backup of a saved file, named after the time at which it was replaced.
+pub struct Backup {
+    pub path: PathBuf,
+    pub time: DateTime,
+}
+
+/// List the backups of `path`, most recent first.
+pub fn list_backups(path: &Path) -> io::Result<Vec<Backup>> {
+    let (Some(dir), Some(file_name)) = (path.parent(), path.file_name()) else {
+        return Ok(Vec::new());
+    };
+    let dir = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
+    let prefix = format!("{}.", file_name.to_string_lossy());
+    let mut backups = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let Some(time) = name
+            .to_str()
+            .and_then(|name| name.strip_prefix(&prefix))
+            .and_then(|suffix| suffix.parse::<DateTime>().ok())
+        else {
+            continue;
+        };
+        backups.push(Backup {
+            path: entry.path(),
+            time,
+        });
+    }
+    backups.sort_by_key(|backup| Reverse(backup.time));
+    Ok(backups)
+}
+
+/// The backups to create and remove when overwriting a file.
+#[derive(Default)]
+struct BackupPlan {
+    create: Option<PathBuf>,
+    remove: Vec<PathBuf>,
+}
+
+/// Plan a backup of `path` named after `now`, keeping at most `count` backups.
+fn plan_backups(path: &Path, count: usize, now: DateTime) -> io::Result<BackupPlan> {
+    if count == 0 || !path.is_file() {
+        return Ok(BackupPlan::default());
+    }
+    let backup_path = |time: DateTime| {
+        let mut backup = path.as_os_str().to_owned();
+        backup.push(format!(".{time}"));
+        PathBuf::from(backup)
+    };
+    // Backups are named to the millisecond, and one made within the same millisecond as
+    // another is named a millisecond later rather than overwriting it.
+    let mut time = now
+        .round(jiff::Unit::Millisecond)
+        .map_err(io::Error::other)?;
+    while backup_path(time).exists() {
+        time = time
+            .checked_add(jiff::SignedDuration::from_millis(1))
+            .map_err(io::Error::other)?;
+    }
+    let remove = list_backups(path)?
+        .into_iter()
+        .map(|backup| backup.path)
+        .skip(count - 1)
+        .collect();
+    Ok(BackupPlan {
+        create: Some(backup_path(time)),
+        remove,
+    })
+}
+
+fn write_with_backups(path: &Path, contents: &[u8], backups: &BackupPlan) -> io::Result<()> {
+    if let Some(backup) = &backups.create {
+        fs::copy(path, backup)?;
+    }
+    write_atomic(path, contents)?;
+    for backup in &backups.remove {
+        fs::remove_file(backup)?;
+    }
+    Ok(())
+}
+
+/// Write `contents` to a temporary file next to `path` and rename it into place, so readers
+/// never observe a partially written file. The file and the rename are synced to disk, so a
+/// crash leaves either the old or the new file behind, never an empty one.
+fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let mut temp_file = tempfile::NamedTempFile::new_in(dir)?;
+    temp_file.write_all(contents)?;
+    // Temporary files are only readable by their owner, but mirrorlists need to be readable
+    // by everyone. Keep the permissions of the file being replaced, if any.
+    if let Ok(metadata) = fs::metadata(path) {
+        temp_file
+            .as_file()
+            .set_permissions(metadata.permissions())?;
+    } else {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            temp_file
+                .as_file()
+                .set_permissions(fs::Permissions::from_mode(0o644))?;
+        }
+    }
+    temp_file.as_file().sync_all()?;
+    temp_file.persist(path)?;
+    #[cfg(unix)]
+    fs::File::open(dir)?.sync_all()?;
+    Ok(())
+}
+
+fn is_interactive() -> bool {
+    io::stdin().is_terminal() && io::stderr().is_terminal()
+}
+
+/// Ask a yes/no question on STDERR and read the answer from STDIN. Defaults to no.
+fn prompt(question: &str) -> Result<bool> {
+    let mut err = io::stderr().lock();
+    write!(err, "{question} [y/N] ")?;
+    err.flush()?;
+
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes" | "Yes"))
+}
+
+pub fn find_escalation_program() -> Option<PathBuf> {
+    let paths = std::env::var_os("PATH")?;
+    ESCALATION_PROGRAMS.iter().find_map(|program| {
+        std::env::split_paths(&paths)
+            .map(|dir| dir.join(program))
+            .find(|candidate| candidate.is_file())
+    })
+}
+
+/// Write `contents` to a temporary file as the current user, then use `program` to copy it
+/// next to `path` and atomically rename it into place. Only the final install step, which
+/// also takes care of the backups, runs with elevated privileges.
+fn install_escalated(
+    program: &Path,
+    path: &Path,
+    contents: &[u8],
+    backups: &BackupPlan,
+) -> Result<()> {
+    const SCRIPT: &str = r#"
+        set -e
+        if [ -n "$3" ]; then cp -p -- "$2" "$3"; fi
+        install -m 0644 -- "$1" "$2.reflector-new"
+        sync -- "$2.reflector-new" || true
+        mv -f -- "$2.reflector-new" "$2"
+        shift 3
+        if [ "$#" -gt 0 ]; then rm -f -- "$@"; fi
+    "#;
+
+    let mut temp_file = tempfile::NamedTempFile::new()?;
+    temp_file.write_all(contents)?;
+    temp_file.flush()?;
+
+    // The paths are passed as positional parameters so they never need to be quoted.
+    let exit_status = Command::new(program)
+        .arg("sh")
+        .arg("-c")
+        .arg(SCRIPT)
+        .arg("sh")
+        .arg(temp_file.path())
+        .arg(path)
+        .arg(backups.create.as_deref().unwrap_or(Path::new("")))
+        .args(&backups.remove)
+        .status()?;
+    if !exit_status.success() {
+        anyhow::bail!(
+            "failed to install {} using {}: {exit_status}",
+            path.display(),
+            program.display()
+        );
+    }
+    Ok(())
+}
+
+/// Extract the server URLs from the `Server = ...` lines of a mirrorlist.
+pub fn server_urls(content: &str) -> impl Iterator<Item = &str> {
+    content.lines().filter_map(|line| {
+        let (key, value) = line.split_once('=')?;
+        (key.trim() == "Server").then(|| value.trim())
+    })
+}
+
+/// How the servers of a mirrorlist changed.
+#[derive(Debug, PartialEq, Eq)]
+struct DiffStat {
+    /// The number of servers only in the new list.
+    added: usize,
+    /// The number of servers only in the old list.
+    removed: usize,
+    /// The number of servers in both lists whose position among the servers in both lists
+    /// changed.
+    reordered: usize,
+}
+
+/// Compare the servers of the mirrorlists `old` and `new`.
+fn diff_stat(old: &str, new: &str) -> DiffStat {
+    let old_servers = server_urls(old).collect::<HashSet<_>>();
+    let new_servers = server_urls(new).collect::<HashSet<_>>();
+    let kept_in = |content, other: &HashSet<&str>| {
+        let mut seen = HashSet::new();
+        server_urls(content)
+            .filter(|url| other.contains(url) && seen.insert(*url))
+            .collect::<Vec<_>>()
+    };
+    let reordered = kept_in(old, &new_servers)
+        .into_iter()
+        .zip(kept_in(new, &old_servers))
+        .filter(|(old, new)| old != new)
+        .count();
+    DiffStat {
+        added: new_servers.difference(&old_servers).count(),
+        removed: old_servers.difference(&new_servers).count(),
+        reordered,
+    }
+}
+
+/// Print a summary of the pending change to STDERR and ask whether to proceed.
+fn confirm_overwrite(path: &Path, status: &Status, contents: &[u8]) -> Result<bool> {
+    let old = fs::read_to_string(path).unwrap_or_default();
+    let new = String::from_utf8_lossy(contents);
+    let DiffStat {
+        added,
+        removed,
+        reordered,
+    } = diff_stat(&old, &new);
+
+    let mut err = io::stderr().lock();
+    writeln!(
+        err,
+        "{} already exists. The new list contains {} mirrors ({added} added, {removed} removed, \
+         {reordered} reordered).",
+        path.display(),
+        status.urls.len(),
+    )?;
+    for mirror in status.urls.iter().take(3) {
+        writeln!(err, "  {}", mirror.url)?;
+    }
+    if status.urls.len() > 3 {
+        writeln!(err, "  ...")?;
+    }
+    drop(err);
+    prompt(&format!("Overwrite {}?", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn save_at(path: &Path, contents: &str, count: usize, now: DateTime) {
+        let backups = plan_backups(path, count, now).unwrap();
+        write_with_backups(path, contents.as_bytes(), &backups).unwrap();
+    }
+
+    #[test]
+    fn rotate_backups() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mirrorlist");
+        let now = "2024-05-01T10:00:00".parse::<DateTime>().unwrap();
+        save_at(&path, "0", 2, now);
+        for (hour, contents) in [(1, "1"), (2, "2"), (3, "3")] {
+            save_at(
+                &path,
+                contents,
+                2,
+                now.checked_add(jiff::Span::new().hours(hour)).unwrap(),
+            );
+        }
+        let backups = list_backups(&path).unwrap();
+        let times = backups
+            .iter()
+            .map(|backup| backup.time.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(times, ["2024-05-01T13:00:00", "2024-05-01T12:00:00"]);
+        assert_eq!(fs::read_to_string(&backups[0].path).unwrap(), "2");
+        assert_eq!(fs::read_to_string(&backups[1].path).unwrap(), "1");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "3");
+    }
+
+    #[test]
+    fn keep_backups_of_the_same_second() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mirrorlist");
+        let now = "2024-05-01T10:00:00".parse::<DateTime>().unwrap();
+        fs::write(&path, "0").unwrap();
+        save_at(&path, "1", 5, now);
+        save_at(&path, "2", 5, now);
+        let contents = list_backups(&path)
+            .unwrap()
+            .iter()
+            .map(|backup| fs::read_to_string(&backup.path).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(contents, ["1", "0"]);
+    }
+
+    #[test]
+    fn select_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mirrorlist");
+        fs::write(&path, "0").unwrap();
+        let now = "2024-05-01T10:00:00".parse::<DateTime>().unwrap();
+        save_at(&path, "1", 3, now);
+        save_at(
+            &path,
+            "2",
+            3,
+            now.checked_add(jiff::Span::new().days(1)).unwrap(),
+        );
+        let backups = list_backups(&path).unwrap();
+        let contents = |backup: Option<&Backup>| fs::read_to_string(&backup.unwrap().path).unwrap();
+        assert_eq!(contents(find_backup(&backups, None)), "1");
+        assert_eq!(
+            contents(find_backup(&backups, Some("2024-05-01T10:00:00"))),
+            "0"
+        );
+        assert_eq!(
+            contents(find_backup(
+                &backups,
+                Some("mirrorlist.2024-05-02T10:00:00")
+            )),
+            "1"
+        );
+        assert!(find_backup(&backups, Some("2024-05-03T10:00:00")).is_none());
+    }
+
+    #[test]
+    fn count_changed_servers() {
+        let list = |urls: &[&str]| {
+            urls.iter()
+                .map(|url| format!("Server = https://{url}/$repo/os/$arch"))
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        let old = list(&["a", "b", "c", "d"]);
+        assert_eq!(
+            diff_stat(&old, &old),
+            DiffStat {
+                added: 0,
+                removed: 0,
+                reordered: 0
+            }
+        );
+        assert_eq!(
+            diff_stat(&old, &list(&["e", "a", "c", "b"])),
+            DiffStat {
+                added: 1,
+                removed: 1,
+                reordered: 2
+            }
+        );
+        assert_eq!(
+            diff_stat("", &list(&["a"])),
+            DiffStat {
+                added: 1,
+                removed: 0,
+                reordered: 0
+            }
+        );
+    }
+}
@@ -0,0 +1,238 @@
+//! Choosing and reordering the mirrors in a terminal interface for "--tui".
+use anyhow::Result;
+use arch_mirrors_rs::Mirror;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Style, Stylize};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Row, Table, TableState};
+use ratatui::{DefaultTerminal, Frame};
+use reqwest::Url;
+use std::cmp::Ordering;
+use std::time::Duration;
+use tokio::sync::mpsc::UnboundedReceiver;
+use tokio::sync::mpsc::error::TryRecvError;
+
+const HELP: &str =
+    "↑/↓ move  space check  shift+↑/↓ reorder  r sort by rate  enter confirm  q cancel";
+
+/// A mirror in the list.
+pub struct Entry {
+    url: Url,
+    country_code: String,
+    score: Option<f64>,
+    /// The measured rate in bytes per second, once the mirror has been rated.
+    rate: Option<f64>,
+    checked: bool,
+}
+
+impl Entry {
+    pub fn new(mirror: &Mirror) -> Self {
+        Self {
+            url: mirror.url.clone(),
+            country_code: mirror.country_code.clone(),
+            score: mirror.score,
+            rate: None,
+            checked: true,
+        }
+    }
+}
+
+/// Show the mirrors, all of them checked, and let the user choose and reorder them while
+/// their rates arrive through `rates`. Returns the URLs of the checked mirrors in the
+/// chosen order, or nothing if the user cancelled.
+pub fn choose(
+    entries: Vec<Entry>,
+    rates: UnboundedReceiver<(Url, f64)>,
+) -> Result<Option<Vec<Url>>> {
+    let mut terminal = ratatui::init();
+    let result = App::new(entries).run(&mut terminal, rates);
+    ratatui::restore();
+    result
+}
+
+struct App {
+    entries: Vec<Entry>,
+    state: TableState,
+    rating: bool,
+}
+
+impl App {
+    fn new(entries: Vec<Entry>) -> Self {
+        Self {
+            entries,
+            state: TableState::new().with_selected(Some(0)),
+            rating: true,
+        }
+    }
+
+    fn run(
+        mut self,
+        terminal: &mut DefaultTerminal,
+        mut rates: UnboundedReceiver<(Url, f64)>,
+    ) -> Result<Option<Vec<Url>>> {
+        loop {
+            while self.rating {
+                match rates.try_recv() {
+                    Ok((url, rate)) => self.set_rate(&url, rate),
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        self.rating = false;
+                        // Warnings about failed ratings are logged when rating completes,
+                        // so the screen has to be redrawn from scratch.
+                        terminal.clear()?;
+                    }
+                }
+            }
+            terminal.draw(|frame| self.draw(frame))?;
+
+            if !event::poll(Duration::from_millis(100))? {
+                continue;
+            }
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            let shift = key.modifiers.contains(KeyModifiers::SHIFT);
+            match key.code {
+                KeyCode::Up if shift => self.move_entry(-1),
+                KeyCode::Down if shift => self.move_entry(1),
+                KeyCode::Char('K') => self.move_entry(-1),
+                KeyCode::Char('J') => self.move_entry(1),
+                KeyCode::Up | KeyCode::Char('k') => self.move_cursor(-1),
+                KeyCode::Down | KeyCode::Char('j') => self.move_cursor(1),
+                KeyCode::Char(' ') => self.toggle(),
+                KeyCode::Char('r') => self.sort_by_rate(),
+                KeyCode::Enter => return Ok(Some(self.chosen())),
+                KeyCode::Esc | KeyCode::Char('q') => return Ok(None),
+                _ => {}
+            }
+        }
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [table_area, help_area] =
+            Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(frame.area());
+        let rows = self.entries.iter().map(|entry| {
+            Row::new([
+                if entry.checked { "[x]" } else { "[ ]" }.to_owned(),
+                entry.url.to_string(),
+                entry.country_code.clone(),
+                entry
+                    .score
+                    .map(|score| format!("{score:.2}"))
+                    .unwrap_or_default(),
+                entry.rate.map_or_else(
+                    || "-".to_owned(),
+                    |rate| format!("{:.2} KiB/s", rate / 1024.0),
+                ),
+            ])
+        });
+        let widths = [
+            Constraint::Length(3),
+            Constraint::Fill(1),
+            Constraint::Length(7),
+            Constraint::Length(6),
+            Constraint::Length(16),
+        ];
+        let checked = self.entries.iter().filter(|entry| entry.checked).count();
+        let title = format!(
+            " {checked} of {} mirrors checked{} ",
+            self.entries.len(),
+            if self.rating { ", rating…" } else { "" }
+        );
+        let table = Table::new(rows, widths)
+            .header(Row::new(["", "URL", "Country", "Score", "Rate"]).bold())
+            .row_highlight_style(Style::new().reversed())
+            .block(Block::bordered().title(title));
+        frame.render_stateful_widget(table, table_area, &mut self.state);
+        frame.render_widget(Line::from(HELP).dim(), help_area);
+    }
+
+    fn cursor(&self) -> usize {
+        self.state.selected().unwrap_or(0)
+    }
+
+    fn move_cursor(&mut self, delta: isize) {
+        let last = self.entries.len().saturating_sub(1);
+        let cursor = self.cursor().saturating_add_signed(delta).min(last);
+        self.state.select(Some(cursor));
+    }
+
+    /// Move the entry under the cursor, along with the cursor.
+    fn move_entry(&mut self, delta: isize) {
+        let cursor = self.cursor();
+        let target = cursor.saturating_add_signed(delta);
+        if target != cursor && target < self.entries.len() {
+            self.entries.swap(cursor, target);
+            self.state.select(Some(target));
+        }
+    }
+
+    fn toggle(&mut self) {
+        let cursor = self.cursor();
+        if let Some(entry) = self.entries.get_mut(cursor) {
+            entry.checked = !entry.checked;
+        }
+    }
+
+    /// Sort the entries by rate, fastest first, with unrated entries last.
+    fn sort_by_rate(&mut self) {
+        self.entries
+            .sort_by(|a, b| b.rate.partial_cmp(&a.rate).unwrap_or(Ordering::Equal));
+    }
+
+    fn set_rate(&mut self, url: &Url, rate: f64) {
+        if let Some(entry) = self.entries.iter_mut().find(|entry| entry.url == *url) {
+            entry.rate = Some(rate);
+        }
+    }
+
+    fn chosen(self) -> Vec<Url> {
+        self.entries
+            .into_iter()
+            .filter(|entry| entry.checked)
+            .map(|entry| entry.url)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(url: &str) -> Entry {
+        Entry {
+            url: url.parse().unwrap(),
+            country_code: "SE".to_owned(),
+            score: None,
+            rate: None,
+            checked: true,
+        }
+    }
+
+    #[test]
+    fn choose_and_reorder() {
+        let mut app = App::new(vec![
+            entry("https://a.example/"),
+            entry("https://b.example/"),
+            entry("https://c.example/"),
+        ]);
+        app.move_entry(1);
+        app.move_cursor(1);
+        app.toggle();
+        app.move_cursor(-5);
+        assert_eq!(app.cursor(), 0);
+        app.set_rate(&"https://a.example/".parse().unwrap(), 10.0);
+        app.sort_by_rate();
+        assert_eq!(
+            app.chosen(),
+            vec![
+                "https://a.example/".parse::<Url>().unwrap(),
+                "https://b.example/".parse().unwrap(),
+            ]
+        );
+    }
+}
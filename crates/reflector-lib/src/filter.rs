@@ -0,0 +1,761 @@
+//! This is where the [`Filters`] of reflector, the [`Rejection`]s they record and the
+//! limits applied after them go.
+use arch_mirrors_rs::country::Continent;
+use arch_mirrors_rs::selector;
+use arch_mirrors_rs::{CountryCode, Mirror, Protocol, Status};
+use jiff::{SignedDuration, Timestamp};
+use regex::Regex;
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+
+/// The criteria of [`evaluate`] in the order they are applied, as returned by
+/// [`Rejection::criterion`].
+const CRITERIA: &[&str] = &[
+    "never synced",
+    "age",
+    "completion",
+    "country",
+    "continent",
+    "protocol",
+    "include",
+    "exclude",
+    "delay",
+    "isos",
+    "ipv4",
+    "ipv6",
+];
+
+/// The filters of reflector that apply to each mirror on its own, named after their options.
+/// The default filters only keep mirrors that synced and completed every check.
+///
+/// ```
+/// use reflector_lib::filter::Filters;
+/// use regex::Regex;
+///
+/// let filters = Filters {
+///     country: vec!["Sweden".to_owned()],
+///     exclude: vec![Regex::new(r"\.example\.se").unwrap()],
+///     ..Filters::default()
+/// };
+/// # let _ = filters;
+/// ```
+#[derive(Debug, Clone)]
+pub struct Filters {
+    /// "--age": only keep mirrors that synced in the last n hours.
+    pub age: Option<f32>,
+
+    /// "--delay": only keep mirrors with a reported delay of at most n hours.
+    pub delay: Option<f32>,
+
+    /// "--completion-percent": only keep mirrors that completed at least this percentage of
+    /// the checks.
+    pub completion_percent: u8,
+
+    /// "--country": only keep mirrors in one of these countries, by name or code.
+    pub country: Vec<String>,
+
+    /// "--continent": only keep mirrors on one of these continents.
+    pub continent: Vec<Continent>,
+
+    /// "--protocol": only keep mirrors that use one of these protocols.
+    pub protocol: Vec<Protocol>,
+
+    /// "--include": only keep mirrors whose URL matches one of these expressions.
+    pub include: Vec<Regex>,
+
+    /// "--exclude": remove mirrors whose URL matches one of these expressions.
+    pub exclude: Vec<Regex>,
+
+    /// "--isos": only keep mirrors that host ISOs.
+    pub isos: bool,
+
+    /// "--ipv4": only keep mirrors that support IPv4.
+    pub ipv4: bool,
+
+    /// "--ipv6": only keep mirrors that support IPv6.
+    pub ipv6: bool,
+}
+
+impl Default for Filters {
+    fn default() -> Self {
+        Self {
+            age: None,
+            delay: None,
+            completion_percent: 100,
+            country: Vec::new(),
+            continent: Vec::new(),
+            protocol: Vec::new(),
+            include: Vec::new(),
+            exclude: Vec::new(),
+            isos: false,
+            ipv4: false,
+            ipv6: false,
+        }
+    }
+}
+
+/// The reason why a mirror was excluded from the results.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum Rejection {
+    /// The mirror has never synchronized.
+    NotSynced,
+    /// The mirror synchronized longer ago than "--age" allows.
+    TooOld,
+    /// The completion percentage is below "--completion-percent".
+    Incomplete(f64),
+    /// The mirror is not in one of the countries given to "--country".
+    Country,
+    /// The mirror is not on one of the continents given to "--continent".
+    Continent,
+    /// The mirror does not use one of the protocols given to "--protocol".
+    Protocol,
+    /// The mirror does not match any of the "--include" expressions.
+    NotIncluded,
+    /// The mirror matches the given "--exclude" expression.
+    Excluded(String),
+    /// The reported delay is missing or exceeds "--delay".
+    Delay(Option<u32>),
+    /// The mirror does not host ISOs.
+    NoIsos,
+    /// The mirror does not support IPv4.
+    NoIpv4,
+    /// The mirror does not support IPv6.
+    NoIpv6,
+    /// The mirror is not in one of the autonomous systems given to "--asn". Holds its AS,
+    /// if it is known.
+    Asn(Option<u32>),
+    /// The lastsync file of the mirror is older than its reported last synchronization by
+    /// more than "--verify-sync" allows. Holds the difference, or nothing if the file could
+    /// not be retrieved.
+    LastSync(Option<SignedDuration>),
+    /// The database of the mirror could not be retrieved. Holds the error.
+    Unreachable(String),
+    /// The mirror does not serve the ISO of the latest release. Holds the reason.
+    NoCurrentIso(String),
+    /// The mirror did not serve its database over HTTP/2 or later. Holds the HTTP version, if
+    /// the mirror was rated over HTTP(S).
+    HttpVersion(Option<String>),
+    /// The mirror passed all filters but did not make the cut of the given limit, named
+    /// after the option that set it.
+    Limit(&'static str),
+    /// The mirror could not be rated, and was left out of a list written as the ratings
+    /// completed.
+    NotRated,
+    /// Another entry of the same host with a preferred protocol was kept.
+    DuplicateHost(Protocol),
+    /// The mirror was unchecked when choosing the mirrors interactively.
+    Unchecked,
+}
+
+impl Rejection {
+    /// A short name of the criterion that rejected the mirror. Limits are named after the
+    /// option that set them, as given to [`truncate_status`] and [`cap_per_country`].
+    #[must_use]
+    pub fn criterion(&self) -> &'static str {
+        match self {
+            Self::NotSynced => "never synced",
+            Self::TooOld => "age",
+            Self::Incomplete(_) => "completion",
+            Self::Country => "country",
+            Self::Continent => "continent",
+            Self::Protocol => "protocol",
+            Self::NotIncluded => "include",
+            Self::Excluded(_) => "exclude",
+            Self::Delay(_) => "delay",
+            Self::NoIsos => "isos",
+            Self::NoIpv4 => "ipv4",
+            Self::NoIpv6 => "ipv6",
+            Self::Asn(_) => "asn",
+            Self::LastSync(_) => "lastsync",
+            Self::Unreachable(_) => "reachability",
+            Self::NoCurrentIso(_) => "latest iso",
+            Self::HttpVersion(_) => "http2",
+            Self::Limit(option) => option,
+            Self::NotRated => "rating",
+            Self::DuplicateHost(_) => "duplicate host",
+            Self::Unchecked => "unchecked",
+        }
+    }
+}
+
+impl Display for Rejection {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotSynced => write!(f, "the mirror has never synchronized"),
+            Self::TooOld => write!(f, "last synchronized longer ago than the maximum age"),
+            Self::Incomplete(pct) => {
+                write!(f, "completion of {:.1}% is below the minimum", pct * 100.0)
+            }
+            Self::Country => write!(f, "not in one of the selected countries"),
+            Self::Continent => write!(f, "not on one of the selected continents"),
+            Self::Protocol => write!(f, "does not use one of the selected protocols"),
+            Self::NotIncluded => write!(f, "URL does not match any included expression"),
+            Self::Excluded(re) => write!(f, "URL matches the excluded expression '{re}'"),
+            Self::Delay(Some(delay)) => write!(f, "delay of {delay}s exceeds the maximum delay"),
+            Self::Delay(None) => write!(f, "no delay reported while the delay is limited"),
+            Self::NoIsos => write!(f, "does not host ISOs"),
+            Self::NoIpv4 => write!(f, "does not support IPv4"),
+            Self::NoIpv6 => write!(f, "does not support IPv6"),
+            Self::Asn(Some(asn)) => write!(
+                f,
+                "hosted in AS{asn}, which is not one of the selected ones"
+            ),
+            Self::Asn(None) => write!(f, "the autonomous system is unknown"),
+            Self::LastSync(Some(lag)) => write!(
+                f,
+                "the lastsync file is {lag:#} older than reported, which exceeds the tolerance"
+            ),
+            Self::LastSync(None) => write!(f, "the lastsync file could not be retrieved"),
+            Self::Unreachable(err) => write!(f, "unreachable: {err}"),
+            Self::NoCurrentIso(reason) => {
+                write!(f, "does not serve the latest ISO: {reason}")
+            }
+            Self::HttpVersion(Some(version)) => {
+                write!(f, "served the database over {version}, below HTTP/2")
+            }
+            Self::HttpVersion(None) => write!(f, "could not be rated over HTTP(S)"),
+            Self::Limit(option) => write!(f, "did not make the cut of {option}"),
+            Self::NotRated => write!(f, "could not be rated"),
+            Self::DuplicateHost(protocol) => {
+                write!(f, "the same host is kept with the {protocol} protocol")
+            }
+            Self::Unchecked => write!(f, "unchecked when choosing the mirrors"),
+        }
+    }
+}
+
+/// Remove all mirrors that do not match the filters from `status`, returning the removed
+/// mirrors along with the first criterion that rejected them. Without `statistics`, e.g. for
+/// a list of mirrors, the filters on the statistics of the mirror status do not apply.
+#[tracing::instrument(name = "filter", skip_all)]
+pub fn filter_status(
+    filters: &Filters,
+    status: &mut Status,
+    statistics: bool,
+) -> Vec<(Mirror, Rejection)> {
+    let now = Timestamp::now();
+    let mut rejected = Vec::new();
+    for mirror in std::mem::take(&mut status.urls) {
+        match check_mirror(filters, &mirror, now, statistics) {
+            Some(rejection) => {
+                tracing::debug!(mirror_url = %mirror.url, phase = "filter", "rejected: {rejection}");
+                rejected.push((mirror, rejection));
+            }
+            None => status.urls.push(mirror),
+        }
+    }
+    rejected
+}
+
+/// Truncate the list to `n` mirrors, recording the removed mirrors as rejected by `option`.
+pub fn truncate_status(
+    status: &mut Status,
+    n: usize,
+    option: &'static str,
+    rejected: &mut Vec<(Mirror, Rejection)>,
+) {
+    if status.urls.len() > n {
+        rejected.extend(
+            status
+                .urls
+                .drain(n..)
+                .map(|mirror| (mirror, Rejection::Limit(option))),
+        );
+    }
+}
+
+/// Group the entries of hosts that serve the mirror over several protocols at the position
+/// of their first entry, ordered by `preference`. Protocols missing from `preference` come
+/// last, in their original order. With `unique`, only the first entry of each host is kept
+/// and the others are recorded as rejected.
+pub fn group_hosts(
+    status: &mut Status,
+    preference: &[Protocol],
+    unique: bool,
+    rejected: &mut Vec<(Mirror, Rejection)>,
+) {
+    if preference.is_empty() && !unique {
+        return;
+    }
+    let rank = |protocol: Protocol| {
+        preference
+            .iter()
+            .position(|preferred| *preferred == protocol)
+            .unwrap_or(preference.len())
+    };
+
+    let mut groups: Vec<Vec<Mirror>> = Vec::new();
+    let mut index = HashMap::new();
+    for mirror in std::mem::take(&mut status.urls) {
+        let host = mirror.url.host_str().unwrap_or_default().to_owned();
+        let i = *index.entry(host).or_insert_with(|| {
+            groups.push(Vec::new());
+            groups.len() - 1
+        });
+        groups[i].push(mirror);
+    }
+
+    for mut group in groups {
+        group.sort_by_key(|mirror| rank(mirror.protocol));
+        let mut group = group.into_iter();
+        let Some(first) = group.next() else {
+            continue;
+        };
+        let kept = first.protocol;
+        status.urls.push(first);
+        if unique {
+            rejected.extend(group.map(|mirror| (mirror, Rejection::DuplicateHost(kept))));
+        } else {
+            status.urls.extend(group);
+        }
+    }
+}
+
+/// Keep at most `n` mirrors of each country, recording the removed mirrors as rejected by
+/// `option`. Mirrors without a country are not capped, since they need not share one. The
+/// order of the kept mirrors does not change.
+pub fn cap_per_country(
+    status: &mut Status,
+    n: usize,
+    option: &'static str,
+    rejected: &mut Vec<(Mirror, Rejection)>,
+) {
+    let mut counts = HashMap::<String, usize>::new();
+    for mirror in std::mem::take(&mut status.urls) {
+        if mirror.country_code.is_empty() {
+            status.urls.push(mirror);
+            continue;
+        }
+        let count = counts.entry(mirror.country_code.clone()).or_default();
+        if *count < n {
+            *count += 1;
+            status.urls.push(mirror);
+        } else {
+            rejected.push((mirror, Rejection::Limit(option)));
+        }
+    }
+}
+
+/// Summarize how many mirrors were removed by each criterion, e.g. "started with 823
+/// mirrors, 214 removed by age, 380 by country, 229 remaining". The criteria of [`evaluate`]
+/// come first in the order they are applied, followed by the others in the order they
+/// first rejected a mirror.
+#[must_use]
+pub fn funnel_summary(rejected: &[(Mirror, Rejection)], remaining: usize) -> String {
+    let mut parts = vec![format!(
+        "started with {} mirrors",
+        rejected.len() + remaining
+    )];
+    let mut counts: Vec<(&str, usize)> = Vec::new();
+    for (_, rejection) in rejected {
+        let criterion = rejection.criterion();
+        match counts.iter_mut().find(|(other, _)| *other == criterion) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((criterion, 1)),
+        }
+    }
+    counts.sort_by_key(|(criterion, _)| {
+        CRITERIA
+            .iter()
+            .position(|other| other == criterion)
+            .unwrap_or(CRITERIA.len())
+    });
+    for (criterion, count) in counts {
+        let removed = if parts.len() == 1 { " removed" } else { "" };
+        parts.push(format!("{count}{removed} by {criterion}"));
+    }
+    parts.push(format!("{remaining} remaining"));
+    parts.join(", ")
+}
+
+/// Whether the URL of `mirror` matches one of the `include` expressions.
+#[must_use]
+pub fn included(mirror: &Mirror, include: &[Regex]) -> bool {
+    include.iter().any(|re| re.is_match(mirror.url.as_str()))
+}
+
+/// The first of the `exclude` expressions that the URL of `mirror` matches, if any.
+#[must_use]
+pub fn excluded_by<'a>(mirror: &Mirror, exclude: &'a [Regex]) -> Option<&'a Regex> {
+    exclude.iter().find(|re| re.is_match(mirror.url.as_str()))
+}
+
+/// Whether the country of `mirror` is on one of `continents`.
+#[must_use]
+pub fn on_continents(mirror: &Mirror, continents: &[Continent]) -> bool {
+    CountryCode::from_code(&mirror.country_code)
+        .is_some_and(|code| continents.contains(&code.continent()))
+}
+
+/// Check a single mirror against the filters, returning the first criterion it fails.
+fn check_mirror(
+    filters: &Filters,
+    mirror: &Mirror,
+    now: Timestamp,
+    statistics: bool,
+) -> Option<Rejection> {
+    evaluate(filters, mirror, now, statistics)
+        .into_iter()
+        .find_map(|(_, rejection)| rejection)
+}
+
+/// Evaluate every active filter for a single mirror, in the order they are applied. Each
+/// entry holds the name of the criterion and the rejection, if the mirror failed it. The
+/// filters on the statistics of the mirror status are only evaluated with `statistics`.
+#[must_use]
+pub fn evaluate(
+    filters: &Filters,
+    mirror: &Mirror,
+    now: Timestamp,
+    statistics: bool,
+) -> Vec<(&'static str, Option<Rejection>)> {
+    let mut decisions = Vec::new();
+
+    // Lists of mirrors do not say when the mirrors synced.
+    if statistics {
+        if mirror.last_sync.is_some() {
+            // Filter by age. Servers with a last refresh older than the age are omitted.
+            if let Some(age) = filters.age {
+                let too_old = !selector::synced_within(mirror, age, now);
+                decisions.push(("age", too_old.then_some(Rejection::TooOld)));
+            }
+        } else {
+            // Filter unsynced mirrors.
+            decisions.push(("never synced", Some(Rejection::NotSynced)));
+        }
+    }
+
+    // Filter by completion "percent" [0-1].
+    if let Some(completion_pct) = mirror.completion_pct.filter(|_| statistics) {
+        decisions.push((
+            "completion",
+            (!selector::completed(mirror, filters.completion_percent))
+                .then_some(Rejection::Incomplete(completion_pct)),
+        ));
+    }
+
+    if !filters.country.is_empty() {
+        let country_matches = selector::in_countries(mirror, &filters.country);
+        decisions.push(("country", (!country_matches).then_some(Rejection::Country)));
+    }
+
+    if !filters.continent.is_empty() {
+        let matches = on_continents(mirror, &filters.continent);
+        decisions.push(("continent", (!matches).then_some(Rejection::Continent)));
+    }
+
+    // Filter by protocols.
+    if !filters.protocol.is_empty() {
+        let matches = filters.protocol.contains(&mirror.protocol);
+        decisions.push(("protocol", (!matches).then_some(Rejection::Protocol)));
+    }
+
+    // Filter by include expressions.
+    if !filters.include.is_empty() {
+        let included = included(mirror, &filters.include);
+        decisions.push(("include", (!included).then_some(Rejection::NotIncluded)));
+    }
+
+    // Filter by exclude expressions.
+    if !filters.exclude.is_empty() {
+        let excluded =
+            excluded_by(mirror, &filters.exclude).map(|re| Rejection::Excluded(re.to_string()));
+        decisions.push(("exclude", excluded));
+    }
+
+    // Filter by delay.
+    if let Some(delay) = filters.delay.filter(|_| statistics) {
+        let rejection =
+            (!selector::delayed_within(mirror, delay)).then_some(Rejection::Delay(mirror.delay));
+        decisions.push(("delay", rejection));
+    }
+
+    // Filter by ISO hosing.
+    if filters.isos && statistics {
+        decisions.push(("isos", (!mirror.isos).then_some(Rejection::NoIsos)));
+    }
+
+    // Filter by IPv4 support.
+    if filters.ipv4 && statistics {
+        decisions.push(("ipv4", (!mirror.ipv4).then_some(Rejection::NoIpv4)));
+    }
+
+    // Filter by IPv6 support.
+    if filters.ipv6 && statistics {
+        decisions.push(("ipv6", (!mirror.ipv6).then_some(Rejection::NoIpv6)));
+    }
+
+    decisions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arch_mirrors_rs::extra::Extra;
+
+    fn now() -> Timestamp {
+        "2024-05-01T10:00:00Z".parse().unwrap()
+    }
+
+    /// A mirror that passes every filter, synced an hour ago.
+    fn mirror(url: &str, country_code: &str) -> Mirror {
+        Mirror {
+            url: url.parse().unwrap(),
+            protocol: Protocol::Https,
+            last_sync: Some(now() - SignedDuration::from_hours(1)),
+            completion_pct: Some(1.0),
+            delay: Some(600),
+            duration_average: None,
+            duration_stddev: None,
+            score: Some(1.0),
+            active: true,
+            country: String::new(),
+            country_code: country_code.to_owned(),
+            isos: true,
+            ipv4: true,
+            ipv6: true,
+            details: String::new(),
+            extra: Extra::default(),
+        }
+    }
+
+    fn status(urls: Vec<Mirror>) -> Status {
+        Status {
+            cutoff: 3600,
+            last_check: now(),
+            num_checks: 1,
+            check_frequency: 600,
+            urls,
+            version: Status::VERSION,
+            extra: Extra::default(),
+        }
+    }
+
+    fn hosts(mirrors: &[Mirror]) -> Vec<&str> {
+        mirrors
+            .iter()
+            .map(|mirror| mirror.url.host_str().unwrap())
+            .collect()
+    }
+
+    /// A change to a mirror that makes it fail one of the filters.
+    type Change = fn(&mut Mirror);
+
+    fn rejection(filters: &Filters, mirror: &Mirror) -> Option<Rejection> {
+        check_mirror(filters, mirror, now(), true)
+    }
+
+    #[test]
+    fn reject_by_each_filter() {
+        let ok = mirror("https://mirror.example.se/archlinux/", "SE");
+        let all = Filters {
+            age: Some(2.0),
+            delay: Some(0.5),
+            country: vec!["SE".to_owned()],
+            continent: vec![Continent::Europe],
+            protocol: vec![Protocol::Https],
+            include: vec![Regex::new(r"\.se/").unwrap()],
+            exclude: vec![Regex::new("excluded").unwrap()],
+            isos: true,
+            ipv4: true,
+            ipv6: true,
+            ..Filters::default()
+        };
+        assert_eq!(rejection(&all, &ok), None);
+
+        let cases: &[(Change, Rejection)] = &[
+            (|m| m.last_sync = None, Rejection::NotSynced),
+            (
+                |m| m.last_sync = Some(now() - SignedDuration::from_hours(3)),
+                Rejection::TooOld,
+            ),
+            (|m| m.completion_pct = Some(0.5), Rejection::Incomplete(0.5)),
+            (|m| m.country_code = "DE".to_owned(), Rejection::Country),
+            (|m| m.protocol = Protocol::Rsync, Rejection::Protocol),
+            (
+                |m| m.url = "https://mirror.example.com/".parse().unwrap(),
+                Rejection::NotIncluded,
+            ),
+            (
+                |m| m.url = "https://excluded.example.se/".parse().unwrap(),
+                Rejection::Excluded("excluded".to_owned()),
+            ),
+            (|m| m.delay = Some(3600), Rejection::Delay(Some(3600))),
+            (|m| m.delay = None, Rejection::Delay(None)),
+            (|m| m.isos = false, Rejection::NoIsos),
+            (|m| m.ipv4 = false, Rejection::NoIpv4),
+            (|m| m.ipv6 = false, Rejection::NoIpv6),
+        ];
+        for (change, expected) in cases {
+            let mut mirror = ok.clone();
+            change(&mut mirror);
+            assert_eq!(rejection(&all, &mirror).as_ref(), Some(expected));
+            assert!(!expected.to_string().contains("--"), "{expected}");
+        }
+
+        let continent = Filters {
+            continent: vec![Continent::Europe],
+            ..Filters::default()
+        };
+        let us = mirror("https://mirror.example.com/", "US");
+        assert_eq!(rejection(&continent, &us), Some(Rejection::Continent));
+    }
+
+    #[test]
+    fn skip_statistics_of_lists() {
+        let filters = Filters {
+            age: Some(1.0),
+            delay: Some(0.1),
+            isos: true,
+            ..Filters::default()
+        };
+        let mut listed = mirror("https://mirror.example.se/archlinux/", "SE");
+        listed.last_sync = None;
+        listed.completion_pct = None;
+        listed.delay = None;
+        listed.isos = false;
+        assert!(evaluate(&filters, &listed, now(), false).is_empty());
+        assert_eq!(rejection(&filters, &listed), Some(Rejection::NotSynced));
+    }
+
+    #[test]
+    fn evaluate_every_active_filter() {
+        let filters = Filters {
+            country: vec!["DE".to_owned()],
+            ipv4: true,
+            ..Filters::default()
+        };
+        let mirror = mirror("https://mirror.example.se/archlinux/", "SE");
+        let decisions = evaluate(&filters, &mirror, now(), true);
+        assert_eq!(
+            decisions,
+            [
+                ("completion", None),
+                ("country", Some(Rejection::Country)),
+                ("ipv4", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn filter_and_truncate() {
+        let filters = Filters {
+            country: vec!["SE".to_owned()],
+            ..Filters::default()
+        };
+        let mut status = status(vec![
+            mirror("https://a.example.se/", "SE"),
+            mirror("https://b.example.de/", "DE"),
+            mirror("https://c.example.se/", "SE"),
+        ]);
+        let mut rejected = filter_status(&filters, &mut status, true);
+        truncate_status(&mut status, 1, "--number", &mut rejected);
+        assert_eq!(hosts(&status.urls), ["a.example.se"]);
+        let removed = rejected.iter().map(|(mirror, _)| mirror.clone());
+        assert_eq!(
+            hosts(&removed.collect::<Vec<_>>()),
+            ["b.example.de", "c.example.se"]
+        );
+        assert_eq!(rejected[0].1, Rejection::Country);
+        assert_eq!(rejected[1].1, Rejection::Limit("--number"));
+        assert_eq!(rejected[1].1.criterion(), "--number");
+    }
+
+    #[test]
+    fn summarize_the_funnel() {
+        let rejection = |rejection| (mirror("https://mirror.example.se/", "SE"), rejection);
+        let rejected = [
+            rejection(Rejection::Limit("--fastest")),
+            rejection(Rejection::Country),
+            rejection(Rejection::Unchecked),
+            rejection(Rejection::TooOld),
+            rejection(Rejection::Country),
+            rejection(Rejection::Limit("--fastest")),
+        ];
+        assert_eq!(
+            funnel_summary(&rejected, 4),
+            "started with 10 mirrors, 1 removed by age, 2 by country, 2 by --fastest, \
+             1 by unchecked, 4 remaining"
+        );
+        assert_eq!(
+            funnel_summary(&[], 3),
+            "started with 3 mirrors, 3 remaining"
+        );
+    }
+
+    #[test]
+    fn cap_each_country() {
+        let mut status = status(vec![
+            mirror("https://a.example.se/", "SE"),
+            mirror("https://b.example.de/", "DE"),
+            mirror("https://c.example.se/", "SE"),
+            mirror("https://d.example.net/", ""),
+            mirror("https://e.example.org/", ""),
+            mirror("https://f.example.de/", "DE"),
+        ]);
+        let mut rejected = Vec::new();
+        cap_per_country(&mut status, 1, "--max-per-country", &mut rejected);
+        assert_eq!(
+            hosts(&status.urls),
+            [
+                "a.example.se",
+                "b.example.de",
+                "d.example.net",
+                "e.example.org"
+            ]
+        );
+        let removed = rejected.into_iter().map(|(mirror, _)| mirror);
+        assert_eq!(
+            hosts(&removed.collect::<Vec<_>>()),
+            ["c.example.se", "f.example.de"]
+        );
+    }
+
+    #[test]
+    fn group_hosts_by_preference() {
+        let with_protocol = |url: &str, protocol| Mirror {
+            protocol,
+            ..mirror(url, "SE")
+        };
+        let mirrors = vec![
+            with_protocol("http://a.example.se/", Protocol::Http),
+            with_protocol("https://b.example.se/", Protocol::Https),
+            with_protocol("rsync://a.example.se/", Protocol::Rsync),
+            with_protocol("https://a.example.se/", Protocol::Https),
+        ];
+        let urls = |mirrors: &[Mirror]| {
+            mirrors
+                .iter()
+                .map(|mirror| mirror.url.to_string())
+                .collect::<Vec<_>>()
+        };
+
+        let mut grouped = status(mirrors.clone());
+        let mut rejected = Vec::new();
+        group_hosts(&mut grouped, &[Protocol::Https], false, &mut rejected);
+        assert_eq!(
+            urls(&grouped.urls),
+            [
+                "https://a.example.se/",
+                "http://a.example.se/",
+                "rsync://a.example.se/",
+                "https://b.example.se/",
+            ]
+        );
+        assert!(rejected.is_empty());
+
+        let mut unique = status(mirrors);
+        group_hosts(&mut unique, &[Protocol::Https], true, &mut rejected);
+        assert_eq!(
+            urls(&unique.urls),
+            ["https://a.example.se/", "https://b.example.se/"]
+        );
+        assert_eq!(rejected.len(), 2);
+        assert!(
+            rejected
+                .iter()
+                .all(|(_, rejection)| *rejection == Rejection::DuplicateHost(Protocol::Https))
+        );
+    }
+}
@@ -0,0 +1,24 @@
+//! # Reflector
+//! Retrieve, filter and rate Arch Linux mirrors like reflector.
+#![warn(missing_docs)]
+#![warn(rustdoc::missing_crate_level_docs)]
+#![warn(rustdoc::invalid_codeblock_attributes)]
+pub mod filter;
+pub mod output;
+pub mod quality;
+pub mod rate;
+pub mod rater;
+pub mod reflector;
+pub mod retry;
+pub mod schema;
+pub mod sort;
+pub mod template;
+
+pub use arch_mirrors_rs;
+pub use filter::{Filters, Rejection};
+pub use rate::{Prober, Rating, Ratings};
+pub use rater::Rater;
+pub use reflector::{Reflector, Selection};
+pub use regex;
+pub use reqwest;
+pub use template::Template;
@@ -0,0 +1,149 @@
+//! This is where the outputs of reflector that describe the selected mirrors go: the
+//! mirrorlist with its header and the [`MirrorList`] written as JSON.
+use crate::rate::Ratings;
+use arch_mirrors_rs::mirrorlist::{Header, MirrorlistWriter};
+use arch_mirrors_rs::{Protocol, Status};
+use jiff::Timestamp;
+use serde::Serialize;
+
+/// Where and when the selected mirrors came from, for the header of the outputs.
+#[derive(Debug, Clone)]
+pub struct Metadata<'a> {
+    /// When the output was generated.
+    pub when: Timestamp,
+
+    /// The command that generated the output, if any.
+    pub command: Option<String>,
+
+    /// Where the mirror status was retrieved from.
+    pub origin: &'a str,
+
+    /// When the mirror status was retrieved.
+    pub retrieved: Timestamp,
+
+    /// The path of a repository relative to the base URL of a mirror.
+    pub repo_path: &'a str,
+}
+
+/// The selected mirrors as written by the JSON output.
+#[derive(Debug, Serialize)]
+pub struct MirrorList<'a> {
+    /// When the list was generated.
+    pub generated: Timestamp,
+
+    /// Where the mirror status was retrieved from.
+    pub origin: &'a str,
+
+    /// When the mirror status was retrieved.
+    pub retrieved: Timestamp,
+
+    /// When the mirror status was last checked.
+    pub last_check: Timestamp,
+
+    /// The selected mirrors, best first.
+    pub mirrors: Vec<MirrorEntry<'a>>,
+}
+
+/// A selected mirror in the JSON output.
+#[derive(Debug, Serialize)]
+pub struct MirrorEntry<'a> {
+    /// The position in the mirrorlist, starting at 1.
+    pub rank: usize,
+
+    /// The base URL of the mirror.
+    pub url: &'a str,
+
+    /// The value of the "Server" line in the mirrorlist.
+    pub server: String,
+
+    /// The protocol of the mirror.
+    pub protocol: Protocol,
+
+    /// The country of the mirror.
+    pub country: &'a str,
+
+    /// The country code of the mirror.
+    pub country_code: &'a str,
+
+    /// When the mirror last synced.
+    pub last_sync: Option<Timestamp>,
+
+    /// The time since the mirror last synced.
+    pub age_seconds: Option<i64>,
+
+    /// The delay of the mirror in seconds.
+    pub delay: Option<u32>,
+
+    /// The score of the mirror status.
+    pub score: Option<f64>,
+
+    /// The completion percentage of the mirror status, between 0 and 1.
+    pub completion_pct: Option<f64>,
+
+    /// The measured rate. Only present if the mirror was rated.
+    pub rate_bytes_per_second: Option<f64>,
+
+    /// The time to first byte of the rating.
+    pub ttfb_seconds: Option<f64>,
+
+    /// The HTTP version of the rating response, e.g. "HTTP/2.0".
+    pub http_version: Option<String>,
+}
+
+/// The mirrors of `status` with their `ratings`, in order, for the JSON output.
+#[must_use]
+pub fn mirror_list<'a>(
+    metadata: &Metadata<'a>,
+    status: &'a Status,
+    ratings: &Ratings,
+) -> MirrorList<'a> {
+    let mirrors = status
+        .urls
+        .iter()
+        .enumerate()
+        .map(|(i, mirror)| {
+            let rating = ratings.get(&mirror.url);
+            MirrorEntry {
+                rank: i + 1,
+                url: mirror.url.as_str(),
+                server: format!("{}{}", mirror.url, metadata.repo_path),
+                protocol: mirror.protocol,
+                country: &mirror.country,
+                country_code: &mirror.country_code,
+                last_sync: mirror.last_sync,
+                age_seconds: mirror.age(metadata.when).map(|age| age.as_secs()),
+                delay: mirror.delay,
+                score: mirror.score,
+                completion_pct: mirror.completion_pct,
+                rate_bytes_per_second: rating.map(|rating| rating.rate),
+                ttfb_seconds: rating
+                    .and_then(|rating| rating.ttfb)
+                    .map(|ttfb| ttfb.as_secs_f64()),
+                http_version: rating
+                    .and_then(|rating| rating.http_version)
+                    .map(|version| format!("{version:?}")),
+            }
+        })
+        .collect();
+    MirrorList {
+        generated: metadata.when,
+        origin: metadata.origin,
+        retrieved: metadata.retrieved,
+        last_check: status.last_check,
+        mirrors,
+    }
+}
+
+/// A writer for the mirrorlist with the header of reflector for `metadata` and `status`.
+#[must_use]
+pub fn mirrorlist_writer(metadata: &Metadata, status: &Status) -> MirrorlistWriter {
+    MirrorlistWriter::new()
+        .header(Header {
+            with: metadata.command.clone(),
+            when: Some(metadata.when),
+            from: Some(metadata.origin.to_owned()),
+            retrieved: Some(metadata.retrieved),
+            last_check: Some(status.last_check),
+        })
+        .repo_path(metadata.repo_path)
+}
@@ -0,0 +1,245 @@
+//! This is where sorting by quality, which blends the measurements of the mirrors with
+//! their status, goes.
+use crate::rate::Ratings;
+use arch_mirrors_rs::Mirror;
+use jiff::Timestamp;
+use reqwest::Url;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+use std::time::Duration;
+
+/// How much each measure contributes to the quality of a mirror.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QualityWeights {
+    /// The weight of the measured download rate.
+    pub rate: f64,
+    /// The weight of the measured latency.
+    pub latency: f64,
+    /// The weight of the time since the last synchronization.
+    pub age: f64,
+    /// The weight of the completion percentage of the mirror status.
+    pub completion: f64,
+}
+
+impl Default for QualityWeights {
+    fn default() -> Self {
+        Self {
+            rate: 1.0,
+            latency: 1.0,
+            age: 1.0,
+            completion: 1.0,
+        }
+    }
+}
+
+/// Parse weights like "rate=2,latency=0", where measures that are left out keep their
+/// default weight.
+///
+/// # Errors
+/// Fails with a message if a term is not a known measure and a weight of at least 0, or if
+/// every weight is 0.
+pub fn parse_weights(s: &str) -> Result<QualityWeights, String> {
+    let defaults = QualityWeights::default();
+    let [rate, latency, age, completion] = parse_terms(
+        s,
+        ["rate", "latency", "age", "completion"],
+        [
+            defaults.rate,
+            defaults.latency,
+            defaults.age,
+            defaults.completion,
+        ],
+    )
+    .map_err(|term| {
+        format!(
+            "invalid weight '{term}', expected a weight of at least 0 of rate, latency, age \
+             or completion, e.g. 'rate=2'"
+        )
+    })?;
+    if rate + latency + age + completion <= 0.0 {
+        return Err("at least one weight must be above 0".to_owned());
+    }
+    Ok(QualityWeights {
+        rate,
+        latency,
+        age,
+        completion,
+    })
+}
+
+/// Parse a comma-separated list of weights like "rate=2,latency=0.5" into the weights of
+/// the given names, in the same order. Names that are left out keep their default weight.
+/// Fails with the offending term if it is not one of the names followed by "=" and a
+/// finite weight of at least 0.
+fn parse_terms<'a, const N: usize>(
+    s: &'a str,
+    names: [&str; N],
+    defaults: [f64; N],
+) -> Result<[f64; N], &'a str> {
+    let mut weights = defaults;
+    for term in s.split(',') {
+        let term = term.trim();
+        let (name, weight) = term.split_once('=').ok_or(term)?;
+        let weight = weight
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .filter(|weight| weight.is_finite() && *weight >= 0.0)
+            .ok_or(term)?;
+        let index = names
+            .iter()
+            .position(|known| *known == name.trim())
+            .ok_or(term)?;
+        weights[index] = weight;
+    }
+    Ok(weights)
+}
+
+/// The measures of a single mirror, each oriented so that higher is better. Measures that
+/// are not available are left out.
+#[derive(Debug, Clone, Copy, Default)]
+struct Measures {
+    rate: Option<f64>,
+    latency: Option<f64>,
+    age: Option<f64>,
+    completion: Option<f64>,
+}
+
+/// Picks one of the [`Measures`] of a mirror.
+type Measure = fn(&Measures) -> Option<f64>;
+
+/// Sort the mirrors by their quality, best first. Each measure is scaled between the worst
+/// and the best of the mirrors, and the quality is the weighted average of the scaled
+/// measures. A measure that is not available for a mirror, e.g. because it failed to rate,
+/// counts as the worst.
+pub fn sort_by_quality<S: BuildHasher>(
+    mirrors: &mut [Mirror],
+    weights: &QualityWeights,
+    ratings: &Ratings,
+    latencies: &HashMap<Url, Duration, S>,
+    now: Timestamp,
+) {
+    let measures = mirrors
+        .iter()
+        .map(|mirror| Measures {
+            rate: ratings.get(&mirror.url).map(|rating| rating.rate),
+            latency: latencies
+                .get(&mirror.url)
+                .map(|latency| -latency.as_secs_f64()),
+            age: mirror.age(now).map(|age| -age.as_secs_f64()),
+            completion: mirror.completion_pct,
+        })
+        .collect::<Vec<_>>();
+    let qualities = qualities(&measures, weights);
+    let mut ranked = mirrors.iter().cloned().zip(qualities).collect::<Vec<_>>();
+    ranked.sort_by(|(_, a), (_, b)| b.partial_cmp(a).unwrap_or(Ordering::Equal));
+    for (slot, (mirror, _)) in mirrors.iter_mut().zip(ranked) {
+        *slot = mirror;
+    }
+}
+
+/// The quality of each of the mirrors with the given measures, between 0 and 1.
+fn qualities(measures: &[Measures], weights: &QualityWeights) -> Vec<f64> {
+    let terms: [(f64, Measure); 4] = [
+        (weights.rate, |measures| measures.rate),
+        (weights.latency, |measures| measures.latency),
+        (weights.age, |measures| measures.age),
+        (weights.completion, |measures| measures.completion),
+    ];
+    let total_weight = terms.iter().map(|(weight, _)| weight).sum::<f64>();
+    let mut qualities = vec![0.0; measures.len()];
+    for (weight, measure) in terms {
+        if weight <= 0.0 {
+            continue;
+        }
+        let values = measures.iter().map(measure).collect::<Vec<_>>();
+        let (min, max) = values
+            .iter()
+            .flatten()
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), value| {
+                (min.min(*value), max.max(*value))
+            });
+        for (quality, value) in qualities.iter_mut().zip(values) {
+            let scaled = match value {
+                // All mirrors are equally good in this measure.
+                Some(_) if max <= min => 1.0,
+                Some(value) => (value - min) / (max - min),
+                None => 0.0,
+            };
+            *quality += weight * scaled / total_weight;
+        }
+    }
+    qualities
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_quality_weights() {
+        let weights = parse_weights("rate=2, latency=0").unwrap();
+        assert_eq!(
+            weights,
+            QualityWeights {
+                rate: 2.0,
+                latency: 0.0,
+                ..QualityWeights::default()
+            }
+        );
+        assert!(parse_weights("speed=1").is_err());
+        assert!(parse_weights("rate=-1").is_err());
+        assert!(parse_weights("rate=0,latency=0,age=0,completion=0").is_err());
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp, reason = "The weights are parsed, not computed.")]
+    fn parse_weight_terms() {
+        let weights = parse_terms("b=0.5", ["a", "b"], [1.0, 1.0]).unwrap();
+        assert_eq!(weights, [1.0, 0.5]);
+        assert_eq!(parse_terms("a=1, c=2", ["a", "b"], [1.0, 1.0]), Err("c=2"));
+        assert_eq!(parse_terms("a", ["a"], [1.0]), Err("a"));
+        assert_eq!(parse_terms("a=inf", ["a"], [1.0]), Err("a=inf"));
+    }
+
+    #[test]
+    fn blend_measures() {
+        let measures = [
+            Measures {
+                rate: Some(100.0),
+                age: Some(-3600.0),
+                completion: Some(1.0),
+                ..Measures::default()
+            },
+            Measures {
+                rate: Some(300.0),
+                age: Some(-7200.0),
+                completion: Some(1.0),
+                ..Measures::default()
+            },
+            Measures {
+                rate: None,
+                age: Some(-5400.0),
+                completion: Some(1.0),
+                ..Measures::default()
+            },
+        ];
+        let weights = QualityWeights {
+            latency: 0.0,
+            ..QualityWeights::default()
+        };
+        let blended = qualities(&measures, &weights);
+        // The rate and the age of the first two mirrors cancel each other out, while the
+        // third failed to rate and counts as the slowest.
+        assert!((blended[0] - 2.0 / 3.0).abs() < f64::EPSILON);
+        assert!((blended[1] - 2.0 / 3.0).abs() < f64::EPSILON);
+        assert!((blended[2] - 0.5).abs() < f64::EPSILON);
+        let weights = QualityWeights {
+            rate: 2.0,
+            ..weights
+        };
+        let blended = qualities(&measures, &weights);
+        assert!(blended[1] > blended[0] && blended[0] > blended[2]);
+    }
+}
@@ -0,0 +1,796 @@
+//! This is where the [`Prober`] and the [`Ratings`] it measures go.
+use crate::rater::{self, ByProtocol, HttpRater, LatencyRater, Rater, RsyncRater};
+use crate::retry;
+use anyhow::Result;
+use arch_mirrors_rs::{Mirror, Protocol};
+use futures_util::future::{BoxFuture, FutureExt};
+use jiff::Timestamp;
+use reqwest::Url;
+use std::cmp::Ordering;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{BuildHasher, RandomState};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, OwnedSemaphorePermit, Semaphore};
+use tokio::task::JoinSet;
+use tracing::Instrument;
+
+/// The file in the root of each mirror holding the time of its last synchronization.
+const LASTSYNC_FILENAME: &str = "lastsync";
+
+/// How much faster the combined download rate must get to rate one more mirror at the
+/// same time with [`Prober::adaptive`].
+const MIN_SPEEDUP: f64 = 1.1;
+
+/// The result of rating a single mirror.
+#[derive(Debug, Clone, Copy)]
+pub struct Rating {
+    /// The measured transfer rate in bytes per second.
+    pub rate: f64,
+    /// The time it took to receive the response headers, which covers DNS resolution and
+    /// connection setup. Not available for rsync and FTP.
+    pub ttfb: Option<Duration>,
+    /// The number of bytes transferred.
+    pub bytes: u64,
+    /// The time the transfer took. For HTTP(S), this only covers receiving the body, so
+    /// the rate does not penalize distant mirrors for their connection setup.
+    pub elapsed: Duration,
+    /// The HTTP version of the response. Not available for rsync and FTP.
+    pub http_version: Option<reqwest::Version>,
+}
+
+/// The outcome of rating each mirror that was rated, including failures.
+#[derive(Debug, Default)]
+pub struct Ratings {
+    results: HashMap<Url, Result<Rating, String>>,
+}
+
+impl Ratings {
+    /// The successful rating of the mirror with the given URL, if any.
+    #[must_use]
+    pub fn get(&self, url: &Url) -> Option<&Rating> {
+        self.results.get(url)?.as_ref().ok()
+    }
+
+    /// The error that occurred while rating the mirror with the given URL, if any.
+    #[must_use]
+    pub fn error(&self, url: &Url) -> Option<&str> {
+        self.results.get(url)?.as_ref().err().map(String::as_str)
+    }
+
+    /// Whether the mirror with the given URL was rated, successfully or not.
+    #[must_use]
+    pub fn contains(&self, url: &Url) -> bool {
+        self.results.contains_key(url)
+    }
+
+    /// Whether no mirror was rated at all.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.results.is_empty()
+    }
+
+    /// Sort `mirrors` by their rate, fastest first. Unrated mirrors go last.
+    pub fn sort_by_rate(&self, mirrors: &mut [Mirror]) {
+        mirrors.sort_by(|a, b| match (self.get(&a.url), self.get(&b.url)) {
+            (Some(rating_a), Some(rating_b)) => rating_a
+                .rate
+                .partial_cmp(&rating_b.rate)
+                .unwrap_or(Ordering::Equal)
+                .reverse(),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        });
+    }
+}
+
+impl Extend<(Url, Result<Rating, String>)> for Ratings {
+    fn extend<I: IntoIterator<Item = (Url, Result<Rating, String>)>>(&mut self, iter: I) {
+        self.results.extend(iter);
+    }
+}
+
+impl FromIterator<(Url, Result<Rating, String>)> for Ratings {
+    fn from_iter<I: IntoIterator<Item = (Url, Result<Rating, String>)>>(iter: I) -> Self {
+        Self {
+            results: iter.into_iter().collect(),
+        }
+    }
+}
+
+/// Order the mirrors round-robin across countries, so the probes are spread over all
+/// regions instead of working through the countries one after another. Within a country,
+/// and between countries, the original order is kept.
+pub fn interleave_countries<'a>(mirrors: impl IntoIterator<Item = &'a Mirror>) -> Vec<&'a Mirror> {
+    let mut countries: Vec<VecDeque<&Mirror>> = Vec::new();
+    let mut index = HashMap::new();
+    for mirror in mirrors {
+        let i = *index
+            .entry(mirror.country_code.as_str())
+            .or_insert_with(|| {
+                countries.push(VecDeque::new());
+                countries.len() - 1
+            });
+        countries[i].push_back(mirror);
+    }
+
+    let mut interleaved = Vec::new();
+    while !countries.is_empty() {
+        countries.retain_mut(|queue| {
+            interleaved.extend(queue.pop_front());
+            !queue.is_empty()
+        });
+    }
+    interleaved
+}
+
+/// The settings for rating and otherwise probing mirrors, which it does concurrently with
+/// [`Prober::probe_all`].
+///
+/// ```no_run
+/// # async fn run() -> anyhow::Result<()> {
+/// use reflector_lib::arch_mirrors_rs::Protocol;
+/// use reflector_lib::rate::Prober;
+/// use reflector_lib::reqwest::{Client, Url};
+///
+/// let prober = Prober::new(Client::new()).threads(8).download_size(1 << 20);
+/// let url = Url::parse("https://geo.mirror.pkgbuild.com/")?;
+/// let rating = prober.rate(&url, Protocol::Https).await?;
+/// println!("{:.2} KiB/s", rating.rate / 1024.0);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Prober {
+    http_client: reqwest::Client,
+    /// The path of the database to download, relative to the URL of the mirror.
+    db_path: String,
+    connection_timeout: Duration,
+    proxy: Option<Url>,
+    /// The number of times to retry a failed rating.
+    retries: u32,
+    /// The number of bytes to download instead of the whole database, if limited.
+    download_size: Option<u64>,
+    /// Extra arguments passed to rsync.
+    rsync_extra_args: Vec<String>,
+    threads: usize,
+    rsync_threads: Option<usize>,
+    /// Whether to adapt the number of mirrors rated at the same time up to the threads.
+    adaptive: bool,
+    /// The average time between starting two probes, if they are spaced out.
+    spacing: Option<Duration>,
+    /// The longest a mirror may take to be rated, including retries.
+    rate_timeout: Option<Duration>,
+    /// Whether to attempt HTTP/3 for HTTPS mirrors.
+    #[cfg(feature = "http3")]
+    http3: bool,
+    /// The rater to use instead of the one made from the settings, if any.
+    rater: Option<Arc<dyn Rater>>,
+}
+
+impl Prober {
+    /// The path of the database of the extra repository of Arch Linux on x86-64, relative
+    /// to the URL of a mirror.
+    pub const DB_PATH: &'static str = "extra/os/x86_64/extra.db";
+
+    /// A prober that sends its HTTP(S) requests with `http_client`. It downloads the whole
+    /// database at [`Prober::DB_PATH`], probes one mirror at a time and does not retry.
+    #[must_use]
+    pub fn new(http_client: reqwest::Client) -> Self {
+        Self {
+            http_client,
+            db_path: Self::DB_PATH.to_owned(),
+            connection_timeout: Duration::from_secs(5),
+            proxy: None,
+            retries: 0,
+            download_size: None,
+            rsync_extra_args: Vec::new(),
+            threads: 1,
+            rsync_threads: None,
+            adaptive: false,
+            spacing: None,
+            rate_timeout: None,
+            #[cfg(feature = "http3")]
+            http3: false,
+            rater: None,
+        }
+    }
+
+    /// The path of the database to download, relative to the URL of a mirror.
+    #[must_use]
+    pub fn db_path(mut self, db_path: impl Into<String>) -> Self {
+        self.db_path = db_path.into();
+        self
+    }
+
+    /// The timeout for connecting to rsync and FTP mirrors and for measuring the latency
+    /// of rsync mirrors. The timeouts of HTTP(S) mirrors are those of the HTTP client.
+    #[must_use]
+    pub fn connection_timeout(mut self, timeout: Duration) -> Self {
+        self.connection_timeout = timeout;
+        self
+    }
+
+    /// The proxy for rsync and FTP mirrors. The proxy of HTTP(S) mirrors is that of the
+    /// HTTP client.
+    #[must_use]
+    pub fn proxy(mut self, proxy: Url) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// The number of times to retry a failed rating.
+    #[must_use]
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Only download the first `bytes` of the database of HTTP(S) and FTP mirrors.
+    #[must_use]
+    pub fn download_size(mut self, bytes: u64) -> Self {
+        self.download_size = Some(bytes);
+        self
+    }
+
+    /// Extra arguments passed to rsync.
+    #[must_use]
+    pub fn rsync_extra_args<S: Into<String>>(mut self, args: impl IntoIterator<Item = S>) -> Self {
+        self.rsync_extra_args = args.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// The number of HTTP(S) mirrors to probe at the same time.
+    #[must_use]
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    /// The number of rsync and FTP mirrors to probe at the same time, which defaults to the
+    /// number of [threads](Self::threads).
+    #[must_use]
+    pub fn rsync_threads(mut self, threads: usize) -> Self {
+        self.rsync_threads = Some(threads);
+        self
+    }
+
+    /// Whether to adapt the number of mirrors that are rated at the same time instead of
+    /// always rating as many as there are [threads](Self::threads). Rating starts with one
+    /// mirror at a time and adds another whenever the combined download rate of a round of
+    /// ratings grows by at least 10%, up to the number of threads. Once it stops growing,
+    /// the bandwidth is saturated, so the last mirror that was added is taken away again.
+    /// HTTP(S) mirrors and other mirrors adapt separately.
+    #[must_use]
+    pub fn adaptive(mut self, adaptive: bool) -> Self {
+        self.adaptive = adaptive;
+        self
+    }
+
+    /// Wait a random time between half and one and a half times `spacing` between starting
+    /// two probes, so concurrent probes do not start in bursts and skew each other.
+    #[must_use]
+    pub fn spacing(mut self, spacing: Duration) -> Self {
+        self.spacing = Some(spacing);
+        self
+    }
+
+    /// Give up on rating a mirror after `rate_timeout`, including all retries. This covers
+    /// the whole transfer, so rsync and curl are stopped as well, and keeps a single
+    /// stalled mirror from holding up the rest.
+    #[must_use]
+    pub fn rate_timeout(mut self, rate_timeout: Duration) -> Self {
+        self.rate_timeout = Some(rate_timeout);
+        self
+    }
+
+    /// Whether to attempt HTTP/3 when rating HTTPS mirrors, falling back to the version
+    /// negotiated with the server.
+    #[cfg(feature = "http3")]
+    #[must_use]
+    pub fn http3(mut self, http3: bool) -> Self {
+        self.http3 = http3;
+        self
+    }
+
+    /// Rate mirrors with `rater` instead of downloading their database with the settings
+    /// of the prober. The retries and the concurrency still apply.
+    #[must_use]
+    pub fn rater(mut self, rater: impl Rater + 'static) -> Self {
+        self.rater = Some(Arc::new(rater));
+        self
+    }
+
+    /// Rate the mirrors with the given base URLs and protocols, returning the rating or the
+    /// error that occurred for each of them. `on_result` is called as soon as each rating
+    /// completes.
+    pub async fn rate_all(
+        &self,
+        mirrors: impl IntoIterator<Item = (Url, Protocol)>,
+        on_result: &mut dyn FnMut(&Url, &Result<Rating>),
+    ) -> HashMap<Url, Result<Rating>> {
+        self.probe_with(
+            mirrors,
+            |probe, url, protocol| async move { probe.rate(&url, protocol).await }.boxed(),
+            on_result,
+            Some(|rating: &Rating| rating.bytes),
+        )
+        .await
+    }
+
+    /// Concurrently run `measure` for the mirrors with the given base URLs and protocols,
+    /// returning its result for each of them. `on_result` is called as soon as each
+    /// measurement completes.
+    pub async fn probe_all<T: Send + 'static>(
+        &self,
+        mirrors: impl IntoIterator<Item = (Url, Protocol)>,
+        measure: fn(Arc<Self>, Url, Protocol) -> BoxFuture<'static, Result<T>>,
+        on_result: &mut dyn FnMut(&Url, &Result<T>),
+    ) -> HashMap<Url, Result<T>> {
+        self.probe_with(mirrors, measure, on_result, None).await
+    }
+
+    /// Like [`Prober::probe_all`], adapting the concurrency to the number of bytes each
+    /// measurement `transferred` if the prober is adaptive.
+    async fn probe_with<T: Send + 'static>(
+        &self,
+        mirrors: impl IntoIterator<Item = (Url, Protocol)>,
+        measure: fn(Arc<Self>, Url, Protocol) -> BoxFuture<'static, Result<T>>,
+        on_result: &mut dyn FnMut(&Url, &Result<T>),
+        transferred: Option<fn(&T) -> u64>,
+    ) -> HashMap<Url, Result<T>> {
+        let mut task_set = JoinSet::new();
+        // rsync and FTP probes spawn external processes and contend differently than HTTP
+        // transfers, so each class gets its own limit to keep one from starving the other.
+        let adaptive = self.adaptive && transferred.is_some();
+        let mut http_throttle = Throttle::new(self.threads.max(1), adaptive);
+        let mut rsync_throttle =
+            Throttle::new(self.rsync_threads.unwrap_or(self.threads).max(1), adaptive);
+        let probe = Arc::new(self.clone());
+        // The earliest time the next probe may start, which is held while waiting for it so
+        // the probes start one at a time.
+        let next_start = Arc::new(Mutex::new(tokio::time::Instant::now()));
+
+        for (url, protocol) in mirrors {
+            let permits = match protocol {
+                Protocol::Http | Protocol::Https => http_throttle.permits.clone(),
+                // Other protocols are rated by running a command.
+                _ => rsync_throttle.permits.clone(),
+            };
+            let probe = probe.clone();
+            let next_start = next_start.clone();
+            task_set.spawn(
+                async move {
+                    let result = match permits.acquire().await {
+                        Ok(_permit) => {
+                            if let Some(spacing) = probe.spacing {
+                                let mut next_start = next_start.lock().await;
+                                tokio::time::sleep_until(*next_start).await;
+                                *next_start = tokio::time::Instant::now() + jittered(spacing);
+                            }
+                            measure(probe, url.clone(), protocol).await
+                        }
+                        Err(err) => Err(err),
+                    };
+                    (url, protocol, result)
+                }
+                .in_current_span(),
+            );
+        }
+
+        let mut results = HashMap::new();
+        while let Some(result) = task_set.join_next().await {
+            match result {
+                Ok((url, protocol, result)) => {
+                    if let (Some(transferred), Ok(value)) = (transferred, &result) {
+                        let throttle = match protocol {
+                            Protocol::Http | Protocol::Https => &mut http_throttle,
+                            _ => &mut rsync_throttle,
+                        };
+                        throttle.record(transferred(value));
+                    }
+                    on_result(&url, &result);
+                    results.insert(url, result);
+                }
+                Err(err) => tracing::warn!("error while probing mirror: {err}"),
+            }
+        }
+        results
+    }
+
+    /// Rate a single mirror with the [rater](Self::rater), which by default downloads its
+    /// database.
+    ///
+    /// # Errors
+    /// Fails if the mirror cannot be rated after all retries or within the
+    /// [rate timeout](Self::rate_timeout), or the protocol of the mirror is not supported.
+    pub async fn rate(&self, url: &Url, protocol: Protocol) -> Result<Rating> {
+        let rater = match &self.rater {
+            Some(rater) => rater.clone(),
+            None => Arc::new(self.default_rater()),
+        };
+        if !rater.supports(protocol) {
+            anyhow::bail!("rating {protocol} mirrors is not supported");
+        }
+        let what = format!("rating {url}");
+        let rating = retry::retry(self.retries, &what, |_| true, || rater.rate(url, protocol));
+        match self.rate_timeout {
+            Some(rate_timeout) => tokio::time::timeout(rate_timeout, rating)
+                .await
+                .map_err(|_| anyhow::anyhow!("rating timed out after {rate_timeout:?}"))?,
+            None => rating.await,
+        }
+    }
+
+    /// The rater for the protocols supported by default, made from the settings of the
+    /// prober. If rsync is not installed, rsync mirrors are rated over HTTP(S) instead.
+    #[must_use]
+    pub fn default_rater(&self) -> ByProtocol {
+        let mut http = HttpRater::new(self.http_client.clone()).db_path(&self.db_path);
+        if let Some(download_size) = self.download_size {
+            http = http.download_size(download_size);
+        }
+        #[cfg(feature = "http3")]
+        {
+            http = http.http3(self.http3);
+        }
+        let mut rsync = RsyncRater::new()
+            .db_path(&self.db_path)
+            .connection_timeout(self.connection_timeout)
+            .extra_args(&self.rsync_extra_args);
+        if let Some(proxy) = &self.proxy {
+            rsync = rsync.proxy(proxy.clone());
+        }
+        let rater = ByProtocol::new()
+            .with(http.clone())
+            .with(rsync.http_fallback(http));
+        #[cfg(feature = "ftp")]
+        let rater = {
+            let mut ftp = rater::FtpRater::new()
+                .db_path(&self.db_path)
+                .connection_timeout(self.connection_timeout);
+            if let Some(proxy) = &self.proxy {
+                ftp = ftp.proxy(proxy.clone());
+            }
+            if let Some(download_size) = self.download_size {
+                ftp = ftp.download_size(download_size);
+            }
+            rater.with(ftp)
+        };
+        rater
+    }
+
+    /// Measure the latency of a single mirror. For HTTP(S), this is the time to the first
+    /// byte of the response to a HEAD request for its database, which includes the TLS
+    /// handshake. For other protocols, it is the time to open a TCP connection.
+    ///
+    /// # Errors
+    /// Fails if the request fails or the connection cannot be opened in time.
+    pub async fn latency(&self, url: &Url, protocol: Protocol) -> Result<Duration> {
+        LatencyRater::new(self.http_client.clone())
+            .db_path(&self.db_path)
+            .connection_timeout(self.connection_timeout)
+            .latency(url, protocol)
+            .await
+    }
+
+    /// Check that the database of a single mirror can be retrieved without downloading it.
+    /// If rsync is not installed, rsync mirrors are checked over HTTP(S) instead.
+    ///
+    /// # Errors
+    /// Fails if the database cannot be retrieved, or the protocol of the mirror is not
+    /// supported.
+    pub async fn reachable(&self, url: &Url, protocol: Protocol) -> Result<()> {
+        let db_url = url.join(&self.db_path)?;
+        match protocol {
+            Protocol::Http | Protocol::Https => {
+                self.http_client
+                    .head(db_url)
+                    .send()
+                    .await?
+                    .error_for_status()?;
+            }
+            Protocol::Rsync if !rater::rsync_installed() => {
+                rater::http_fallback(url, |url| async move {
+                    self.http_client
+                        .head(url.join(&self.db_path)?)
+                        .send()
+                        .await?
+                        .error_for_status()?;
+                    Ok(())
+                })
+                .await?;
+            }
+            Protocol::Rsync => {
+                let mut command = rater::rsync_command(
+                    self.connection_timeout,
+                    self.proxy.as_ref(),
+                    &self.rsync_extra_args,
+                );
+                command.arg("--list-only").arg(db_url.as_str());
+                rater::run_command(&mut command, protocol).await?;
+            }
+            #[cfg(feature = "ftp")]
+            Protocol::Ftp => {
+                let mut command = rater::curl_command(self.connection_timeout, self.proxy.as_ref());
+                command.arg("--head").arg(db_url.as_str());
+                rater::run_command(&mut command, protocol).await?;
+            }
+            _ => anyhow::bail!("checking {protocol} mirrors is not supported"),
+        }
+        Ok(())
+    }
+
+    /// Retrieve the release of the latest ISO of an HTTP(S) mirror and check that the ISO
+    /// itself can be retrieved.
+    ///
+    /// # Errors
+    /// Fails if the checksums of the release or the ISO cannot be retrieved, or no ISO is
+    /// listed in the checksums.
+    pub async fn iso_version(&self, url: &Url) -> Result<String> {
+        let latest = url.join("iso/latest/")?;
+        let sha256sums = self
+            .http_client
+            .get(latest.join("sha256sums.txt")?)
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        let version = parse_iso_version(&sha256sums)
+            .ok_or_else(|| anyhow::anyhow!("no ISO is listed in sha256sums.txt"))?;
+        self.http_client
+            .head(latest.join(&format!("archlinux-{version}-x86_64.iso"))?)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(version.to_owned())
+    }
+
+    /// Retrieve the time of the last synchronization from the lastsync file of an HTTP(S)
+    /// mirror, which holds a Unix timestamp.
+    ///
+    /// # Errors
+    /// Fails if the file cannot be retrieved or does not hold a Unix timestamp.
+    pub async fn last_sync(&self, url: &Url) -> Result<Timestamp> {
+        let text = self
+            .http_client
+            .get(url.join(LASTSYNC_FILENAME)?)
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        let seconds = text
+            .trim()
+            .parse()
+            .map_err(|err| anyhow::anyhow!("invalid {LASTSYNC_FILENAME} file: {err}"))?;
+        Ok(Timestamp::from_second(seconds)?)
+    }
+}
+
+/// Find the release of the ISO listed in the "sha256sums.txt" file of a release, e.g.
+/// "2026.10.01" for "archlinux-2026.10.01-x86_64.iso".
+fn parse_iso_version(sha256sums: &str) -> Option<&str> {
+    sha256sums.lines().find_map(|line| {
+        let (_, file_name) = line.split_once(char::is_whitespace)?;
+        file_name
+            .trim()
+            .strip_prefix("archlinux-")?
+            .strip_suffix("-x86_64.iso")
+            .filter(|version| version.starts_with(|c: char| c.is_ascii_digit()))
+    })
+}
+
+/// Limits how many mirrors are probed at the same time, adapting the limit to the combined
+/// download rate of the probes if it is adaptive.
+struct Throttle {
+    permits: Permits,
+    /// The number of mirrors that may be probed at the same time.
+    limit: usize,
+    max: usize,
+    /// Whether the limit no longer changes.
+    settled: bool,
+    /// The highest combined download rate of a round so far.
+    best_rate: f64,
+    /// The bytes transferred by the probes completed in the current round.
+    bytes: u64,
+    completed: usize,
+    started: Instant,
+}
+
+impl Throttle {
+    /// A throttle that starts at one probe at a time if `adaptive`, or else always allows
+    /// `max` probes at the same time.
+    fn new(max: usize, adaptive: bool) -> Self {
+        let limit = if adaptive { 1 } else { max };
+        Self {
+            permits: Permits {
+                semaphore: Arc::new(Semaphore::new(limit)),
+                surplus: Arc::new(AtomicUsize::new(0)),
+            },
+            limit,
+            max,
+            settled: !adaptive || limit >= max,
+            best_rate: 0.0,
+            bytes: 0,
+            completed: 0,
+            started: Instant::now(),
+        }
+    }
+
+    /// Record a completed probe that transferred `bytes`. A round ends once as many probes
+    /// as the limit have completed, and the limit is raised if the round was faster than
+    /// the ones before, or else lowered again and kept.
+    #[allow(clippy::cast_precision_loss)]
+    fn record(&mut self, bytes: u64) {
+        if self.settled {
+            return;
+        }
+        self.bytes += bytes;
+        self.completed += 1;
+        if self.completed < self.limit {
+            return;
+        }
+        let rate = self.bytes as f64 / self.started.elapsed().as_secs_f64().max(1e-6);
+        if rate >= self.best_rate * MIN_SPEEDUP {
+            self.best_rate = rate;
+            self.limit += 1;
+            self.permits.semaphore.add_permits(1);
+            self.settled = self.limit >= self.max;
+            tracing::debug!(
+                phase = "rate",
+                "rating up to {} mirrors at the same time",
+                self.limit
+            );
+        } else {
+            self.limit -= 1;
+            self.settled = true;
+            // If all permits are in use, one is taken away as soon as a probe releases it,
+            // before it can pass to the next probe waiting for it.
+            if self.permits.semaphore.forget_permits(1) == 0 {
+                self.permits.surplus.fetch_add(1, AtomicOrdering::SeqCst);
+            }
+            tracing::debug!(
+                phase = "rate",
+                "the bandwidth is saturated, rating up to {} mirrors at the same time",
+                self.limit
+            );
+        }
+        self.bytes = 0;
+        self.completed = 0;
+        self.started = Instant::now();
+    }
+}
+
+/// The permits of a [`Throttle`], which probes acquire before they start.
+#[derive(Clone)]
+struct Permits {
+    semaphore: Arc<Semaphore>,
+    /// The number of permits to take away as probes release them, since the limit was
+    /// lowered while they were in use.
+    surplus: Arc<AtomicUsize>,
+}
+
+impl Permits {
+    /// Wait for a permit to probe a mirror.
+    async fn acquire(&self) -> Result<Permit> {
+        Ok(Permit {
+            permit: Some(self.semaphore.clone().acquire_owned().await?),
+            surplus: self.surplus.clone(),
+        })
+    }
+}
+
+/// A permit to probe a mirror, which is released when the probe completes unless it is
+/// taken away to lower the limit.
+struct Permit {
+    permit: Option<OwnedSemaphorePermit>,
+    surplus: Arc<AtomicUsize>,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let surplus =
+            self.surplus
+                .fetch_update(AtomicOrdering::SeqCst, AtomicOrdering::SeqCst, |surplus| {
+                    surplus.checked_sub(1)
+                });
+        if let (Ok(_), Some(permit)) = (surplus, self.permit.take()) {
+            permit.forget();
+        }
+    }
+}
+
+/// A random duration between half and one and a half times `spacing`.
+fn jittered(spacing: Duration) -> Duration {
+    // Every RandomState is seeded differently, which is random enough for jitter.
+    let random = RandomState::new().hash_one(spacing);
+    #[allow(clippy::cast_precision_loss)]
+    let fraction = random as f64 / u64::MAX as f64;
+    spacing.mul_f64(0.5 + fraction)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_iso_versions() {
+        let sha256sums = "\
+0a1b2c  archlinux-2026.10.01-x86_64.iso
+3d4e5f  archlinux-bootstrap-2026.10.01-x86_64.tar.zst
+6a7b8c  archlinux-x86_64.iso
+";
+        assert_eq!(parse_iso_version(sha256sums), Some("2026.10.01"));
+        assert_eq!(parse_iso_version("6a7b8c  archlinux-x86_64.iso\n"), None);
+        assert_eq!(parse_iso_version(""), None);
+    }
+
+    #[test]
+    fn jitter_spacing() {
+        let spacing = Duration::from_millis(200);
+        for _ in 0..100 {
+            let delay = jittered(spacing);
+            assert!(delay >= spacing / 2 && delay <= spacing * 3 / 2);
+        }
+    }
+
+    #[test]
+    fn adapt_concurrency() {
+        let mut throttle = Throttle::new(4, true);
+        let semaphore = throttle.permits.semaphore.clone();
+        assert_eq!(semaphore.available_permits(), 1);
+        // Any download rate beats no rating at all.
+        throttle.record(1000);
+        assert_eq!(semaphore.available_permits(), 2);
+        // A slower round means the bandwidth is saturated.
+        throttle.record(0);
+        throttle.record(0);
+        assert_eq!(semaphore.available_permits(), 1);
+        throttle.record(1_000_000);
+        assert_eq!(semaphore.available_permits(), 1);
+
+        let mut throttle = Throttle::new(4, false);
+        throttle.record(1000);
+        assert_eq!(throttle.permits.semaphore.available_permits(), 4);
+    }
+
+    #[test]
+    fn lower_concurrency_with_waiting_probes() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let mut throttle = Throttle::new(4, true);
+            let first = throttle.permits.acquire().await.unwrap();
+            throttle.record(1000);
+            let second = throttle.permits.acquire().await.unwrap();
+            // More probes wait for a permit, like the probes of all the other mirrors.
+            let permits = throttle.permits.clone();
+            let waiting = (0..2)
+                .map(|_| {
+                    let permits = permits.clone();
+                    tokio::spawn(async move { permits.acquire().await.unwrap() })
+                })
+                .collect::<Vec<_>>();
+            tokio::task::yield_now().await;
+            throttle.record(0);
+            throttle.record(0);
+
+            // The permit of the first probe is taken away instead of passing to a waiting
+            // probe, so only one probe runs at a time again.
+            drop(first);
+            tokio::task::yield_now().await;
+            assert!(waiting.iter().all(|probe| !probe.is_finished()));
+            drop(second);
+            tokio::task::yield_now().await;
+            assert_eq!(
+                waiting.iter().filter(|probe| probe.is_finished()).count(),
+                1
+            );
+        });
+    }
+}
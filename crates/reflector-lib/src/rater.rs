@@ -0,0 +1,755 @@
+//! This is where the [`Rater`] trait and the ways of rating mirrors that implement it go.
+use crate::rate::{Prober, Rating};
+use anyhow::Result;
+use arch_mirrors_rs::Protocol;
+use futures_util::StreamExt;
+use futures_util::future::{BoxFuture, FutureExt};
+use reqwest::Url;
+use std::io;
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+
+/// The default port of rsync daemons.
+const RSYNC_PORT: u16 = 873;
+
+/// A way of rating single mirrors. The higher the rate of a [`Rating`], the better the
+/// mirror.
+///
+/// Raters are combined with [`ByProtocol`] and used by a [`Prober`] with
+/// [`Prober::rater`], which takes care of the concurrency and retries.
+///
+/// ```
+/// use reflector_lib::arch_mirrors_rs::Protocol;
+/// use futures_util::future::{self, BoxFuture, FutureExt};
+/// use reflector_lib::rate::Rating;
+/// use reflector_lib::rater::Rater;
+/// use reflector_lib::reqwest::Url;
+/// use std::time::Duration;
+///
+/// /// Prefers mirrors with shorter URLs.
+/// #[derive(Debug)]
+/// struct Shortest;
+///
+/// impl Rater for Shortest {
+///     fn supports(&self, _: Protocol) -> bool {
+///         true
+///     }
+///
+///     fn rate<'a>(&'a self, url: &'a Url, _: Protocol) -> BoxFuture<'a, anyhow::Result<Rating>> {
+///         let rating = Rating {
+///             rate: 1.0 / url.as_str().len() as f64,
+///             ttfb: None,
+///             bytes: 0,
+///             elapsed: Duration::ZERO,
+///             http_version: None,
+///         };
+///         future::ready(Ok(rating)).boxed()
+///     }
+/// }
+/// ```
+pub trait Rater: std::fmt::Debug + Send + Sync {
+    /// Whether mirrors of `protocol` can be rated.
+    fn supports(&self, protocol: Protocol) -> bool;
+
+    /// Rate the mirror with the base URL `url`, which is served over `protocol`.
+    fn rate<'a>(&'a self, url: &'a Url, protocol: Protocol) -> BoxFuture<'a, Result<Rating>>;
+}
+
+impl<R: Rater + ?Sized> Rater for Arc<R> {
+    fn supports(&self, protocol: Protocol) -> bool {
+        (**self).supports(protocol)
+    }
+
+    fn rate<'a>(&'a self, url: &'a Url, protocol: Protocol) -> BoxFuture<'a, Result<Rating>> {
+        (**self).rate(url, protocol)
+    }
+}
+
+/// Rates each mirror with the first of its raters that supports the protocol of the mirror.
+#[derive(Debug, Clone, Default)]
+pub struct ByProtocol {
+    raters: Vec<Arc<dyn Rater>>,
+}
+
+impl ByProtocol {
+    /// A rater without any raters, which supports no protocol.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Try `rater` after the raters that were added before it.
+    #[must_use]
+    pub fn with(mut self, rater: impl Rater + 'static) -> Self {
+        self.raters.push(Arc::new(rater));
+        self
+    }
+}
+
+impl Rater for ByProtocol {
+    fn supports(&self, protocol: Protocol) -> bool {
+        self.raters.iter().any(|rater| rater.supports(protocol))
+    }
+
+    fn rate<'a>(&'a self, url: &'a Url, protocol: Protocol) -> BoxFuture<'a, Result<Rating>> {
+        match self.raters.iter().find(|rater| rater.supports(protocol)) {
+            Some(rater) => rater.rate(url, protocol),
+            None => futures_util::future::ready(Err(anyhow::anyhow!(
+                "rating {protocol} mirrors is not supported"
+            )))
+            .boxed(),
+        }
+    }
+}
+
+/// Rates HTTP(S) mirrors by the rate at which their database downloads. The time until
+/// the response headers arrive is reported as the time to first byte and not included.
+#[derive(Debug, Clone)]
+pub struct HttpRater {
+    http_client: reqwest::Client,
+    db_path: String,
+    download_size: Option<u64>,
+    #[cfg(feature = "http3")]
+    http3: bool,
+}
+
+impl HttpRater {
+    /// A rater that downloads the whole database at [`Prober::DB_PATH`] with `http_client`.
+    #[must_use]
+    pub fn new(http_client: reqwest::Client) -> Self {
+        Self {
+            http_client,
+            db_path: Prober::DB_PATH.to_owned(),
+            download_size: None,
+            #[cfg(feature = "http3")]
+            http3: false,
+        }
+    }
+
+    /// The path of the database to download, relative to the URL of a mirror.
+    #[must_use]
+    pub fn db_path(mut self, db_path: impl Into<String>) -> Self {
+        self.db_path = db_path.into();
+        self
+    }
+
+    /// Only download the first `bytes` of the database.
+    #[must_use]
+    pub fn download_size(mut self, bytes: u64) -> Self {
+        self.download_size = Some(bytes);
+        self
+    }
+
+    /// Whether to attempt HTTP/3 for HTTPS mirrors, falling back to the version negotiated
+    /// with the server.
+    #[cfg(feature = "http3")]
+    #[must_use]
+    pub fn http3(mut self, http3: bool) -> Self {
+        self.http3 = http3;
+        self
+    }
+
+    async fn rate_url(&self, url: &Url) -> Result<Rating> {
+        let db_url = url.join(&self.db_path)?;
+        // HTTP/3 runs over QUIC, which is only used with TLS.
+        #[cfg(feature = "http3")]
+        if self.http3 && db_url.scheme() == "https" {
+            match self
+                .rate_version(db_url.clone(), Some(reqwest::Version::HTTP_3))
+                .await
+            {
+                Ok(rating) => return Ok(rating),
+                Err(err) => tracing::debug!(
+                    mirror_url = %db_url,
+                    phase = "rate",
+                    "falling back from HTTP/3: {err}"
+                ),
+            }
+        }
+        self.rate_version(db_url, None).await
+    }
+
+    /// Rate an HTTP(S) mirror using the given HTTP version, or the one negotiated with the
+    /// server if none is given.
+    #[allow(clippy::cast_precision_loss)]
+    async fn rate_version(&self, db_url: Url, version: Option<reqwest::Version>) -> Result<Rating> {
+        let start = Instant::now();
+        let mut content_length = 0;
+        let mut request = self.http_client.get(db_url);
+        if let Some(version) = version {
+            request = request.version(version);
+        }
+        if let Some(download_size) = self.download_size {
+            request = request.header(
+                reqwest::header::RANGE,
+                format!("bytes=0-{}", download_size - 1),
+            );
+        }
+        let response = request.send().await?;
+        let ttfb = start.elapsed();
+        let http_version = response.version();
+        // Servers that ignore the Range header send the whole database, which then is rated
+        // as a full download.
+        if self.download_size.is_some() && response.status() != reqwest::StatusCode::PARTIAL_CONTENT
+        {
+            tracing::debug!(
+                mirror_url = %response.url(),
+                phase = "rate",
+                "the server ignored the Range header, downloading the whole database"
+            );
+        }
+        // Only time the transfer of the body. The time until then is reported as the time
+        // to first byte instead.
+        let start = Instant::now();
+        let mut stream = response.bytes_stream();
+        while let Some(chunk) = stream.next().await {
+            content_length += chunk?.len();
+        }
+        let elapsed = start.elapsed().max(Duration::from_micros(1));
+        let rate = (content_length as f64) / elapsed.as_secs_f64();
+        Ok(Rating {
+            rate,
+            ttfb: Some(ttfb),
+            bytes: content_length as u64,
+            elapsed,
+            http_version: Some(http_version),
+        })
+    }
+}
+
+impl Rater for HttpRater {
+    fn supports(&self, protocol: Protocol) -> bool {
+        matches!(protocol, Protocol::Http | Protocol::Https)
+    }
+
+    fn rate<'a>(&'a self, url: &'a Url, _: Protocol) -> BoxFuture<'a, Result<Rating>> {
+        self.rate_url(url).boxed()
+    }
+}
+
+/// Rates rsync mirrors by the rate at which rsync downloads their database, including the
+/// time it takes to connect. Requires rsync, unless a fallback is set with
+/// [`RsyncRater::http_fallback`].
+#[derive(Debug, Clone)]
+pub struct RsyncRater {
+    db_path: String,
+    connection_timeout: Duration,
+    proxy: Option<Url>,
+    extra_args: Vec<String>,
+    http_fallback: Option<HttpRater>,
+}
+
+impl RsyncRater {
+    /// A rater that downloads the database at [`Prober::DB_PATH`] with a connection timeout
+    /// of 5 seconds.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            db_path: Prober::DB_PATH.to_owned(),
+            connection_timeout: Duration::from_secs(5),
+            proxy: None,
+            extra_args: Vec::new(),
+            http_fallback: None,
+        }
+    }
+
+    /// The path of the database to download, relative to the URL of a mirror.
+    #[must_use]
+    pub fn db_path(mut self, db_path: impl Into<String>) -> Self {
+        self.db_path = db_path.into();
+        self
+    }
+
+    /// The timeout for connecting to mirrors.
+    #[must_use]
+    pub fn connection_timeout(mut self, timeout: Duration) -> Self {
+        self.connection_timeout = timeout;
+        self
+    }
+
+    /// Connect to mirrors through the HTTP proxy at `proxy`.
+    #[must_use]
+    pub fn proxy(mut self, proxy: Url) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Extra arguments passed to rsync.
+    #[must_use]
+    pub fn extra_args<S: Into<String>>(mut self, args: impl IntoIterator<Item = S>) -> Self {
+        self.extra_args = args.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// If rsync is not installed, rate mirrors with `rater` over HTTPS and then HTTP
+    /// instead, at the same host and path. Most rsync mirrors also serve HTTP(S), but the
+    /// rates are those of a different protocol.
+    #[must_use]
+    pub fn http_fallback(mut self, rater: HttpRater) -> Self {
+        self.http_fallback = Some(rater);
+        self
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    async fn rate_url(&self, url: &Url) -> Result<Rating> {
+        if let Some(http) = &self.http_fallback {
+            if !rsync_installed() {
+                return http_fallback(url, |url| async move { http.rate_url(&url).await }).await;
+            }
+        }
+        let db_url = url.join(&self.db_path)?;
+        let temp_dir = tempfile::TempDir::new()?;
+
+        let mut command = rsync_command(
+            self.connection_timeout,
+            self.proxy.as_ref(),
+            &self.extra_args,
+        );
+        command
+            .arg("-avL")
+            .arg("--no-h")
+            .arg(db_url.as_str())
+            .arg(temp_dir.path());
+
+        let start = Instant::now();
+        run_command(&mut command, Protocol::Rsync).await?;
+
+        let elapsed = start.elapsed();
+        let file_path = Path::join(temp_dir.path(), file_name(&self.db_path));
+        let content_length = std::fs::metadata(file_path)?.len();
+
+        let rate = (content_length as f64) / elapsed.as_secs_f64();
+        Ok(Rating {
+            rate,
+            ttfb: None,
+            bytes: content_length,
+            elapsed,
+            http_version: None,
+        })
+    }
+}
+
+impl Default for RsyncRater {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Rater for RsyncRater {
+    fn supports(&self, protocol: Protocol) -> bool {
+        protocol == Protocol::Rsync
+    }
+
+    fn rate<'a>(&'a self, url: &'a Url, _: Protocol) -> BoxFuture<'a, Result<Rating>> {
+        self.rate_url(url).boxed()
+    }
+}
+
+/// Rates FTP mirrors by the rate at which curl downloads their database, including the
+/// time it takes to connect. Requires curl.
+#[cfg(feature = "ftp")]
+#[derive(Debug, Clone)]
+pub struct FtpRater {
+    db_path: String,
+    connection_timeout: Duration,
+    proxy: Option<Url>,
+    download_size: Option<u64>,
+}
+
+#[cfg(feature = "ftp")]
+impl FtpRater {
+    /// A rater that downloads the whole database at [`Prober::DB_PATH`] with a connection
+    /// timeout of 5 seconds.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            db_path: Prober::DB_PATH.to_owned(),
+            connection_timeout: Duration::from_secs(5),
+            proxy: None,
+            download_size: None,
+        }
+    }
+
+    /// The path of the database to download, relative to the URL of a mirror.
+    #[must_use]
+    pub fn db_path(mut self, db_path: impl Into<String>) -> Self {
+        self.db_path = db_path.into();
+        self
+    }
+
+    /// The timeout for connecting to mirrors.
+    #[must_use]
+    pub fn connection_timeout(mut self, timeout: Duration) -> Self {
+        self.connection_timeout = timeout;
+        self
+    }
+
+    /// Connect to mirrors through the proxy at `proxy`.
+    #[must_use]
+    pub fn proxy(mut self, proxy: Url) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Only download the first `bytes` of the database.
+    #[must_use]
+    pub fn download_size(mut self, bytes: u64) -> Self {
+        self.download_size = Some(bytes);
+        self
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    async fn rate_url(&self, url: &Url) -> Result<Rating> {
+        let db_url = url.join(&self.db_path)?;
+        let temp_dir = tempfile::TempDir::new()?;
+        let file_path = Path::join(temp_dir.path(), file_name(&self.db_path));
+
+        let mut command = curl_command(self.connection_timeout, self.proxy.as_ref());
+        command.arg("--output").arg(&file_path).arg(db_url.as_str());
+        if let Some(download_size) = self.download_size {
+            command
+                .arg("--range")
+                .arg(format!("0-{}", download_size - 1));
+        }
+
+        let start = Instant::now();
+        run_command(&mut command, Protocol::Ftp).await?;
+
+        let elapsed = start.elapsed();
+        let content_length = std::fs::metadata(file_path)?.len();
+
+        let rate = (content_length as f64) / elapsed.as_secs_f64();
+        Ok(Rating {
+            rate,
+            ttfb: None,
+            bytes: content_length,
+            elapsed,
+            http_version: None,
+        })
+    }
+}
+
+#[cfg(feature = "ftp")]
+impl Default for FtpRater {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "ftp")]
+impl Rater for FtpRater {
+    fn supports(&self, protocol: Protocol) -> bool {
+        protocol == Protocol::Ftp
+    }
+
+    fn rate<'a>(&'a self, url: &'a Url, _: Protocol) -> BoxFuture<'a, Result<Rating>> {
+        self.rate_url(url).boxed()
+    }
+}
+
+/// Rates mirrors of any protocol by their latency, without downloading anything. The rate
+/// of its ratings is the number of round trips per second rather than a transfer rate, so
+/// they cannot be compared with those of other raters.
+///
+/// For HTTP(S), the latency is the time to the first byte of the response to a HEAD
+/// request for the database, which includes the TLS handshake. For other protocols, it is
+/// the time to open a TCP connection.
+#[derive(Debug, Clone)]
+pub struct LatencyRater {
+    http_client: reqwest::Client,
+    db_path: String,
+    connection_timeout: Duration,
+}
+
+impl LatencyRater {
+    /// A rater that sends its HTTP(S) requests for the database at [`Prober::DB_PATH`] with
+    /// `http_client` and connects to other mirrors with a timeout of 5 seconds.
+    #[must_use]
+    pub fn new(http_client: reqwest::Client) -> Self {
+        Self {
+            http_client,
+            db_path: Prober::DB_PATH.to_owned(),
+            connection_timeout: Duration::from_secs(5),
+        }
+    }
+
+    /// The path of the database to request, relative to the URL of a mirror.
+    #[must_use]
+    pub fn db_path(mut self, db_path: impl Into<String>) -> Self {
+        self.db_path = db_path.into();
+        self
+    }
+
+    /// The timeout for connecting to mirrors other than HTTP(S) ones.
+    #[must_use]
+    pub fn connection_timeout(mut self, timeout: Duration) -> Self {
+        self.connection_timeout = timeout;
+        self
+    }
+
+    /// Measure the latency of the mirror with the base URL `url`.
+    ///
+    /// # Errors
+    /// Fails if the request fails, the server answers with an error, or the connection
+    /// cannot be opened in time.
+    pub async fn latency(&self, url: &Url, protocol: Protocol) -> Result<Duration> {
+        let start = Instant::now();
+        if matches!(protocol, Protocol::Http | Protocol::Https) {
+            let db_url = url.join(&self.db_path)?;
+            // A mirror that quickly answers that it lacks the database is not a fast one.
+            self.http_client
+                .head(db_url)
+                .send()
+                .await?
+                .error_for_status()?;
+        } else {
+            let host = url
+                .host_str()
+                .ok_or_else(|| anyhow::anyhow!("{url} has no host"))?;
+            let port = url.port_or_known_default().unwrap_or(RSYNC_PORT);
+            tokio::time::timeout(
+                self.connection_timeout,
+                tokio::net::TcpStream::connect((host, port)),
+            )
+            .await??;
+        }
+        Ok(start.elapsed())
+    }
+}
+
+impl Rater for LatencyRater {
+    fn supports(&self, _: Protocol) -> bool {
+        true
+    }
+
+    fn rate<'a>(&'a self, url: &'a Url, protocol: Protocol) -> BoxFuture<'a, Result<Rating>> {
+        async move {
+            let latency = self
+                .latency(url, protocol)
+                .await?
+                .max(Duration::from_micros(1));
+            Ok(Rating {
+                rate: 1.0 / latency.as_secs_f64(),
+                ttfb: Some(latency),
+                bytes: 0,
+                elapsed: latency,
+                http_version: None,
+            })
+        }
+        .boxed()
+    }
+}
+
+/// An rsync command with the given connection timeout, proxy and extra arguments.
+pub(crate) fn rsync_command(
+    connection_timeout: Duration,
+    proxy: Option<&Url>,
+    extra_args: &[String],
+) -> tokio::process::Command {
+    let mut command = tokio::process::Command::new("rsync");
+    command
+        .arg("--no-motd")
+        .arg(format!("--contimeout={}", connection_timeout.as_secs()))
+        .args(extra_args);
+    // rsync can tunnel through HTTP proxies, which it expects as "host:port".
+    if let Some(proxy) = proxy {
+        if let (Some(host), Some(port)) = (proxy.host_str(), proxy.port_or_known_default()) {
+            command.env("RSYNC_PROXY", format!("{host}:{port}"));
+        }
+    }
+    command
+}
+
+/// Whether rsync can be found on the PATH. It is only looked up once.
+pub(crate) fn rsync_installed() -> bool {
+    static INSTALLED: OnceLock<bool> = OnceLock::new();
+    *INSTALLED.get_or_init(|| {
+        let installed = std::env::var_os("PATH").is_some_and(|path| {
+            std::env::split_paths(&path).any(|dir| dir.join("rsync").is_file())
+        });
+        if !installed {
+            tracing::warn!("rsync is not installed, rating rsync mirrors over HTTP(S) instead");
+        }
+        installed
+    })
+}
+
+/// The HTTPS and HTTP URLs of the rsync mirror with the base URL `url`, at the same host
+/// and path. The port of the rsync daemon is dropped.
+pub(crate) fn http_urls(url: &Url) -> Result<[Url; 2]> {
+    let rest = url
+        .as_str()
+        .strip_prefix("rsync:")
+        .ok_or_else(|| anyhow::anyhow!("{url} is not an rsync URL"))?;
+    let http_url = |scheme: &str| -> Result<Url> {
+        let mut http_url = Url::parse(&format!("{scheme}:{rest}"))?;
+        if url.port().is_some() {
+            http_url
+                .set_port(None)
+                .map_err(|()| anyhow::anyhow!("{url} has no host"))?;
+        }
+        Ok(http_url)
+    };
+    Ok([http_url("https")?, http_url("http")?])
+}
+
+/// Probe the rsync mirror with the base URL `url` with `probe` over HTTPS and, if that
+/// fails, over HTTP.
+pub(crate) async fn http_fallback<T, F>(url: &Url, probe: impl Fn(Url) -> F) -> Result<T>
+where
+    F: Future<Output = Result<T>>,
+{
+    let [secure, plain] = http_urls(url)?;
+    match probe(secure).await {
+        Ok(result) => Ok(result),
+        Err(err) => {
+            tracing::debug!(mirror_url = %url, "{err:#}, retrying over HTTP");
+            probe(plain)
+                .await
+                .map_err(|err| err.context("rsync is not installed and HTTP(S) failed"))
+        }
+    }
+}
+
+/// A curl command with the given connection timeout and proxy.
+#[cfg(feature = "ftp")]
+pub(crate) fn curl_command(
+    connection_timeout: Duration,
+    proxy: Option<&Url>,
+) -> tokio::process::Command {
+    let mut command = tokio::process::Command::new("curl");
+    command
+        .arg("--silent")
+        .arg("--fail")
+        .arg("--connect-timeout")
+        .arg(connection_timeout.as_secs().to_string());
+    if let Some(proxy) = proxy {
+        command.arg("--proxy").arg(proxy.as_str());
+    }
+    command
+}
+
+/// The name of the file at `path`, as rsync and curl save it.
+fn file_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+/// Run the external program used to probe mirrors of the given protocol, failing if it
+/// does not exit successfully. A missing program is reported along with how to skip the
+/// mirrors that need it.
+pub(crate) async fn run_command(
+    command: &mut tokio::process::Command,
+    protocol: Protocol,
+) -> Result<()> {
+    let program = command
+        .as_std()
+        .get_program()
+        .to_string_lossy()
+        .into_owned();
+    // The program is killed if probing is abandoned, e.g. because it timed out.
+    let spawned = command
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn();
+    let mut child = match spawned {
+        Ok(child) => child,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => anyhow::bail!(
+            "{program} is not installed, but it is required to probe {protocol} mirrors; \
+             install it or exclude these mirrors"
+        ),
+        Err(err) => return Err(anyhow::anyhow!("failed to run {program}: {err}")),
+    };
+    let exit_status = child.wait().await?;
+    if !exit_status.success() {
+        anyhow::bail!("{program} failed with {exit_status}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::future;
+
+    /// Rates every mirror of one protocol at a fixed rate.
+    #[derive(Debug)]
+    struct Fixed(Protocol, f64);
+
+    impl Rater for Fixed {
+        fn supports(&self, protocol: Protocol) -> bool {
+            protocol == self.0
+        }
+
+        fn rate<'a>(&'a self, _: &'a Url, _: Protocol) -> BoxFuture<'a, Result<Rating>> {
+            future::ready(Ok(Rating {
+                rate: self.1,
+                ttfb: None,
+                bytes: 0,
+                elapsed: Duration::ZERO,
+                http_version: None,
+            }))
+            .boxed()
+        }
+    }
+
+    fn rate_now(rater: &impl Rater, protocol: Protocol) -> Result<f64> {
+        let url = Url::parse("https://mirror.example.se/arch/").unwrap();
+        let rating = rater.rate(&url, protocol).now_or_never().unwrap()?;
+        Ok(rating.rate)
+    }
+
+    #[test]
+    fn rate_by_protocol() {
+        let rater = ByProtocol::new()
+            .with(Fixed(Protocol::Https, 1.0))
+            .with(Fixed(Protocol::Rsync, 2.0))
+            .with(Fixed(Protocol::Https, 3.0));
+        assert!(rater.supports(Protocol::Rsync));
+        assert!(!rater.supports(Protocol::Http));
+        assert!((rate_now(&rater, Protocol::Https).unwrap() - 1.0).abs() < f64::EPSILON);
+        assert!((rate_now(&rater, Protocol::Rsync).unwrap() - 2.0).abs() < f64::EPSILON);
+        assert!(rate_now(&rater, Protocol::Http).is_err());
+    }
+
+    #[test]
+    fn http_urls_of_rsync_mirror() {
+        let url = Url::parse("rsync://mirror.example.de:873/archlinux/").unwrap();
+        let [secure, plain] = http_urls(&url).unwrap();
+        assert_eq!(secure.as_str(), "https://mirror.example.de/archlinux/");
+        assert_eq!(plain.as_str(), "http://mirror.example.de/archlinux/");
+        let url = Url::parse("https://mirror.example.de/archlinux/").unwrap();
+        assert!(http_urls(&url).is_err());
+    }
+
+    #[test]
+    fn prober_uses_rater() {
+        let prober = Prober::new(reqwest::Client::new()).rater(Fixed(Protocol::Http, 4.0));
+        let url = Url::parse("http://mirror.example.de/arch/").unwrap();
+        let rating = prober.rate(&url, Protocol::Http).now_or_never().unwrap();
+        assert!((rating.unwrap().rate - 4.0).abs() < f64::EPSILON);
+        let result = prober.rate(&url, Protocol::Https).now_or_never().unwrap();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn latency_of_missing_database() {
+        use std::io::{Read, Write};
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!("http://{}/arch/", listener.local_addr().unwrap())).unwrap();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let _ = stream.read(&mut [0; 1024]);
+            let _ = stream.write_all(b"HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\n\r\n");
+        });
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let latency = runtime
+            .block_on(LatencyRater::new(reqwest::Client::new()).latency(&url, Protocol::Http));
+        assert!(latency.is_err());
+    }
+}
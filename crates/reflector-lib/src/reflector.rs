@@ -0,0 +1,198 @@
+//! This is where the [`Reflector`] builder and the [`Selection`] it returns go.
+use crate::filter;
+use crate::rate::{self, Prober, Ratings};
+use crate::template::Template;
+use anyhow::Result;
+use arch_mirrors_rs::country::Continent;
+use arch_mirrors_rs::mirrorlist::{Header, MirrorlistWriter};
+use arch_mirrors_rs::selector::MirrorSelector;
+use arch_mirrors_rs::{Mirror, Status, StatusFetcher};
+use jiff::Timestamp;
+use regex::Regex;
+use std::io::{self, Write};
+
+/// A builder for what a run of reflector does: retrieving the mirror status, selecting its
+/// mirrors, optionally rating them and sorting them by rate, and keeping the best.
+///
+/// ```no_run
+/// # async fn run() -> anyhow::Result<()> {
+/// use reflector_lib::arch_mirrors_rs::selector::MirrorSelector;
+/// use reflector_lib::arch_mirrors_rs::{Protocol, StatusFetcher};
+/// use reflector_lib::regex::Regex;
+/// use reflector_lib::{Prober, Reflector, Template};
+///
+/// let fetcher = StatusFetcher::new().user_agent("my-setup/1.0");
+/// let http_client = fetcher.client_builder()?.build()?;
+/// let selection = Reflector::new()
+///     .fetcher(fetcher)
+///     .selector(
+///         MirrorSelector::new()
+///             .age(12.0)
+///             .countries(["Sweden", "DE"])
+///             .protocols([Protocol::Https]),
+///     )
+///     .exclude([Regex::new(r"\.example\.de/")?])
+///     .rate(Prober::new(http_client).threads(8))
+///     .max_per_country(5)
+///     .number(10)
+///     .run()
+///     .await?;
+/// selection.write_mirrorlist(std::fs::File::create("mirrorlist")?)?;
+/// let template = "{rank}. {url} ({rate} KiB/s)".parse::<Template>()?;
+/// selection.write_template(&template, std::io::stdout())?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Reflector {
+    fetcher: StatusFetcher,
+    selector: MirrorSelector,
+    include: Vec<Regex>,
+    exclude: Vec<Regex>,
+    continents: Vec<Continent>,
+    prober: Option<Prober>,
+    max_per_country: Option<usize>,
+    number: Option<usize>,
+}
+
+/// The mirrors selected by a [`Reflector`], best first, along with their ratings.
+#[derive(Debug)]
+pub struct Selection {
+    /// The mirror status, with only the selected mirrors in order.
+    pub status: Status,
+
+    /// The ratings of the mirrors that were rated, which is empty if none were.
+    pub ratings: Ratings,
+}
+
+impl Reflector {
+    /// A reflector that keeps every mirror of the official mirror status that synced and
+    /// completed every check, without rating them, like reflector without any options.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Retrieve the mirror status with `fetcher`.
+    #[must_use]
+    pub fn fetcher(mut self, fetcher: StatusFetcher) -> Self {
+        self.fetcher = fetcher;
+        self
+    }
+
+    /// Filter, sort and truncate the mirrors with `selector` before rating them.
+    #[must_use]
+    pub fn selector(mut self, selector: MirrorSelector) -> Self {
+        self.selector = selector;
+        self
+    }
+
+    /// Only keep mirrors whose URL matches one of the expressions, like "--include".
+    #[must_use]
+    pub fn include(mut self, include: impl IntoIterator<Item = Regex>) -> Self {
+        self.include = include.into_iter().collect();
+        self
+    }
+
+    /// Remove mirrors whose URL matches one of the expressions, like "--exclude".
+    #[must_use]
+    pub fn exclude(mut self, exclude: impl IntoIterator<Item = Regex>) -> Self {
+        self.exclude = exclude.into_iter().collect();
+        self
+    }
+
+    /// Only keep mirrors on one of the given continents, like "--continent".
+    #[must_use]
+    pub fn continents(mut self, continents: impl IntoIterator<Item = Continent>) -> Self {
+        self.continents = continents.into_iter().collect();
+        self
+    }
+
+    /// Rate the selected mirrors with `prober` and sort them by rate, fastest first.
+    #[must_use]
+    pub fn rate(mut self, prober: Prober) -> Self {
+        self.prober = Some(prober);
+        self
+    }
+
+    /// Keep at most `n` mirrors of each country, after rating, like "--max-per-country".
+    #[must_use]
+    pub fn max_per_country(mut self, n: usize) -> Self {
+        self.max_per_country = Some(n);
+        self
+    }
+
+    /// Keep at most `n` mirrors, after rating.
+    #[must_use]
+    pub fn number(mut self, n: usize) -> Self {
+        self.number = Some(n);
+        self
+    }
+
+    /// Retrieve the mirror status and select, rate and sort its mirrors. Mirrors that fail
+    /// to rate are kept after the others, with their error in the ratings.
+    ///
+    /// # Errors
+    /// Fails if the mirror status cannot be retrieved.
+    pub async fn run(&self) -> Result<Selection> {
+        let mut status = self.fetcher.fetch().await?;
+        // These filters go first, so the selector sorts and truncates what is left of them.
+        status.urls.retain(|mirror| {
+            (self.include.is_empty() || filter::included(mirror, &self.include))
+                && filter::excluded_by(mirror, &self.exclude).is_none()
+                && (self.continents.is_empty() || filter::on_continents(mirror, &self.continents))
+        });
+        status.urls = self.selector.select(&status);
+        let mut ratings = Ratings::default();
+        if let Some(prober) = &self.prober {
+            let mirrors = rate::interleave_countries(&status.urls)
+                .into_iter()
+                .map(|mirror| (mirror.url.clone(), mirror.protocol))
+                .collect::<Vec<_>>();
+            ratings = prober
+                .rate_all(mirrors, &mut |_, _| {})
+                .await
+                .into_iter()
+                .map(|(url, result)| (url, result.map_err(|err| err.to_string())))
+                .collect();
+            ratings.sort_by_rate(&mut status.urls);
+        }
+        if let Some(n) = self.max_per_country {
+            filter::cap_per_country(&mut status, n, "max_per_country", &mut Vec::new());
+        }
+        if let Some(n) = self.number {
+            status.urls.truncate(n);
+        }
+        Ok(Selection { status, ratings })
+    }
+}
+
+impl Selection {
+    /// The selected mirrors, best first.
+    #[must_use]
+    pub fn mirrors(&self) -> &[Mirror] {
+        &self.status.urls
+    }
+
+    /// Write the selected mirrors as a pacman mirrorlist with the header of reflector.
+    ///
+    /// # Errors
+    /// Fails if `out` cannot be written to.
+    pub fn write_mirrorlist(&self, out: impl Write) -> io::Result<()> {
+        MirrorlistWriter::new()
+            .header(Header {
+                when: Some(Timestamp::now()),
+                last_check: Some(self.status.last_check),
+                ..Header::default()
+            })
+            .write(out, self.mirrors())
+    }
+
+    /// Write the selected mirrors with `template`, one per line.
+    ///
+    /// # Errors
+    /// Fails if `out` cannot be written to.
+    pub fn write_template(&self, template: &Template, out: impl Write) -> io::Result<()> {
+        template.render(&self.status, &self.ratings, out)
+    }
+}
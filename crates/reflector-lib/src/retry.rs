@@ -0,0 +1,72 @@
+//! Retrying transient failures with exponential backoff.
+use std::future::Future;
+use std::hash::{BuildHasher, RandomState};
+use std::time::Duration;
+
+/// The delay before the first retry. It doubles with every further retry.
+const BASE_DELAY: Duration = Duration::from_millis(500);
+
+/// The longest delay between two attempts.
+const MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// Run `attempt` until it succeeds, `should_retry` rejects its error or `retries` retries
+/// have been made, returning the last result. Each retry is logged along with `what` was
+/// attempted.
+///
+/// # Errors
+/// Fails with the error of the last attempt.
+pub async fn retry<T, E, F, Fut>(
+    retries: u32,
+    what: &str,
+    should_retry: impl Fn(&E) -> bool,
+    mut attempt: F,
+) -> Result<T, E>
+where
+    E: std::fmt::Display,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let mut retry = 0;
+    loop {
+        match attempt().await {
+            Err(err) if retry < retries && should_retry(&err) => {
+                let delay = backoff(retry);
+                retry += 1;
+                tracing::info!(
+                    "{what} failed: {err}, retrying in {:.1}s ({retry}/{retries})",
+                    delay.as_secs_f64()
+                );
+                tokio::time::sleep(delay).await;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// The delay before the given retry, counting from zero. The exponential delay is randomly
+/// reduced by up to half, so clients that failed together do not retry in lockstep.
+fn backoff(retry: u32) -> Duration {
+    let delay = BASE_DELAY
+        .saturating_mul(2_u32.saturating_pow(retry))
+        .min(MAX_DELAY);
+    // Every RandomState is seeded differently, which is random enough for jitter.
+    let random = RandomState::new().hash_one(retry);
+    #[allow(clippy::cast_precision_loss)]
+    let fraction = random as f64 / u64::MAX as f64;
+    delay.mul_f64(1.0 - fraction / 2.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_grows_and_is_capped() {
+        for retry in 0..10 {
+            let delay = backoff(retry);
+            let full = BASE_DELAY * 2_u32.pow(retry);
+            assert!(delay <= full.min(MAX_DELAY));
+            assert!(delay >= full.min(MAX_DELAY) / 2);
+        }
+    }
+}
@@ -0,0 +1,133 @@
+//! Versioned envelopes for the machine-readable (JSON) outputs.
+//!
+//! Every JSON document written by reflector is an object with a `schema_version` field and
+//! a single payload field named after its contents, e.g.
+//!
+//! ```json
+//! { "schema_version": 1, "countries": [ ... ] }
+//! ```
+//!
+//! Compatibility policy: new fields may be added to any object without changing
+//! [`SCHEMA_VERSION`], so consumers must ignore fields they do not know. Removing or renaming
+//! a field, or changing its type or meaning, increments [`SCHEMA_VERSION`].
+use serde::Serialize;
+use serde::ser::{SerializeMap, Serializer};
+use std::io::Write;
+
+/// The current version of the JSON output schema.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// A versioned JSON document with a single payload field.
+pub struct Document<'a, T> {
+    /// The name of the payload field.
+    pub key: &'static str,
+    /// The payload.
+    pub value: &'a T,
+}
+
+impl<T: Serialize> Serialize for Document<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(2))?;
+        map.serialize_entry("schema_version", &SCHEMA_VERSION)?;
+        map.serialize_entry(self.key, self.value)?;
+        map.end()
+    }
+}
+
+/// Write `value` as a pretty-printed, versioned JSON document with the payload field `key`.
+///
+/// # Errors
+/// Fails if `value` cannot be serialized or `out` cannot be written to.
+pub fn write_json<T: Serialize>(
+    mut out: impl Write,
+    key: &'static str,
+    value: &T,
+) -> anyhow::Result<()> {
+    serde_json::to_writer_pretty(&mut out, &Document { key, value })?;
+    writeln!(out)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::{MirrorEntry, MirrorList};
+    use arch_mirrors_rs::Protocol;
+    use jiff::Timestamp;
+
+    // Changing the expected output of these tests requires following the compatibility
+    // policy described at the top of this module.
+
+    #[test]
+    fn document_envelope() {
+        let value = serde_json::to_value(Document {
+            key: "items",
+            value: &[1, 2],
+        })
+        .unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({ "schema_version": 1, "items": [1, 2] })
+        );
+    }
+
+    #[test]
+    fn mirrorlist_schema() {
+        let when = Timestamp::from_second(1_700_000_000).unwrap();
+        let list = MirrorList {
+            generated: when,
+            origin: "https://archlinux.org/mirrors/status/json/",
+            retrieved: when,
+            last_check: when,
+            mirrors: vec![MirrorEntry {
+                rank: 1,
+                url: "https://mirror.example.com/archlinux/",
+                server: "https://mirror.example.com/archlinux/$repo/os/$arch".to_owned(),
+                protocol: Protocol::Https,
+                country: "Sweden",
+                country_code: "SE",
+                last_sync: Some(when),
+                age_seconds: Some(60),
+                delay: Some(120),
+                score: Some(1.5),
+                completion_pct: Some(1.0),
+                rate_bytes_per_second: Some(2048.0),
+                ttfb_seconds: None,
+                http_version: Some("HTTP/2.0".to_owned()),
+            }],
+        };
+        let value = serde_json::to_value(Document {
+            key: "mirrorlist",
+            value: &list,
+        })
+        .unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "schema_version": 1,
+                "mirrorlist": {
+                    "generated": "2023-11-14T22:13:20Z",
+                    "origin": "https://archlinux.org/mirrors/status/json/",
+                    "retrieved": "2023-11-14T22:13:20Z",
+                    "last_check": "2023-11-14T22:13:20Z",
+                    "mirrors": [{
+                        "rank": 1,
+                        "url": "https://mirror.example.com/archlinux/",
+                        "server": "https://mirror.example.com/archlinux/$repo/os/$arch",
+                        "protocol": "https",
+                        "country": "Sweden",
+                        "country_code": "SE",
+                        "last_sync": "2023-11-14T22:13:20Z",
+                        "age_seconds": 60,
+                        "delay": 120,
+                        "score": 1.5,
+                        "completion_pct": 1.0,
+                        "rate_bytes_per_second": 2048.0,
+                        "ttfb_seconds": null,
+                        "http_version": "HTTP/2.0",
+                    }],
+                },
+            })
+        );
+    }
+}
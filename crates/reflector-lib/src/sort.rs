@@ -0,0 +1,111 @@
+//! This is where the orders of reflector that [`SortKey`] does not cover go. Sorting by rate
+//! is done by [`Ratings::sort_by_rate`] and sorting by quality by
+//! [`quality::sort_by_quality`].
+//!
+//! [`SortKey`]: arch_mirrors_rs::selector::SortKey
+//! [`Ratings::sort_by_rate`]: crate::Ratings::sort_by_rate
+//! [`quality::sort_by_quality`]: crate::quality::sort_by_quality
+use arch_mirrors_rs::Mirror;
+use arch_mirrors_rs::mirror::ScoreWeights;
+use reqwest::Url;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+use std::time::Duration;
+
+/// Sort the mirrors by their score recomputed with `weights`, lowest and thus best first.
+/// Mirrors without a score go last.
+pub fn sort_by_weighted_score(mirrors: &mut [Mirror], weights: &ScoreWeights) {
+    mirrors.sort_by(
+        |a, b| match (a.weighted_score(weights), b.weighted_score(weights)) {
+            (Some(score_a), Some(score_b)) => {
+                score_a.partial_cmp(&score_b).unwrap_or(Ordering::Equal)
+            }
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        },
+    );
+}
+
+/// Sort the mirrors by their measured `latencies`, lowest first. Mirrors whose latency could
+/// not be measured go last.
+pub fn sort_by_latency<S: BuildHasher>(
+    mirrors: &mut [Mirror],
+    latencies: &HashMap<Url, Duration, S>,
+) {
+    mirrors.sort_by_key(|mirror| latencies.get(&mirror.url).copied().unwrap_or(Duration::MAX));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arch_mirrors_rs::Error;
+
+    /// Mirrors as listed by the Arch Linux mirror status: a fast but unstable one, a
+    /// stable one with a long delay and one that never completed a check.
+    fn archweb_mirrors() -> Vec<Mirror> {
+        let mirror = |host: &str, delay: &str, avg: &str, stddev: &str, pct: &str| {
+            let json = format!(
+                r#"{{"url": "https://{host}/archlinux/", "protocol": "https",
+                "last_sync": "2024-05-01T09:30:00Z", "completion_pct": {pct},
+                "delay": {delay}, "duration_avg": {avg}, "duration_stddev": {stddev},
+                "score": null, "active": true, "country": "Sweden", "country_code": "SE",
+                "isos": true, "ipv4": true, "ipv6": false, "details": ""}}"#
+            );
+            serde_json::from_str::<Mirror>(&json).unwrap()
+        };
+        vec![
+            mirror("unstable.example.se", "600", "0.3", "0.5", "1.0"),
+            mirror("broken.example.se", "null", "null", "null", "0.0"),
+            mirror("delayed.example.se", "3600", "0.5", "0.1", "1.0"),
+        ]
+    }
+
+    fn sorted_hosts(weights: &str) -> Vec<String> {
+        let mut mirrors = archweb_mirrors();
+        sort_by_weighted_score(&mut mirrors, &weights.parse().unwrap());
+        mirrors
+            .iter()
+            .map(|mirror| mirror.url.host_str().unwrap().to_owned())
+            .collect()
+    }
+
+    #[test]
+    fn weights_change_the_order() {
+        assert_eq!(
+            sorted_hosts("delay=1"),
+            [
+                "unstable.example.se",
+                "delayed.example.se",
+                "broken.example.se"
+            ]
+        );
+        assert_eq!(
+            sorted_hosts("stddev=4"),
+            [
+                "delayed.example.se",
+                "unstable.example.se",
+                "broken.example.se"
+            ]
+        );
+    }
+
+    #[test]
+    fn reject_invalid_weights() {
+        for (weights, term) in [
+            ("latency=1", "latency=1"),
+            ("stddev=4,delay=fast", "delay=fast"),
+            ("delay=-1", "delay=-1"),
+            ("stddev", "stddev"),
+        ] {
+            assert!(
+                matches!(
+                    weights.parse::<ScoreWeights>(),
+                    Err(Error::InvalidScoreWeight(invalid)) if invalid == term
+                ),
+                "{weights}"
+            );
+        }
+    }
+}
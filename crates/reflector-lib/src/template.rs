@@ -0,0 +1,225 @@
+//! This is where the custom output [`Template`]s go.
+use crate::rate::Ratings;
+use arch_mirrors_rs::{Mirror, Status};
+use std::fmt::{self, Display, Formatter};
+use std::io::{self, Write};
+use std::str::FromStr;
+
+/// A template that is rendered once for every selected mirror, e.g.
+/// `Server = {url}$repo/os/$arch`. Placeholders are enclosed in braces, and literal braces
+/// are written as "{{" and "}}".
+///
+/// ```
+/// use reflector_lib::template::Template;
+///
+/// let template = "{rank}. {host} ({country_code})".parse::<Template>().unwrap();
+/// assert!("{nope}".parse::<Template>().is_err());
+/// # let _ = template;
+/// ```
+#[derive(Debug, Clone)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone)]
+enum Part {
+    Literal(String),
+    Field(Field),
+}
+
+/// The placeholders that can be used in a template.
+#[derive(Debug, Clone, Copy)]
+enum Field {
+    Url,
+    Host,
+    Protocol,
+    Country,
+    CountryCode,
+    LastSync,
+    Delay,
+    Score,
+    Rate,
+    Rank,
+}
+
+impl Field {
+    const ALL: &[(&str, Self)] = &[
+        ("url", Self::Url),
+        ("host", Self::Host),
+        ("protocol", Self::Protocol),
+        ("country", Self::Country),
+        ("country_code", Self::CountryCode),
+        ("last_sync", Self::LastSync),
+        ("delay", Self::Delay),
+        ("score", Self::Score),
+        ("rate", Self::Rate),
+        ("rank", Self::Rank),
+    ];
+}
+
+/// The possible errors when parsing a [`Template`].
+#[derive(Debug)]
+pub enum Error {
+    /// A placeholder that is not known.
+    UnknownField(String),
+    /// A "{" without a matching "}".
+    Unclosed,
+    /// A "}" that does not close a placeholder and is not escaped as "}}".
+    Unopened,
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownField(name) => {
+                let known = Field::ALL
+                    .iter()
+                    .map(|(name, _)| *name)
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                write!(
+                    f,
+                    "unknown placeholder '{{{name}}}', expected one of {known}"
+                )
+            }
+            Self::Unclosed => write!(f, "unclosed '{{', use '{{{{' for a literal brace"),
+            Self::Unopened => write!(f, "unmatched '}}', use '}}}}' for a literal brace"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl FromStr for Template {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = s.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => return Err(Error::Unclosed),
+                        }
+                    }
+                    let field = Field::ALL
+                        .iter()
+                        .find(|(known, _)| *known == name.trim())
+                        .map(|(_, field)| *field)
+                        .ok_or(Error::UnknownField(name))?;
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(Part::Field(field));
+                }
+                '}' => return Err(Error::Unopened),
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+        Ok(Self { parts })
+    }
+}
+
+impl Template {
+    /// Render the template for every mirror of `status`, one per line. Placeholders for
+    /// values that are not available render as an empty string.
+    ///
+    /// # Errors
+    /// Fails if `out` cannot be written to.
+    pub fn render(
+        &self,
+        status: &Status,
+        ratings: &Ratings,
+        mut out: impl Write,
+    ) -> io::Result<()> {
+        for (rank, mirror) in status.urls.iter().enumerate() {
+            for part in &self.parts {
+                match part {
+                    Part::Literal(literal) => write!(out, "{literal}")?,
+                    Part::Field(field) => write_field(&mut out, *field, rank + 1, mirror, ratings)?,
+                }
+            }
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+}
+
+fn write_field(
+    out: &mut impl Write,
+    field: Field,
+    rank: usize,
+    mirror: &Mirror,
+    ratings: &Ratings,
+) -> io::Result<()> {
+    match field {
+        Field::Url => write!(out, "{}", mirror.url),
+        Field::Host => write!(out, "{}", mirror.url.host_str().unwrap_or_default()),
+        Field::Protocol => write!(out, "{}", mirror.protocol),
+        Field::Country => write!(out, "{}", mirror.country),
+        Field::CountryCode => write!(out, "{}", mirror.country_code),
+        Field::LastSync => write_optional(out, mirror.last_sync),
+        Field::Delay => write_optional(out, mirror.delay),
+        Field::Score => write_optional(out, mirror.score),
+        Field::Rate => write_optional(
+            out,
+            ratings
+                .get(&mirror.url)
+                .map(|rating| format!("{:.2}", rating.rate / 1024.0)),
+        ),
+        Field::Rank => write!(out, "{rank}"),
+    }
+}
+
+fn write_optional(out: &mut impl Write, value: Option<impl Display>) -> io::Result<()> {
+    match value {
+        Some(value) => write!(out, "{value}"),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_escapes_and_placeholders() {
+        let template = "{{{url}}} {rank}".parse::<Template>().unwrap();
+        assert!(matches!(
+            template.parts.as_slice(),
+            [
+                Part::Literal(open),
+                Part::Field(Field::Url),
+                Part::Literal(close),
+                Part::Field(Field::Rank),
+            ] if open == "{" && close == "} "
+        ));
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(matches!(
+            "{nope}".parse::<Template>(),
+            Err(Error::UnknownField(name)) if name == "nope"
+        ));
+        assert!(matches!("{url".parse::<Template>(), Err(Error::Unclosed)));
+        assert!(matches!("url}".parse::<Template>(), Err(Error::Unopened)));
+    }
+}